@@ -5,7 +5,7 @@ use libcovau::{anyhow, dirs};
 use serde::{Deserialize, Serialize};
 
 use libcovau::config::Config;
-pub use libcovau::config::FeCommand;
+pub use libcovau::config::{AuthCommand, DbCommand, FeCommand};
 
 #[derive(Subcommand, Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub enum Command {
@@ -14,6 +14,14 @@ pub enum Command {
         command: FeCommand,
     },
     Server,
+    Db {
+        #[command(subcommand)]
+        command: DbCommand,
+    },
+    Auth {
+        #[command(subcommand)]
+        command: AuthCommand,
+    },
     #[cfg(feature = "tao-wry")]
     TaoWry {
         #[arg(long, short, default_value_t = false)]
@@ -242,6 +242,12 @@ async fn main() -> Result<()> {
         cli::Command::FeCommand { command } => {
             libcovau::run_command(config.server_port, cli.debug, command).await?;
         }
+        cli::Command::Db { command } => {
+            libcovau::run_db_command(config, command).await?;
+        }
+        cli::Command::Auth { command } => {
+            libcovau::run_auth_command(config.server_port, command).await?;
+        }
         cli::Command::Test => {
             // dbg!(ulid::Ulid::new().to_string());
 
@@ -0,0 +1,700 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use sea_orm::{ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter, QueryOrder, QuerySelect};
+use serde::{Deserialize, Serialize};
+
+use crate::covau_types::{serde_with_string, InfoSource, Queue, Song, SourcePath};
+use crate::db::{object, play, trash, DbId, Typ, BATCH_SIZE};
+use crate::musimanager as mm;
+
+type MmSong = mm::Song<Option<mm::SongInfo>, SourcePath>;
+
+// how many items the top songs / artists / queues lists have
+const TOP_ITEMS: usize = 25;
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, specta::Type)]
+pub struct ListeningStats {
+    // plays that started in [after, before)
+    #[serde(with = "serde_with_string")]
+    pub after: u64,
+    #[serde(with = "serde_with_string")]
+    pub before: u64,
+    pub plays: u32,
+    pub listened_secs: u32,
+    pub skips: u32,
+    pub top_songs: Vec<SongStat>,
+    pub top_artists: Vec<ArtistStat>,
+    pub top_queues: Vec<QueueStat>,
+    // utc days / weeks (starting on monday) with at least 1 play
+    pub per_day: Vec<TimeBucket>,
+    pub per_week: Vec<TimeBucket>,
+    pub autoplay: AutoplayStats,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, specta::Type)]
+pub struct SongStat {
+    // Song or MmSong
+    pub id: DbId,
+    // None if the song is gone (even from the trash)
+    pub title: Option<String>,
+    pub artists: Vec<String>,
+    pub plays: u32,
+    pub listened_secs: u32,
+    pub skips: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, specta::Type)]
+pub struct ArtistStat {
+    pub name: String,
+    // every id this artist was seen with (yt channels, mbz artists)
+    pub ids: Vec<InfoSource>,
+    pub plays: u32,
+    pub listened_secs: u32,
+    pub skips: u32,
+    pub skip_rate: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, specta::Type)]
+pub struct QueueStat {
+    pub id: DbId,
+    pub title: Option<String>,
+    pub plays: u32,
+    pub listened_secs: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, specta::Type)]
+pub struct TimeBucket {
+    #[serde(with = "serde_with_string")]
+    pub start_ts: u64,
+    pub plays: u32,
+    pub listened_secs: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, specta::Type)]
+pub struct AutoplayStats {
+    pub plays: u32,
+    // first play of the song ever
+    pub new: u32,
+    pub repeats: u32,
+    // new / plays
+    pub new_ratio: f32,
+}
+
+struct SongInfo {
+    title: String,
+    artists: Vec<(String, Option<InfoSource>)>,
+}
+
+pub async fn collect<C: ConnectionTrait>(
+    conn: &C,
+    after: u64,
+    before: u64,
+) -> anyhow::Result<ListeningStats> {
+    let plays = play::Entity::find()
+        .filter(play::Column::StartedTs.gte(after as i64))
+        .filter(play::Column::StartedTs.lt(before as i64))
+        .order_by_asc(play::Column::StartedTs)
+        .order_by_asc(play::Column::Id)
+        .all(conn)
+        .await?;
+
+    let song_ids = plays.iter().map(|p| p.song_id).collect::<HashSet<_>>();
+    let songs = song_infos(conn, &song_ids).await?;
+    let queue_ids = plays
+        .iter()
+        .filter_map(|p| p.queue_id)
+        .collect::<HashSet<_>>();
+    let queues = queue_titles(conn, &queue_ids).await?;
+
+    // in id order. the first artist of a group names it
+    let mut ordered = songs.iter().collect::<Vec<_>>();
+    ordered.sort_by_key(|(&id, _)| id);
+    let mut artists = ArtistGroups::default();
+    let song_artists = ordered
+        .into_iter()
+        .map(|(&id, s)| {
+            let nodes = s
+                .artists
+                .iter()
+                .map(|(name, source)| artists.add(name, source.as_ref()))
+                .collect::<Vec<_>>();
+            (id, nodes)
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut stats = ListeningStats {
+        after,
+        before,
+        ..Default::default()
+    };
+    let mut by_song: HashMap<DbId, SongStat> = HashMap::new();
+    let mut by_artist: HashMap<usize, ArtistStat> = HashMap::new();
+    let mut by_queue: HashMap<DbId, QueueStat> = HashMap::new();
+    let mut per_day: BTreeMap<u64, TimeBucket> = BTreeMap::new();
+    let mut per_week: BTreeMap<u64, TimeBucket> = BTreeMap::new();
+    for p in plays.iter() {
+        let secs = p.listened_secs as u32;
+        let skips = p.skipped as u32;
+        stats.plays += 1;
+        stats.listened_secs += secs;
+        stats.skips += skips;
+
+        let song = songs.get(&p.song_id);
+        let s = by_song.entry(p.song_id).or_insert_with(|| SongStat {
+            id: p.song_id,
+            title: song.map(|s| s.title.clone()),
+            artists: song
+                .map(|s| s.artists.iter().map(|(name, _)| name.clone()).collect())
+                .unwrap_or_default(),
+            plays: 0,
+            listened_secs: 0,
+            skips: 0,
+        });
+        s.plays += 1;
+        s.listened_secs += secs;
+        s.skips += skips;
+
+        // once per play. even if the song lists the same artist twice
+        let groups = song_artists
+            .get(&p.song_id)
+            .map(|nodes| {
+                nodes
+                    .iter()
+                    .map(|&n| artists.find(n))
+                    .collect::<HashSet<_>>()
+            })
+            .unwrap_or_default();
+        for g in groups {
+            let a = by_artist.entry(g).or_insert_with(|| ArtistStat {
+                name: artists.names[g].clone(),
+                ids: vec![],
+                plays: 0,
+                listened_secs: 0,
+                skips: 0,
+                skip_rate: 0.0,
+            });
+            a.plays += 1;
+            a.listened_secs += secs;
+            a.skips += skips;
+        }
+
+        if let Some(q) = p.queue_id {
+            let s = by_queue.entry(q).or_insert_with(|| QueueStat {
+                id: q,
+                title: queues.get(&q).cloned(),
+                plays: 0,
+                listened_secs: 0,
+            });
+            s.plays += 1;
+            s.listened_secs += secs;
+        }
+
+        let ts = p.started_ts as u64;
+        for (buckets, start) in [
+            (&mut per_day, day_start(ts)),
+            (&mut per_week, week_start(ts)),
+        ] {
+            let b = buckets.entry(start).or_insert(TimeBucket {
+                start_ts: start,
+                plays: 0,
+                listened_secs: 0,
+            });
+            b.plays += 1;
+            b.listened_secs += secs;
+        }
+    }
+
+    for (&g, a) in by_artist.iter_mut() {
+        a.ids = artists.ids_of(g);
+        a.skip_rate = a.skips as f32 / a.plays as f32;
+    }
+
+    stats.top_songs = top(
+        by_song.into_values(),
+        |s| (s.plays, s.listened_secs),
+        |s| s.id,
+    );
+    stats.top_artists = top(
+        by_artist.into_values(),
+        |a| (a.plays, a.listened_secs),
+        |a| a.name.clone(),
+    );
+    stats.top_queues = top(
+        by_queue.into_values(),
+        |q| (q.plays, q.listened_secs),
+        |q| q.id,
+    );
+    stats.per_day = per_day.into_values().collect();
+    stats.per_week = per_week.into_values().collect();
+    stats.autoplay = autoplay_stats(conn, &plays).await?;
+
+    Ok(stats)
+}
+
+// most played first. ties are broken using key
+fn top<T, K: Ord>(
+    items: impl Iterator<Item = T>,
+    score: impl Fn(&T) -> (u32, u32),
+    key: impl Fn(&T) -> K,
+) -> Vec<T> {
+    let mut items = items.collect::<Vec<_>>();
+    items.sort_by(|a, b| score(b).cmp(&score(a)).then_with(|| key(a).cmp(&key(b))));
+    items.truncate(TOP_ITEMS);
+    items
+}
+
+async fn autoplay_stats<C: ConnectionTrait>(
+    conn: &C,
+    plays: &[play::Model],
+) -> anyhow::Result<AutoplayStats> {
+    let autoplayed = plays.iter().filter(|p| p.autoplay).collect::<Vec<_>>();
+    let ids = autoplayed
+        .iter()
+        .map(|p| p.song_id)
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+
+    // song -> started_ts of its first play. plays before the time window count too
+    let mut first = HashMap::new();
+    for chunk in ids.chunks(BATCH_SIZE) {
+        let rows = play::Entity::find()
+            .select_only()
+            .column(play::Column::SongId)
+            .column_as(play::Column::StartedTs.min(), "first")
+            .filter(play::Column::SongId.is_in(chunk.iter().copied()))
+            .group_by(play::Column::SongId)
+            .into_tuple::<(DbId, i64)>()
+            .all(conn)
+            .await?;
+        first.extend(rows);
+    }
+
+    let mut stats = AutoplayStats::default();
+    let mut seen = HashSet::new();
+    for p in autoplayed {
+        stats.plays += 1;
+        if first.get(&p.song_id) == Some(&p.started_ts) && seen.insert(p.song_id) {
+            stats.new += 1;
+        } else {
+            stats.repeats += 1;
+        }
+    }
+    if stats.plays > 0 {
+        stats.new_ratio = stats.new as f32 / stats.plays as f32;
+    }
+    Ok(stats)
+}
+
+// trashed songs still show up in old plays
+async fn song_infos<C: ConnectionTrait>(
+    conn: &C,
+    ids: &HashSet<DbId>,
+) -> anyhow::Result<HashMap<DbId, SongInfo>> {
+    let ids = ids.iter().copied().collect::<Vec<_>>();
+    let mut infos = HashMap::new();
+    for chunk in ids.chunks(BATCH_SIZE) {
+        let live = object::Entity::find()
+            .select_only()
+            .column(object::Column::Id)
+            .column(object::Column::Typ)
+            .column(object::Column::Data)
+            .filter(object::Column::Id.is_in(chunk.iter().copied()))
+            .into_tuple::<(DbId, Typ, String)>()
+            .all(conn)
+            .await?;
+        let trashed = trash::Entity::find()
+            .select_only()
+            .column(trash::Column::Id)
+            .column(trash::Column::Typ)
+            .column(trash::Column::Data)
+            .filter(trash::Column::Id.is_in(chunk.iter().copied()))
+            .into_tuple::<(DbId, Typ, String)>()
+            .all(conn)
+            .await?;
+        for (id, typ, data) in live.into_iter().chain(trashed) {
+            if let Some(info) = song_info(&typ, &data) {
+                infos.insert(id, info);
+            }
+        }
+    }
+    Ok(infos)
+}
+
+fn song_info(typ: &Typ, data: &str) -> Option<SongInfo> {
+    match typ {
+        Typ::Song => {
+            let s = serde_json::from_str::<Song>(data).ok()?;
+            Some(SongInfo {
+                title: s.title,
+                artists: s.artists.into_iter().map(|a| (a.name, a.source)).collect(),
+            })
+        }
+        Typ::MmSong => {
+            let s = serde_json::from_str::<MmSong>(data).ok()?;
+            let info = s.info.as_ref();
+            // the channel is the first artist's
+            let channel = info
+                .filter(|i| !i.channel_id.is_empty())
+                .map(|i| InfoSource::YtId(i.channel_id.clone()));
+            let names = match s.artist_name {
+                Some(name) => vec![name],
+                None => info.map(|i| i.artist_names.clone()).unwrap_or_default(),
+            };
+            let artists = names
+                .into_iter()
+                .enumerate()
+                .map(|(i, name)| (name, if i == 0 { channel.clone() } else { None }))
+                .collect();
+            Some(SongInfo {
+                title: s.title,
+                artists,
+            })
+        }
+        _ => None,
+    }
+}
+
+async fn queue_titles<C: ConnectionTrait>(
+    conn: &C,
+    ids: &HashSet<DbId>,
+) -> anyhow::Result<HashMap<DbId, String>> {
+    let ids = ids.iter().copied().collect::<Vec<_>>();
+    let mut titles = HashMap::new();
+    for chunk in ids.chunks(BATCH_SIZE) {
+        let models = object::Entity::find()
+            .filter(object::Column::Typ.eq(Typ::Queue))
+            .filter(object::Column::Id.is_in(chunk.iter().copied()))
+            .all(conn)
+            .await?;
+        for m in models {
+            if let Some(q) = m.parsed_or_skip::<Queue>() {
+                titles.insert(m.id, q.queue.queue.title);
+            }
+        }
+    }
+    Ok(titles)
+}
+
+/// artists that share a normalized name or an id are the same artist.
+/// so an artist seen through yt and mbz counts once
+#[derive(Default)]
+struct ArtistGroups {
+    parent: Vec<usize>,
+    names: Vec<String>,
+    ids: Vec<Option<InfoSource>>,
+    keys: HashMap<String, usize>,
+}
+impl ArtistGroups {
+    fn add(&mut self, name: &str, id: Option<&InfoSource>) -> usize {
+        let i = self.parent.len();
+        self.parent.push(i);
+        self.names.push(name.to_string());
+        self.ids.push(id.cloned());
+
+        let name = crate::dedup::artist_key(name);
+        let keys = [
+            (!name.is_empty()).then(|| format!("name:{}", name)),
+            id.map(|id| match id {
+                InfoSource::YtId(id) => format!("yt:{}", id),
+                InfoSource::MbzId(id) => format!("mbz:{}", id),
+            }),
+        ];
+        for key in keys.into_iter().flatten() {
+            let j = *self.keys.entry(key).or_insert(i);
+            self.union(i, j);
+        }
+        i
+    }
+
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parent[i] != i {
+            self.parent[i] = self.parent[self.parent[i]];
+            i = self.parent[i];
+        }
+        i
+    }
+
+    // the first artist seen stays the root (and names the group)
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            self.parent[a.max(b)] = a.min(b);
+        }
+    }
+
+    fn ids_of(&mut self, group: usize) -> Vec<InfoSource> {
+        let mut ids = vec![];
+        for i in 0..self.parent.len() {
+            if self.find(i) != group {
+                continue;
+            }
+            if let Some(id) = self.ids[i].as_ref() {
+                if !ids.contains(id) {
+                    ids.push(id.clone());
+                }
+            }
+        }
+        ids
+    }
+}
+
+fn day_start(ts: u64) -> u64 {
+    ts / DAY_SECS * DAY_SECS
+}
+
+// 1970-01-01 was a thursday
+fn week_start(ts: u64) -> u64 {
+    let days = ts / DAY_SECS;
+    days.saturating_sub((days + 3) % 7) * DAY_SECS
+}
+
+/// start of the year (utc)
+pub fn year_start(year: i32) -> u64 {
+    days_from_civil(year, 1, 1).max(0) as u64 * DAY_SECS
+}
+
+// - [chrono-Compatible Low-Level Date Algorithms](https://howardhinnant.github.io/date_algorithms.html)
+fn days_from_civil(y: i32, m: u32, d: u32) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y } as i64;
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let m = m as i64;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + d as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+fn civil_from_days(z: i64) -> (i64, u32, u32) {
+    let z = z + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = yoe + era * 400 + (m <= 2) as i64;
+    (y, m, d)
+}
+
+/// 2024-01-31 (utc)
+pub fn date(ts: u64) -> String {
+    let (y, m, d) = civil_from_days((ts / DAY_SECS) as i64);
+    format!("{:04}-{:02}-{:02}", y, m, d)
+}
+
+/// start of the day (utc) of a 2024-01-31 date
+pub fn parse_date(date: &str) -> anyhow::Result<u64> {
+    let parsed = date
+        .split('-')
+        .map(|p| p.parse::<u32>().ok())
+        .collect::<Option<Vec<_>>>();
+    match parsed.as_deref() {
+        Some(&[y, m, d])
+            if (1..=12).contains(&m) && (1..=days_in_month(y, m)).contains(&d) && y >= 1970 =>
+        {
+            Ok(days_from_civil(y as i32, m, d) as u64 * DAY_SECS)
+        }
+        _ => Err(anyhow::anyhow!(
+            "invalid date '{}'. expected YYYY-MM-DD",
+            date
+        )),
+    }
+}
+
+// is_multiple_of needs rust 1.87. the nix build is on an older one
+#[allow(clippy::manual_is_multiple_of)]
+fn days_in_month(y: u32, m: u32) -> u32 {
+    match m {
+        2 if y % 4 == 0 && (y % 100 != 0 || y % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// 3h 05m, 4m 10s
+pub fn human_duration(secs: u32) -> String {
+    let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if h > 0 {
+        format!("{}h {:02}m", h, m)
+    } else {
+        format!("{}m {:02}s", m, s)
+    }
+}
+
+struct Section {
+    title: &'static str,
+    headers: &'static [&'static str],
+    rows: Vec<Vec<String>>,
+}
+
+fn summary(stats: &ListeningStats) -> Vec<String> {
+    let a = &stats.autoplay;
+    vec![
+        format!(
+            "{} to {}",
+            date(stats.after),
+            date(stats.before.saturating_sub(1))
+        ),
+        format!("plays: {} ({} skipped)", stats.plays, stats.skips),
+        format!("listening time: {}", human_duration(stats.listened_secs)),
+        format!(
+            "autoplay: {} plays, {} new ({:.0}%), {} repeats",
+            a.plays,
+            a.new,
+            a.new_ratio * 100.0,
+            a.repeats
+        ),
+    ]
+}
+
+fn sections(stats: &ListeningStats) -> Vec<Section> {
+    let rank = |i: usize| (i + 1).to_string();
+    let buckets = |b: &[TimeBucket]| {
+        b.iter()
+            .map(|b| {
+                vec![
+                    date(b.start_ts),
+                    b.plays.to_string(),
+                    human_duration(b.listened_secs),
+                ]
+            })
+            .collect()
+    };
+    vec![
+        Section {
+            title: "top songs",
+            headers: &["#", "song", "artists", "plays", "time", "skips"],
+            rows: stats
+                .top_songs
+                .iter()
+                .enumerate()
+                .map(|(i, s)| {
+                    vec![
+                        rank(i),
+                        s.title.clone().unwrap_or_else(|| format!("({})", s.id)),
+                        s.artists.join(", "),
+                        s.plays.to_string(),
+                        human_duration(s.listened_secs),
+                        s.skips.to_string(),
+                    ]
+                })
+                .collect(),
+        },
+        Section {
+            title: "top artists",
+            headers: &["#", "artist", "plays", "time", "skip rate"],
+            rows: stats
+                .top_artists
+                .iter()
+                .enumerate()
+                .map(|(i, a)| {
+                    vec![
+                        rank(i),
+                        a.name.clone(),
+                        a.plays.to_string(),
+                        human_duration(a.listened_secs),
+                        format!("{:.0}%", a.skip_rate * 100.0),
+                    ]
+                })
+                .collect(),
+        },
+        Section {
+            title: "top queues",
+            headers: &["#", "queue", "plays", "time"],
+            rows: stats
+                .top_queues
+                .iter()
+                .enumerate()
+                .map(|(i, q)| {
+                    vec![
+                        rank(i),
+                        q.title.clone().unwrap_or_else(|| format!("({})", q.id)),
+                        q.plays.to_string(),
+                        human_duration(q.listened_secs),
+                    ]
+                })
+                .collect(),
+        },
+        Section {
+            title: "per week",
+            headers: &["week of", "plays", "time"],
+            rows: buckets(&stats.per_week),
+        },
+        Section {
+            title: "per day",
+            headers: &["day", "plays", "time"],
+            rows: buckets(&stats.per_day),
+        },
+    ]
+}
+
+pub fn markdown(stats: &ListeningStats) -> String {
+    let cell = |s: &str| s.replace('|', "\\|");
+
+    let mut out = String::from("# listening report\n\n");
+    for line in summary(stats) {
+        out += &format!("- {}\n", line);
+    }
+    for s in sections(stats) {
+        out += &format!("\n## {}\n\n", s.title);
+        if s.rows.is_empty() {
+            out += "nothing here\n";
+            continue;
+        }
+        out += &format!("| {} |\n", s.headers.join(" | "));
+        out += &format!("|{}\n", " --- |".repeat(s.headers.len()));
+        for row in s.rows {
+            let row = row.iter().map(|c| cell(c)).collect::<Vec<_>>();
+            out += &format!("| {} |\n", row.join(" | "));
+        }
+    }
+    out
+}
+
+/// a single file. no external css or scripts
+pub fn html(stats: &ListeningStats) -> String {
+    let esc = |s: &str| {
+        s.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+    };
+
+    let mut out = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>listening report</title>\n<style>\n\
+         body { font-family: sans-serif; max-width: 60em; margin: 2em auto; background: #181818; color: #ddd; }\n\
+         table { border-collapse: collapse; width: 100%; }\n\
+         th, td { text-align: left; padding: 0.3em 0.6em; border-bottom: 1px solid #333; }\n\
+         </style>\n</head>\n<body>\n<h1>listening report</h1>\n<ul>\n",
+    );
+    for line in summary(stats) {
+        out += &format!("<li>{}</li>\n", esc(&line));
+    }
+    out += "</ul>\n";
+    for s in sections(stats) {
+        out += &format!("<h2>{}</h2>\n", s.title);
+        if s.rows.is_empty() {
+            out += "<p>nothing here</p>\n";
+            continue;
+        }
+        out += "<table>\n<tr>";
+        for h in s.headers {
+            out += &format!("<th>{}</th>", h);
+        }
+        out += "</tr>\n";
+        for row in s.rows {
+            out += "<tr>";
+            for c in row {
+                out += &format!("<td>{}</td>", esc(&c));
+            }
+            out += "</tr>\n";
+        }
+        out += "</table>\n";
+    }
+    out += "</body>\n</html>\n";
+    out
+}
@@ -0,0 +1,307 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+use sea_orm::{EntityTrait, QueryOrder, TransactionTrait};
+use serde::{Deserialize, Serialize};
+
+use crate::db::{link, object, refid, Db, DbAble, DbId, DbItem, DbMetadata, Typ};
+use crate::migrations::{self, Step};
+
+// archive is json lines. first line is an [ArchiveHeader], then 1 [ArchiveItem] per line
+pub const ARCHIVE_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ArchiveHeader {
+    pub archive_version: u32,
+    pub schema_version: u32,
+    pub exported_ts: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ArchiveItem {
+    pub id: DbId,
+    pub typ: Typ,
+    pub metadata: DbMetadata,
+
+    // refids and links are derived from data again on import.
+    // these are only here so that the archive is usable without covau
+    pub refids: Vec<String>,
+    pub links: Vec<ArchiveLink>,
+
+    pub data: serde_json::Value,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ArchiveLink {
+    pub from_refid: String,
+    pub to_refid: String,
+    pub to_typ: Typ,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ExportReport {
+    pub exported: u32,
+    // objects that do not parse. logged and left out
+    pub skipped: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ImportReport {
+    pub inserted: u32,
+    // already in the db. matched using refids, or by id and data for items without refids
+    pub existing: u32,
+}
+
+/// writes every object in the db
+pub async fn export(db: &Db, mut w: impl Write + Send) -> anyhow::Result<ExportReport> {
+    ensure_migrated(db).await?;
+
+    // everything from 1 snapshot of the db
+    let txn = db.db.begin().await?;
+
+    let mut refids: HashMap<DbId, Vec<String>> = HashMap::new();
+    for r in refid::Entity::find().all(&txn).await? {
+        refids.entry(r.object_id).or_default().push(r.refid);
+    }
+    let mut links: HashMap<(String, Typ), Vec<ArchiveLink>> = HashMap::new();
+    for l in link::Entity::find().all(&txn).await? {
+        links
+            .entry((l.from_refid.clone(), l.from_typ))
+            .or_default()
+            .push(ArchiveLink {
+                from_refid: l.from_refid,
+                to_refid: l.to_refid,
+                to_typ: l.to_typ,
+            });
+    }
+    let objects = object::Entity::find()
+        .order_by_asc(object::Column::Id)
+        .all(&txn)
+        .await?;
+    txn.commit().await?;
+
+    let header = ArchiveHeader {
+        archive_version: ARCHIVE_VERSION,
+        schema_version: migrations::latest_version(),
+        exported_ts: Db::timestamp(),
+    };
+    serde_json::to_writer(&mut w, &header)?;
+    writeln!(w)?;
+
+    let mut report = ExportReport::default();
+    for m in objects {
+        // a damaged db should still be backed up as far as it can be
+        let Some(data) = m.parsed_or_skip::<serde_json::Value>() else {
+            report.skipped += 1;
+            continue;
+        };
+        let mut item_refids = refids.remove(&m.id).unwrap_or_default();
+        item_refids.sort();
+        let item_links = item_refids
+            .iter()
+            .filter_map(|r| links.remove(&(r.clone(), m.typ.clone())))
+            .flatten()
+            .collect();
+        let item = ArchiveItem {
+            id: m.id,
+            typ: m.typ.clone(),
+            metadata: m.db_metadata(),
+            refids: item_refids,
+            links: item_links,
+            data,
+        };
+        serde_json::to_writer(&mut w, &item)?;
+        writeln!(w)?;
+        report.exported += 1;
+    }
+    w.flush()?;
+
+    Ok(report)
+}
+
+/// imports everything in a single transaction.
+/// items that are already in the db (same refid) are not touched, but references to them still get remapped.
+/// items without refids (playlists, queues, ..) only count as already there if the item with their
+/// archive id has the same data. so importing an archive into the db it came from adds nothing,
+/// but a playlist changed since the export is imported as a new one
+pub async fn import(db: &Db, r: impl BufRead) -> anyhow::Result<ImportReport> {
+    ensure_migrated(db).await?;
+
+    let mut lines = r.lines();
+    let header = lines.next().ok_or(anyhow::anyhow!("archive is empty"))??;
+    let header: ArchiveHeader = serde_json::from_str(&header)?;
+    if header.archive_version != ARCHIVE_VERSION {
+        return Err(anyhow::anyhow!(
+            "unsupported archive version {}",
+            header.archive_version
+        ));
+    }
+    if header.schema_version > migrations::latest_version() {
+        return Err(anyhow::anyhow!(
+            "archive schema version {} is newer than what this covau knows ({})",
+            header.schema_version,
+            migrations::latest_version()
+        ));
+    }
+
+    let mut items = vec![];
+    for (i, line) in lines.enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut item: ArchiveItem = serde_json::from_str(&line)
+            .map_err(|e| anyhow::anyhow!("archive line {}: {}", i + 2, e))?;
+
+        // archives from older versions get the same json rewrites that the db would have gotten
+        for m in migrations::MIGRATIONS
+            .iter()
+            .filter(|m| m.version > header.schema_version)
+        {
+            for step in m.steps {
+                if let Step::Json { typ, rewrite } = step {
+                    if *typ == item.typ {
+                        item.data = rewrite(item.data)?;
+                    }
+                }
+            }
+        }
+        items.push(item);
+    }
+
+    // items can only refer to items that are imported before them
+    items.sort_by_key(|item| match item.typ {
+        Typ::Playlist => 1,
+        Typ::SmartPlaylist => 2,
+        Typ::Queue => 3,
+        Typ::LocalState => 4,
+        _ => 0,
+    });
+
+    let report = db
+        .in_transaction(|txn| Box::pin(import_items(txn, items)))
+        .await?;
+    db.send_event(crate::db::DbEvent::Resync);
+
+    Ok(report)
+}
+
+async fn import_items<C: sea_orm::ConnectionTrait>(
+    conn: &C,
+    items: Vec<ArchiveItem>,
+) -> anyhow::Result<ImportReport> {
+    let mut ids = HashMap::new();
+    let mut report = ImportReport::default();
+    for item in items {
+        use crate::{covau_types as covau, mbz, musimanager as mm, yt::song_tube as st};
+        type MmSong = mm::Song<Option<mm::SongInfo>, covau::SourcePath>;
+
+        let old_id = item.id;
+        let report = &mut report;
+        let id = match item.typ {
+            Typ::MmSong => import_item::<MmSong, _>(conn, item, &ids, report).await?,
+            Typ::MmAlbum => {
+                import_item::<mm::Album<crate::yt::VideoId>, _>(conn, item, &ids, report).await?
+            }
+            Typ::MmArtist => {
+                import_item::<mm::Artist<crate::yt::VideoId, crate::yt::AlbumId>, _>(
+                    conn, item, &ids, report,
+                )
+                .await?
+            }
+            Typ::MmPlaylist => {
+                import_item::<mm::Playlist<crate::yt::VideoId>, _>(conn, item, &ids, report).await?
+            }
+            Typ::MmQueue => {
+                import_item::<mm::Queue<crate::yt::VideoId>, _>(conn, item, &ids, report).await?
+            }
+            Typ::LocalState => import_local_state(conn, item, &ids).await?,
+            Typ::Song => import_item::<covau::Song, _>(conn, item, &ids, report).await?,
+            Typ::Playlist => import_item::<covau::Playlist, _>(conn, item, &ids, report).await?,
+            Typ::Queue => import_item::<covau::Queue, _>(conn, item, &ids, report).await?,
+            Typ::ArtistBlacklist => {
+                import_item::<covau::ArtistBlacklist, _>(conn, item, &ids, report).await?
+            }
+            Typ::SongBlacklist => {
+                import_item::<covau::SongBlacklist, _>(conn, item, &ids, report).await?
+            }
+            Typ::Updater => import_item::<covau::Updater, _>(conn, item, &ids, report).await?,
+            Typ::SmartPlaylist => {
+                import_item::<covau::SmartPlaylist, _>(conn, item, &ids, report).await?
+            }
+            Typ::StSong => import_item::<st::Song, _>(conn, item, &ids, report).await?,
+            Typ::StAlbum => import_item::<st::Album, _>(conn, item, &ids, report).await?,
+            Typ::StPlaylist => import_item::<st::Playlist, _>(conn, item, &ids, report).await?,
+            Typ::StArtist => import_item::<st::Artist, _>(conn, item, &ids, report).await?,
+            Typ::MbzRecording => {
+                import_item::<mbz::RecordingWithInfo, _>(conn, item, &ids, report).await?
+            }
+            Typ::MbzArtist => import_item::<mbz::Artist, _>(conn, item, &ids, report).await?,
+        };
+        ids.insert(old_id, id);
+    }
+    Ok(report)
+}
+
+async fn import_item<T: DbAble, C: sea_orm::ConnectionTrait>(
+    conn: &C,
+    item: ArchiveItem,
+    ids: &HashMap<DbId, DbId>,
+    report: &mut ImportReport,
+) -> anyhow::Result<DbId> {
+    let mut t: T = serde_json::from_value(item.data)
+        .map_err(|e| anyhow::anyhow!("archive item {} ({:?}): {}", item.id, item.typ, e))?;
+
+    // the same item if the archive came from this db. compared before remapping, as references
+    // to items that were not exported (eg: in the trash) are dropped by remap_ids
+    if t.refids().into_iter().next().is_none() {
+        if let Some(old) = T::get(conn, item.id).await? {
+            if serde_json::to_value(&old.t)? == serde_json::to_value(&t)? {
+                report.existing += 1;
+                return Ok(old.id);
+            }
+        }
+    }
+
+    t.remap_ids(ids);
+
+    // get_by_refid matches anything if there are no refids
+    if t.refids().into_iter().next().is_some() {
+        if let Some(old) = t.get_by_refid(conn).await? {
+            report.existing += 1;
+            return Ok(old.id);
+        }
+    }
+
+    let id = t.insert_with_mdata(conn, &item.metadata).await?;
+    report.inserted += 1;
+    Ok(id)
+}
+
+/// there is only 1 LocalState (see [Db::init_state]). so this just points it to the imported queue
+async fn import_local_state<C: sea_orm::ConnectionTrait>(
+    conn: &C,
+    item: ArchiveItem,
+    ids: &HashMap<DbId, DbId>,
+) -> anyhow::Result<DbId> {
+    let mut t: crate::covau_types::LocalState = serde_json::from_value(item.data)?;
+    t.remap_ids(ids);
+
+    let state = crate::covau_types::LocalState::get(conn, 1)
+        .await?
+        .ok_or(anyhow::anyhow!("LocalState not found"))?;
+    if t.queue.is_some() {
+        let state = DbItem { t, ..state };
+        state.update(conn).await?;
+    }
+    Ok(1)
+}
+
+async fn ensure_migrated(db: &Db) -> anyhow::Result<()> {
+    if !migrations::pending(db).await?.is_empty() {
+        return Err(anyhow::anyhow!(
+            "database has pending migrations. run 'covau db migrate' first"
+        ));
+    }
+    Ok(())
+}
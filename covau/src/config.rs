@@ -33,6 +33,58 @@ pub struct Config {
 
     pub run_in_background: bool,
     pub server_port: Option<u16>,
+
+    /// roll back db transactions that are not used for this long
+    /// 30 seconds by default
+    pub db_transaction_timeout_secs: Option<u64>,
+
+    /// how long a writer waits for another transaction to finish
+    /// 30 seconds by default
+    pub db_transaction_wait_secs: Option<u64>,
+
+    /// deleted items are purged from the trash after this many days
+    /// 30 days by default
+    pub trash_retention_days: Option<u64>,
+
+    /// old versions of items are kept for this many days
+    /// 30 days by default
+    pub history_retention_days: Option<u64>,
+
+    /// max old versions kept per item
+    /// 100 by default
+    pub history_max_revisions: Option<u32>,
+
+    /// search score added per like
+    /// 20 by default
+    pub search_like_boost: Option<u32>,
+
+    /// search score added every time the interactions of an item double
+    /// 10 by default
+    pub search_interaction_boost: Option<u32>,
+
+    /// give up on requests sent to the frontend if it does not respond for this long
+    /// 60 seconds by default
+    pub frontend_timeout_secs: Option<u64>,
+
+    /// address the server listens on. 0.0.0.0 lets devices on the lan pair with it
+    /// 127.0.0.1 by default
+    pub bind_address: Option<String>,
+
+    /// web pages other than the server's own ui that can use the server.
+    /// eg: ["http://localhost:5173"]
+    pub allowed_origins: Option<Vec<String>>,
+
+    /// hosts (and their subdomains) that the ui can fetch through the server.
+    /// youtube, musicbrainz, cover art archive and listenbrainz by default
+    pub proxy_allowed_hosts: Option<Vec<String>>,
+
+    /// let the ui fetch from localhost and private network addresses through the server
+    /// false by default
+    pub proxy_allow_private_addresses: Option<bool>,
+
+    /// responses fetched for the ui can't be larger than this
+    /// 20 MB by default
+    pub proxy_max_response_mb: Option<u64>,
 }
 impl Config {
     #[cfg(not(target_os = "android"))]
@@ -302,3 +354,73 @@ pub enum FeCommand {
         error: bool,
     },
 }
+
+#[derive(Subcommand, Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "type", content = "content")]
+pub enum AuthCommand {
+    /// a code that lets a new device (eg: a phone on the lan) use this server
+    Pair,
+    /// list paired devices
+    Devices,
+    /// stop a paired device from using this server
+    Revoke {
+        #[arg(long, short)]
+        name: String,
+    },
+}
+
+#[derive(Subcommand, Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "type", content = "content")]
+pub enum DbCommand {
+    /// list migrations that will run on next start
+    Check,
+    /// run pending migrations and roll them back
+    DryRun,
+    /// backup the database and run pending migrations
+    Migrate,
+    /// write the whole library to a json lines archive
+    Export {
+        #[arg(long, short)]
+        path: PathBuf,
+    },
+    /// add everything from an archive (creates the database if needed)
+    Import {
+        #[arg(long, short)]
+        path: PathBuf,
+    },
+    /// check objects, refids, links and references between objects
+    Fsck {
+        /// fix what can be fixed. unparseable objects are moved to quarantine
+        #[arg(long, short, default_value_t = false)]
+        repair: bool,
+    },
+    /// object counts, disk usage, most liked items and such
+    Stats {
+        #[arg(long, short, default_value_t = false)]
+        json: bool,
+    },
+    /// listening report (top songs, artists, listening time) from the play history
+    Report {
+        /// the last this many days
+        #[arg(long, short, default_value_t = 365)]
+        days: u32,
+
+        /// a whole (utc) year instead
+        #[arg(long, short, conflicts_with = "days")]
+        year: Option<i32>,
+
+        #[arg(long, short, value_enum, default_value_t = ReportFormat::Markdown)]
+        format: ReportFormat,
+
+        /// stdout if not set
+        #[arg(long, short)]
+        path: Option<PathBuf>,
+    },
+}
+
+#[derive(clap::ValueEnum, Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+    Json,
+}
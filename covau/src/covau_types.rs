@@ -9,7 +9,6 @@ use crate::{
     db::{Db, DbAble, DbId},
     mbz,
     server::routes::{FeRequest, FrontendClient},
-    server::MessageResult,
     yt,
     yt::song_tube::TMusicListItem,
 };
@@ -20,27 +19,27 @@ pub struct LocalState {
     // any more settings :/
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, specta::Type)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, specta::Type)]
 pub enum SourcePathType {
     MusimanagerMusic,
     MusimanagerTemp,
     CovauMusic,
     Absolute,
 }
-#[derive(Serialize, Deserialize, Clone, Debug, specta::Type)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, specta::Type)]
 pub struct SourcePath {
     pub typ: SourcePathType,
     pub path: String,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, specta::Type)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, specta::Type)]
 #[serde(tag = "type", content = "content")]
 pub enum PlaySource {
     File(SourcePath),
     YtId(String),
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, specta::Type)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, specta::Type)]
 #[serde(tag = "type", content = "content")]
 pub enum InfoSource {
     YtId(String),
@@ -63,10 +62,52 @@ pub struct Queue {
     /// SongBlacklist
     pub seen: Option<DbId>,
 
-    /// autoplay seed
+    /// autoplay seed. a Song, or a SmartPlaylist that autoplay picks songs from
     pub seed: Option<DbId>,
 }
 
+// songs are picked by evaluating the rules every time. see [crate::smart]
+#[derive(Serialize, Deserialize, Clone, Debug, specta::Type)]
+pub struct SmartPlaylist {
+    pub title: String,
+    // a song has to pass every rule
+    pub rules: Vec<SmartRule>,
+    // None sorts by id
+    pub sort: Option<SmartSort>,
+    pub desc: bool,
+    pub limit: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, specta::Type)]
+#[serde(tag = "type", content = "content")]
+pub enum SmartRule {
+    // any of these. same id or same (normalized) name
+    ArtistIn(Vec<Artist>),
+    Liked,
+    NotPlayedFor {
+        days: u32,
+    },
+    AddedAfter {
+        #[serde(with = "serde_with_string")]
+        ts: u64,
+    },
+    HasLocalFile,
+    // in any of these Playlists
+    InPlaylist(Vec<DbId>),
+    // every word must be in the title or artist names
+    Text(String),
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, specta::Type)]
+#[serde(tag = "type", content = "content")]
+pub enum SmartSort {
+    Metadata(crate::db::MetadataField),
+    Plays,
+    LastPlayed,
+    Title,
+    Random,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, specta::Type)]
 pub struct ArtistBlacklist {
     pub title: Option<String>,
@@ -174,9 +215,56 @@ pub struct Updater {
     pub last_update_ts: u64,
     pub enabled: bool,
 }
+impl Updater {
+    /// updaters are checked again once this much time has passed since their last update
+    pub const CHECK_INTERVAL_SECS: u64 = 4 * 7 * 24 * 60 * 60;
+
+    pub fn is_due(&self, now: u64) -> bool {
+        self.enabled && now.saturating_sub(self.last_update_ts) >= Self::CHECK_INTERVAL_SECS
+    }
+}
+
+// 1 song played by the ui. see [crate::plays]
+#[derive(Serialize, Deserialize, Clone, Debug, specta::Type)]
+pub struct PlayEvent {
+    // Song or MmSong
+    pub song: DbId,
+    pub queue: Option<DbId>,
+    #[serde(with = "serde_with_string")]
+    pub started_ts: u64,
+    pub listened_secs: u32,
+    // stopped before the song ended
+    pub skipped: bool,
+    pub source: PlaySource,
+    // picked by autoplay (not by the user)
+    pub autoplay: bool,
+}
+#[derive(Serialize, Deserialize, Clone, Debug, specta::Type)]
+pub struct Play {
+    pub id: DbId,
+    pub event: PlayEvent,
+}
+// plays that started in [after, before). newest first
+#[derive(Serialize, Deserialize, Clone, Debug, specta::Type)]
+pub struct PlayQuery {
+    #[serde(with = "serde_with_string")]
+    pub after: u64,
+    #[serde(with = "serde_with_string")]
+    pub before: u64,
+    // only plays of this song
+    pub song: Option<DbId>,
+    pub page_size: u32,
+    // started_ts + id of the last play of the previous page
+    pub cursor: Option<String>,
+}
+#[derive(Serialize, Deserialize, Clone, Debug, specta::Type)]
+pub struct PlayMatches {
+    pub items: Vec<Play>,
+    pub continuation: Option<PlayQuery>,
+}
 
 // https://github.com/serde-rs/json/issues/329#issuecomment-305608405
-mod serde_with_string {
+pub(crate) mod serde_with_string {
     use std::fmt::Display;
     use std::str::FromStr;
 
@@ -213,30 +301,27 @@ impl UpdateManager {
     }
 
     async fn notify_error(&self, message: String) -> anyhow::Result<()> {
-        self.fe
-            .send(MessageResult::OkOne(FeRequest::NotifyError(message)))
-            .await?;
+        self.fe.broadcast(FeRequest::NotifyError(message)).await?;
         Ok(())
     }
 
     async fn notify(&self, message: String) -> anyhow::Result<()> {
-        self.fe
-            .send(MessageResult::OkOne(FeRequest::Notify(message)))
-            .await?;
+        self.fe.broadcast(FeRequest::Notify(message)).await?;
         Ok(())
     }
 
     async fn get_next_updater(&self) -> anyhow::Result<Option<crate::db::DbItem<Updater>>> {
         let mut it = self.db.stream_models::<Updater>().await?;
         let ts = Db::timestamp();
-        let week = 2 * 7 * 24 * 60 * 60;
-        let check_delta = week * 2;
+        let check_delta = Updater::CHECK_INTERVAL_SECS;
 
         let mut item: Option<crate::db::DbItem<Updater>> = None;
         let max_delta = 0;
         while let Some(m) = it.next().await {
             let m = m?;
-            let t: Updater = m.parsed_assume();
+            let Some(t) = m.parsed_or_skip::<Updater>() else {
+                continue;
+            };
             if !t.enabled {
                 continue;
             }
@@ -250,7 +335,7 @@ impl UpdateManager {
             }
 
             item = Some(crate::db::DbItem {
-                metadata: m.parse_assume_metadata(),
+                metadata: m.db_metadata(),
                 t,
                 id: m.id,
                 typ: m.typ,
@@ -403,6 +488,7 @@ pub fn dump_types(config: &specta::ts::ExportConfiguration) -> anyhow::Result<St
     types +=
         "import type { ReleaseGroupWithInfo, ReleaseWithInfo, Recording } from '$types/mbz.ts';\n";
     types += "import type { VideoId, AlbumId } from '$types/yt.ts';\n";
+    types += "import type { MetadataField } from '$types/db.ts';\n";
     types += "\n";
     types += &specta::ts::export::<LocalState>(config)?;
     types += ";\n";
@@ -438,6 +524,32 @@ pub fn dump_types(config: &specta::ts::ExportConfiguration) -> anyhow::Result<St
     types += ";\n";
     types += &specta::ts::export::<Updater>(config)?;
     types += ";\n";
+    types += &specta::ts::export::<SmartPlaylist>(config)?;
+    types += ";\n";
+    types += &specta::ts::export::<SmartRule>(config)?;
+    types += ";\n";
+    types += &specta::ts::export::<SmartSort>(config)?;
+    types += ";\n";
+    types += &specta::ts::export::<PlayEvent>(config)?;
+    types += ";\n";
+    types += &specta::ts::export::<Play>(config)?;
+    types += ";\n";
+    types += &specta::ts::export::<PlayQuery>(config)?;
+    types += ";\n";
+    types += &specta::ts::export::<PlayMatches>(config)?;
+    types += ";\n";
+    types += &specta::ts::export::<crate::analytics::ListeningStats>(config)?;
+    types += ";\n";
+    types += &specta::ts::export::<crate::analytics::SongStat>(config)?;
+    types += ";\n";
+    types += &specta::ts::export::<crate::analytics::ArtistStat>(config)?;
+    types += ";\n";
+    types += &specta::ts::export::<crate::analytics::QueueStat>(config)?;
+    types += ";\n";
+    types += &specta::ts::export::<crate::analytics::TimeBucket>(config)?;
+    types += ";\n";
+    types += &specta::ts::export::<crate::analytics::AutoplayStats>(config)?;
+    types += ";\n";
 
     Ok(types)
 }
@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, specta::Type)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, specta::Type)]
 #[cfg_attr(
     feature = "appdeps",
     derive(sea_orm::EnumIter, sea_orm::DeriveActiveEnum),
@@ -37,6 +37,8 @@ pub enum Typ {
     SongBlacklist,
     #[cfg_attr(feature = "appdeps", sea_orm(num_value = 11))]
     Updater,
+    #[cfg_attr(feature = "appdeps", sea_orm(num_value = 19))]
+    SmartPlaylist,
 
     #[cfg_attr(feature = "appdeps", sea_orm(num_value = 12))]
     StSong,
@@ -163,29 +165,293 @@ pub enum SearchQuery {
     Continuation(SearchContinuation),
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, specta::Type)]
+#[cfg_attr(
+    feature = "wasmdeps",
+    derive(tsify_next::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub struct UniversalMatches<T> {
+    // best group first. a group is as good as its best item
+    pub groups: Vec<TypMatches<T>>,
+    pub continuation: Option<UniversalContinuation>,
+}
+#[derive(Serialize, Deserialize, Clone, Debug, specta::Type)]
+#[cfg_attr(
+    feature = "wasmdeps",
+    derive(tsify_next::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub struct TypMatches<T> {
+    pub typ: Typ,
+    pub items: Vec<ScoredItem<T>>,
+}
+#[derive(Serialize, Deserialize, Clone, Debug, specta::Type)]
+#[cfg_attr(
+    feature = "wasmdeps",
+    derive(tsify_next::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub struct ScoredItem<T> {
+    pub score: i32,
+    pub item: DbItem<T>,
+}
+#[derive(Serialize, Deserialize, Clone, Debug, specta::Type)]
+#[cfg_attr(
+    feature = "wasmdeps",
+    derive(tsify_next::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub struct UniversalContinuation {
+    pub typs: Vec<Typ>,
+    pub page_size: u32,
+    pub query: String,
+    pub cont: String, // score + id
+}
+#[derive(Serialize, Deserialize, Clone, Debug, specta::Type)]
+#[serde(tag = "type", content = "content")]
+#[cfg_attr(
+    feature = "wasmdeps",
+    derive(tsify_next::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub enum UniversalQuery {
+    Query {
+        // None searches every typ
+        typs: Option<Vec<Typ>>,
+        page_size: u32,
+        query: String,
+    },
+    Continuation(UniversalContinuation),
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, specta::Type)]
+#[cfg_attr(
+    feature = "wasmdeps",
+    derive(tsify_next::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub enum MetadataField {
+    Likes,
+    Dislikes,
+    Interactions,
+    AddedTs,
+    UpdatedTs,
+}
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, specta::Type)]
+#[cfg_attr(
+    feature = "wasmdeps",
+    derive(tsify_next::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub enum FilterOp {
+    Eq,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+}
+#[derive(Serialize, Deserialize, Clone, Debug, specta::Type)]
+#[serde(tag = "type", content = "content")]
+#[cfg_attr(
+    feature = "wasmdeps",
+    derive(tsify_next::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub enum MetadataFilter {
+    Done(bool),
+    Cmp {
+        field: MetadataField,
+        op: FilterOp,
+        #[serde(with = "serde_with_string")]
+        value: u64,
+    },
+}
+#[derive(Serialize, Deserialize, Clone, Debug, specta::Type)]
+#[cfg_attr(
+    feature = "wasmdeps",
+    derive(tsify_next::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub struct DbQuery {
+    pub filters: Vec<MetadataFilter>,
+    // every word must be a (case insensitive) substring of the item's haystack
+    pub text: Option<String>,
+    // None sorts by id
+    pub sort_by: Option<MetadataField>,
+    pub desc: bool,
+    pub page_size: u32,
+    // sort value + id of the last item of the previous page
+    pub cursor: Option<String>,
+}
+#[derive(Serialize, Deserialize, Clone, Debug, specta::Type)]
+#[cfg_attr(
+    feature = "wasmdeps",
+    derive(tsify_next::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub struct QueryMatches<T> {
+    pub items: Vec<DbItem<T>>,
+    pub continuation: Option<DbQuery>,
+}
+#[derive(Serialize, Deserialize, Clone, Debug, specta::Type)]
+#[cfg_attr(
+    feature = "wasmdeps",
+    derive(tsify_next::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub struct TrashedItem<T> {
+    pub item: DbItem<T>,
+    #[serde(with = "serde_with_string")]
+    pub trashed_ts: u64,
+}
+// an older version of an object. item.metadata.update_counter identifies it
+#[derive(Serialize, Deserialize, Clone, Debug, specta::Type)]
+#[cfg_attr(
+    feature = "wasmdeps",
+    derive(tsify_next::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub struct Revision<T> {
+    pub item: DbItem<T>,
+    #[serde(with = "serde_with_string")]
+    pub replaced_ts: u64,
+}
+// old and new are json. None if the value does not exist on that side
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, specta::Type)]
+#[cfg_attr(
+    feature = "wasmdeps",
+    derive(tsify_next::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub struct JsonChange {
+    // json pointer
+    pub path: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+// items that look like the same track. see [crate::dedup::find_duplicates]
+#[derive(Serialize, Deserialize, Clone, Debug, specta::Type)]
+#[cfg_attr(
+    feature = "wasmdeps",
+    derive(tsify_next::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub struct DuplicateGroup {
+    // suggested survivor. always a covau Song
+    pub survivor: i32,
+    // Song or MmSong
+    pub duplicates: Vec<i32>,
+}
+
+// sent to subscribers after the transaction that caused them is committed
+#[derive(Serialize, Deserialize, Clone, Debug, specta::Type)]
+#[serde(tag = "type", content = "content")]
+#[cfg_attr(
+    feature = "wasmdeps",
+    derive(tsify_next::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub enum DbEvent {
+    Inserted {
+        id: i32,
+        typ: Typ,
+    },
+    Updated {
+        id: i32,
+        typ: Typ,
+        metadata: DbMetadata,
+    },
+    Deleted {
+        id: i32,
+        typ: Typ,
+    },
+    // subscriber was too slow and missed some events. anything it cares about might have changed
+    Resync,
+}
+// events match if either their typ or their id is in here
+#[derive(Serialize, Deserialize, Clone, Debug, Default, specta::Type)]
+#[cfg_attr(
+    feature = "wasmdeps",
+    derive(tsify_next::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub struct DbSubscription {
+    pub typs: Vec<Typ>,
+    pub ids: Vec<i32>,
+}
+impl DbSubscription {
+    pub fn matches(&self, event: &DbEvent) -> bool {
+        match event {
+            DbEvent::Inserted { id, typ }
+            | DbEvent::Updated { id, typ, .. }
+            | DbEvent::Deleted { id, typ } => self.typs.contains(typ) || self.ids.contains(id),
+            DbEvent::Resync => true,
+        }
+    }
+}
+
 #[cfg(feature = "appdeps")]
 pub use db::*;
 #[cfg(feature = "appdeps")]
 pub mod db {
-    use std::collections::HashSet;
+    use std::collections::{HashMap, HashSet};
     use std::sync::atomic::AtomicU32;
     use std::sync::Arc;
+    use std::time::Duration;
 
-    use anyhow::Context;
     use intrusive_collections::{intrusive_adapter, KeyAdapter, RBTreeLink};
     use sea_orm::{entity::prelude::*, Schema};
     use sea_orm::{Condition, DeriveEntityModel};
-    use sea_orm::{RelationTrait, TransactionTrait};
+    use sea_orm::{QueryOrder, QuerySelect, RelationTrait, TransactionTrait};
     use tokio::sync::{Mutex, Notify};
     use tokio_stream::StreamExt;
 
+    use crate::server::message_server::ConnectionId;
+    use crate::server::{ErrorKind, ErrorMessage};
+
     use super::*;
 
+    /// an object whose data does not parse as it's [Typ].
+    /// usually written by an older or newer covau
+    #[derive(Debug)]
+    pub struct BadRow {
+        pub id: DbId,
+        pub typ: Typ,
+        pub error: serde_json::Error,
+    }
+    impl std::fmt::Display for BadRow {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(
+                f,
+                "object {} ({:?}) could not be parsed: {}",
+                self.id, self.typ, self.error
+            )
+        }
+    }
+    impl std::error::Error for BadRow {}
+    impl BadRow {
+        pub fn log(&self) {
+            eprintln!("skipping bad row: {}", self);
+        }
+    }
+
+    /// parses json of a row. None (and logged) if it does not parse
+    fn bad_row_or<T: for<'de> Deserialize<'de>>(id: DbId, typ: Typ, json: &str) -> Option<T> {
+        serde_json::from_str(json)
+            .map_err(|error| BadRow { id, typ, error })
+            .inspect_err(BadRow::log)
+            .ok()
+    }
+
     impl DbItem<String> {
         pub fn parsed<T: DbAble>(self) -> anyhow::Result<DbItem<T>> {
             let t: T = serde_json::from_str(&self.t)?;
             if T::typ() != self.typ {
-                return Err(anyhow::anyhow!("type mismatch while parsing"));
+                return Err(
+                    ErrorMessage::new(ErrorKind::Invalid, "type mismatch while parsing").into(),
+                );
             }
             let dbitem = DbItem {
                 metadata: self.metadata,
@@ -206,8 +472,14 @@ pub mod db {
         fn to_json(&self) -> String;
         fn typ() -> Typ;
         fn haystack(&self) -> impl IntoIterator<Item = String>;
+        /// same strings as [DbAble::haystack], with the field each one belongs to
+        fn haystack_fields(&self) -> impl IntoIterator<Item = (HaystackField, String)>;
         fn refids(&self) -> impl IntoIterator<Item = String>;
         fn links(&self) -> impl IntoIterator<Item = Link>;
+        /// replace [DbId]s of other objects using this map. ids not in the map are dropped
+        fn remap_ids(&mut self, ids: &HashMap<DbId, DbId>);
+        /// [DbId]s of other objects
+        fn referenced_ids(&self) -> impl IntoIterator<Item = DbId>;
 
         async fn get_by_refid<C: ConnectionTrait>(
             &self,
@@ -226,12 +498,15 @@ pub mod db {
                 .await?
                 .map(|(_refid, obj)| obj)
                 .flatten()
-                .map(|e| DbItem {
-                    metadata: e.parse_assume_metadata(),
-                    t: e.parsed_assume(),
-                    id: e.id,
-                    typ: Self::typ(),
-                });
+                .map(|e| -> anyhow::Result<_> {
+                    Ok(DbItem {
+                        metadata: e.db_metadata(),
+                        t: e.try_parsed()?,
+                        id: e.id,
+                        typ: Self::typ(),
+                    })
+                })
+                .transpose()?;
             Ok(e)
         }
 
@@ -239,28 +514,14 @@ pub mod db {
         where
             C: ConnectionTrait,
         {
-            let am = object::ActiveModel {
-                id: sea_orm::ActiveValue::NotSet,
-                data: sea_orm::ActiveValue::Set(self.to_json()),
-                typ: sea_orm::ActiveValue::Set(Self::typ()),
-                metadata: sea_orm::ActiveValue::Set(DbMetadata::new().to_json()),
-            };
-            let obj = object::Entity::insert(am).exec(conn).await?;
+            self.insert_with_mdata(conn, &DbMetadata::new()).await
+        }
 
-            let refids = self
-                .refids()
-                .into_iter()
-                .map(String::from)
-                .collect::<HashSet<_>>();
-            for rid in refids {
-                let id = refid::ActiveModel {
-                    refid: sea_orm::ActiveValue::Set(rid.to_string()),
-                    typ: sea_orm::ActiveValue::Set(Self::typ()),
-                    object_id: sea_orm::ActiveValue::Set(obj.last_insert_id),
-                };
-                refid::Entity::insert(id).exec(conn).await?;
-            }
-            Ok(obj.last_insert_id)
+        async fn insert_with_mdata<C>(&self, conn: &C, mdata: &DbMetadata) -> anyhow::Result<i32>
+        where
+            C: ConnectionTrait,
+        {
+            insert_object(conn, self, sea_orm::ActiveValue::NotSet, mdata).await
         }
 
         async fn get<C: ConnectionTrait>(
@@ -268,12 +529,16 @@ pub mod db {
             id: DbId,
         ) -> anyhow::Result<Option<DbItem<Self>>> {
             let m = object::Entity::find_by_id(id).one(conn).await?;
-            let mdata = m.map(|m| DbItem {
-                metadata: m.parse_assume_metadata(),
-                t: m.parsed_assume(),
-                id: m.id,
-                typ: m.typ,
-            });
+            let mdata = m
+                .map(|m| -> anyhow::Result<_> {
+                    Ok(DbItem {
+                        metadata: m.db_metadata(),
+                        t: m.try_parsed()?,
+                        id: m.id,
+                        typ: m.typ,
+                    })
+                })
+                .transpose()?;
             Ok(mdata)
         }
 
@@ -287,7 +552,9 @@ pub mod db {
                 .map(|m| m.metadata.update_counter == mdata.update_counter)
                 .unwrap_or(false)
             {
-                return Err(anyhow::anyhow!("invalid update (mdata)"));
+                return Err(
+                    ErrorMessage::new(ErrorKind::Conflict, "invalid update (mdata)").into(),
+                );
             }
 
             mdata.update_counter += 1;
@@ -297,7 +564,7 @@ pub mod db {
                 id: sea_orm::ActiveValue::Unchanged(id),
                 data: sea_orm::ActiveValue::NotSet,
                 typ: sea_orm::ActiveValue::Unchanged(Self::typ()),
-                metadata: sea_orm::ActiveValue::Set(mdata.to_json()),
+                ..object::ActiveModel::with_metadata(&mdata)
             };
             let _obj = object::Entity::update(am).exec(conn).await?;
             Ok(mdata)
@@ -305,115 +572,595 @@ pub mod db {
     }
 
     impl<T: DbAble> DbItem<T> {
+        /// the old version is kept in history. see [Db::revisions]
         pub async fn update<C: ConnectionTrait>(&self, conn: &C) -> anyhow::Result<DbMetadata> {
-            let old = T::get(conn, self.id).await?;
-            if !old
-                .map(|m| m.metadata.update_counter == self.metadata.update_counter)
-                .unwrap_or(false)
-            {
-                return Err(anyhow::anyhow!("invalid update (mdata)"));
-            }
+            Self::update_many(conn, std::slice::from_ref(self))
+                .await?
+                .pop()
+                .expect("one result per item")
+        }
 
-            let mut mdata = self.metadata.clone();
-            mdata.update_counter += 1;
-            mdata.updated_ts = Db::timestamp();
+        /// one result per item. items that fail the checks are not written. the rest are
+        /// written together, so an error from the db fails all of them
+        pub async fn update_many<C: ConnectionTrait>(
+            conn: &C,
+            items: &[Self],
+        ) -> anyhow::Result<Vec<anyhow::Result<DbMetadata>>> {
+            let ids = items.iter().map(|i| i.id).collect::<Vec<_>>();
+            let mut old = HashMap::new();
+            for chunk in ids.chunks(BATCH_SIZE) {
+                let models = object::Entity::find()
+                    .filter(object::Column::Id.is_in(chunk.iter().copied()))
+                    .filter(object::Column::Typ.eq(T::typ()))
+                    .all(conn)
+                    .await?;
+                old.extend(models.into_iter().map(|m| (m.id, m)));
+            }
+
+            let now = Db::timestamp();
+            let mut res = vec![];
+            let mut history = vec![];
+            let mut updated = vec![];
+            for item in items {
+                // a second update of the same item in a batch has an old update_counter
+                let Some(m) = old
+                    .remove(&item.id)
+                    .filter(|m| m.update_counter as u32 == item.metadata.update_counter)
+                else {
+                    res.push(Err(ErrorMessage::new(
+                        ErrorKind::Conflict,
+                        "invalid update (mdata)",
+                    )
+                    .into()));
+                    continue;
+                };
 
-            let am = object::ActiveModel {
-                id: sea_orm::ActiveValue::Unchanged(self.id),
-                data: sea_orm::ActiveValue::Set(self.t.to_json()),
-                typ: sea_orm::ActiveValue::Unchanged(T::typ()),
-                metadata: sea_orm::ActiveValue::Set(mdata.to_json()),
-            };
-            let _ = object::Entity::update(am).exec(conn).await?;
+                history.push(history::ActiveModel {
+                    object_id: sea_orm::ActiveValue::Set(m.id),
+                    update_counter: sea_orm::ActiveValue::Set(m.update_counter as _),
+                    typ: sea_orm::ActiveValue::Set(T::typ()),
+                    metadata: sea_orm::ActiveValue::Set(m.db_metadata().to_json()),
+                    data: sea_orm::ActiveValue::Set(m.data),
+                    replaced_ts: sea_orm::ActiveValue::Set(now as _),
+                });
 
-            let _ = refid::Entity::delete_many()
-                .filter(refid::Column::ObjectId.eq(self.id))
-                .exec(conn)
-                .await?;
+                let mut mdata = item.metadata.clone();
+                mdata.update_counter += 1;
+                mdata.updated_ts = now;
+                updated.push((item, mdata.clone()));
+                res.push(Ok(mdata));
+            }
+            if updated.is_empty() {
+                return Ok(res);
+            }
 
-            let refids = self
-                .t
-                .refids()
-                .into_iter()
-                .map(String::from)
-                .collect::<HashSet<_>>();
+            for chunk in history.chunks(BATCH_SIZE) {
+                history::Entity::insert_many(chunk.iter().cloned())
+                    .exec_without_returning(conn)
+                    .await?;
+            }
 
-            for rid in refids {
-                let id = refid::ActiveModel {
-                    refid: sea_orm::ActiveValue::Set(rid.to_string()),
-                    typ: sea_orm::ActiveValue::Set(T::typ()),
-                    object_id: sea_orm::ActiveValue::Set(self.id),
+            // data differs per row. so one update each
+            for (item, mdata) in updated.iter() {
+                let am = object::ActiveModel {
+                    id: sea_orm::ActiveValue::Unchanged(item.id),
+                    data: sea_orm::ActiveValue::Set(item.t.to_json()),
+                    typ: sea_orm::ActiveValue::Unchanged(T::typ()),
+                    ..object::ActiveModel::with_metadata(mdata)
                 };
-                refid::Entity::insert(id).exec(conn).await?;
+                let _ = object::Entity::update(am).exec(conn).await?;
             }
-            Ok(mdata)
+
+            let ids = updated.iter().map(|(i, _)| i.id).collect::<Vec<_>>();
+            unindex_objects(conn, T::typ(), &ids).await?;
+            let items = updated
+                .iter()
+                .map(|(i, _)| (i.id, &i.t))
+                .collect::<Vec<_>>();
+            index_objects(conn, &items).await?;
+
+            Ok(res)
         }
 
+        /// moves the item to the trash. see [Db::restore] and [Db::purge]
         pub async fn delete<C: ConnectionTrait>(&self, conn: &C) -> anyhow::Result<()> {
-            let _ = refid::Entity::delete_many()
-                .filter(refid::Column::ObjectId.eq(self.id))
-                .exec(conn)
-                .await?;
+            Self::delete_many(conn, std::slice::from_ref(self))
+                .await?
+                .pop()
+                .expect("one result per item")
+        }
 
-            let _ = object::Entity::delete_by_id(self.id).exec(conn).await?;
-            Ok(())
+        /// one result per item. see [Self::update_many]
+        pub async fn delete_many<C: ConnectionTrait>(
+            conn: &C,
+            items: &[Self],
+        ) -> anyhow::Result<Vec<anyhow::Result<()>>> {
+            let ids = items.iter().map(|i| i.id).collect::<Vec<_>>();
+            let mut found = HashMap::new();
+            for chunk in ids.chunks(BATCH_SIZE) {
+                let models = object::Entity::find()
+                    .filter(object::Column::Id.is_in(chunk.iter().copied()))
+                    .filter(object::Column::Typ.eq(T::typ()))
+                    .all(conn)
+                    .await?;
+                found.extend(models.into_iter().map(|m| (m.id, m)));
+            }
+
+            let now = Db::timestamp();
+            let mut res = vec![];
+            let mut trashed = vec![];
+            for item in items {
+                // also for the second delete of an item in the same batch
+                let Some(m) = found.remove(&item.id) else {
+                    res.push(Err(ErrorMessage::new(
+                        ErrorKind::NotFound,
+                        "item not found",
+                    )
+                    .into()));
+                    continue;
+                };
+                trashed.push(trash::ActiveModel {
+                    id: sea_orm::ActiveValue::Set(m.id),
+                    typ: sea_orm::ActiveValue::Set(m.typ.clone()),
+                    metadata: sea_orm::ActiveValue::Set(m.db_metadata().to_json()),
+                    data: sea_orm::ActiveValue::Set(m.data),
+                    trashed_ts: sea_orm::ActiveValue::Set(now as _),
+                });
+                res.push(Ok(()));
+            }
+
+            for chunk in trashed.chunks(BATCH_SIZE) {
+                trash::Entity::insert_many(chunk.iter().cloned())
+                    .exec_without_returning(conn)
+                    .await?;
+            }
+            let ids = trashed
+                .iter()
+                .map(|t| t.id.clone().unwrap())
+                .collect::<Vec<_>>();
+            remove_objects(conn, T::typ(), &ids).await?;
+
+            Ok(res)
         }
     }
 
-    pub trait Linked<To> {}
+    /// id is only set when restoring objects from the trash
+    async fn insert_object<T: DbAble, C: ConnectionTrait>(
+        conn: &C,
+        t: &T,
+        id: sea_orm::ActiveValue<DbId>,
+        mdata: &DbMetadata,
+    ) -> anyhow::Result<DbId> {
+        let am = object::ActiveModel {
+            id,
+            data: sea_orm::ActiveValue::Set(t.to_json()),
+            typ: sea_orm::ActiveValue::Set(T::typ()),
+            ..object::ActiveModel::with_metadata(mdata)
+        };
+        let obj = object::Entity::insert(am).exec(conn).await?;
+        index_objects(conn, &[(obj.last_insert_id, t)]).await?;
+        Ok(obj.last_insert_id)
+    }
 
-    pub trait AutoDbAble {
-        fn typ() -> Typ;
-        fn haystack(&self) -> impl IntoIterator<Item = String>;
-        fn refids(&self) -> impl IntoIterator<Item = String> {
-            []
-        }
-        fn links(&self) -> impl IntoIterator<Item = Link> {
-            []
+    /// new objects with fresh metadata. ids are in the same order as the items
+    pub(crate) async fn insert_objects<T: DbAble, C: ConnectionTrait>(
+        conn: &C,
+        items: &[&T],
+    ) -> anyhow::Result<Vec<DbId>> {
+        let mdata = DbMetadata::new();
+        let mut ids = vec![];
+        // the refids and the index need the id of each row. so one insert each
+        for t in items {
+            let am = object::ActiveModel {
+                id: sea_orm::ActiveValue::NotSet,
+                data: sea_orm::ActiveValue::Set(t.to_json()),
+                typ: sea_orm::ActiveValue::Set(T::typ()),
+                ..object::ActiveModel::with_metadata(&mdata)
+            };
+            ids.push(object::Entity::insert(am).exec(conn).await?.last_insert_id);
         }
+        let indexed = ids
+            .iter()
+            .copied()
+            .zip(items.iter().copied())
+            .collect::<Vec<_>>();
+        index_objects(conn, &indexed).await?;
+        Ok(ids)
     }
-    impl<T> DbAble for T
-    where
-        T: Serialize
-            + for<'de> Deserialize<'de>
-            + std::fmt::Debug
-            + Clone
-            + AutoDbAble
-            + Sync
-            + Send
-            + 'static,
-    {
-        fn to_json(&self) -> String {
-            serde_json::to_string(self).expect("won't fail")
-        }
-        fn typ() -> Typ {
-            <Self as AutoDbAble>::typ()
+
+    /// refids, links and search index entries of these objects
+    async fn index_objects<T: DbAble, C: ConnectionTrait>(
+        conn: &C,
+        items: &[(DbId, &T)],
+    ) -> anyhow::Result<()> {
+        let mut refids = vec![];
+        let mut links = HashSet::new();
+        let mut haystacks = vec![];
+        for (id, t) in items.iter() {
+            let rids = t.refids().into_iter().collect::<HashSet<_>>();
+            refids.extend(rids.into_iter().map(|rid| refid::ActiveModel {
+                refid: sea_orm::ActiveValue::Set(rid),
+                typ: sea_orm::ActiveValue::Set(T::typ()),
+                object_id: sea_orm::ActiveValue::Set(*id),
+            }));
+            links.extend(t.links());
+            let haystack = t.haystack().into_iter().collect::<Vec<_>>();
+            if !haystack.is_empty() {
+                haystacks.push((*id, haystack.join("\n")));
+            }
         }
-        fn haystack(&self) -> impl IntoIterator<Item = String> {
-            <Self as AutoDbAble>::haystack(self)
+
+        for chunk in refids.chunks(BATCH_SIZE) {
+            refid::Entity::insert_many(chunk.iter().cloned())
+                .exec_without_returning(conn)
+                .await?;
         }
-        fn refids(&self) -> impl IntoIterator<Item = String> {
-            <Self as AutoDbAble>::refids(self)
+        let links = links.into_iter().collect::<Vec<_>>();
+        for chunk in links.chunks(BATCH_SIZE) {
+            insert_links(conn, chunk.iter().cloned().collect()).await?;
         }
-        fn links(&self) -> impl IntoIterator<Item = Link> {
-            <Self as AutoDbAble>::links(self)
+        for chunk in haystacks.chunks(BATCH_SIZE) {
+            let rows = vec!["(?, ?)"; chunk.len()].join(", ");
+            let stmt = sea_orm::Statement::from_sql_and_values(
+                conn.get_database_backend(),
+                format!("INSERT INTO {SEARCH_INDEX_TABLE} (rowid, haystack) VALUES {rows};"),
+                chunk
+                    .iter()
+                    .flat_map(|(id, h)| [(*id).into(), h.clone().into()]),
+            );
+            let _ = conn.execute(stmt).await?;
         }
+        Ok(())
     }
 
-    mod covau_types {
-        use std::collections::HashSet;
+    /// removes what [index_objects] wrote for these objects
+    async fn unindex_objects<C: ConnectionTrait>(
+        conn: &C,
+        typ: Typ,
+        ids: &[DbId],
+    ) -> anyhow::Result<()> {
+        for chunk in ids.chunks(BATCH_SIZE) {
+            // links are keyed on refids. so they have to go before the refids do
+            delete_links(conn, typ.clone(), chunk).await?;
+
+            let marks = vec!["?"; chunk.len()].join(", ");
+            let stmt = sea_orm::Statement::from_sql_and_values(
+                conn.get_database_backend(),
+                format!("DELETE FROM {SEARCH_INDEX_TABLE} WHERE rowid IN ({marks});"),
+                chunk.iter().map(|id| (*id).into()),
+            );
+            let _ = conn.execute(stmt).await?;
 
-        use super::{AutoDbAble, Link, Linked, Typ};
-        use crate::covau_types::*;
+            let _ = refid::Entity::delete_many()
+                .filter(refid::Column::ObjectId.is_in(chunk.iter().copied()))
+                .exec(conn)
+                .await?;
+        }
+        Ok(())
+    }
 
-        impl AutoDbAble for LocalState {
-            fn typ() -> Typ {
-                Typ::LocalState
-            }
+    /// removes the object along with everything derived from it
+    pub(crate) async fn remove_object<C: ConnectionTrait>(
+        conn: &C,
+        typ: Typ,
+        id: DbId,
+    ) -> anyhow::Result<()> {
+        remove_objects(conn, typ, &[id]).await
+    }
 
-            fn haystack(&self) -> impl IntoIterator<Item = String> {
+    pub(crate) async fn remove_objects<C: ConnectionTrait>(
+        conn: &C,
+        typ: Typ,
+        ids: &[DbId],
+    ) -> anyhow::Result<()> {
+        unindex_objects(conn, typ, ids).await?;
+        for chunk in ids.chunks(BATCH_SIZE) {
+            let _ = object::Entity::delete_many()
+                .filter(object::Column::Id.is_in(chunk.iter().copied()))
+                .exec(conn)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// multiple objects of different types can link to the same thing. so conflicts are ignored
+    pub(crate) async fn insert_links<C: ConnectionTrait>(
+        conn: &C,
+        links: HashSet<Link>,
+    ) -> anyhow::Result<()> {
+        if links.is_empty() {
+            return Ok(());
+        }
+
+        let links = links.into_iter().map(|l| link::ActiveModel {
+            from_refid: sea_orm::ActiveValue::Set(l.from_refid),
+            from_typ: sea_orm::ActiveValue::Set(l.from_typ),
+            to_refid: sea_orm::ActiveValue::Set(l.to_refid),
+            to_typ: sea_orm::ActiveValue::Set(l.to_typ),
+        });
+        let _ = link::Entity::insert_many(links)
+            .on_conflict(
+                sea_orm::sea_query::OnConflict::columns([
+                    link::Column::FromRefid,
+                    link::Column::FromTyp,
+                    link::Column::ToRefid,
+                    link::Column::ToTyp,
+                ])
+                .do_nothing()
+                .to_owned(),
+            )
+            .do_nothing()
+            .exec_without_returning(conn)
+            .await?;
+        Ok(())
+    }
+
+    /// refids are unique per typ. so (from_typ, from_refid) always points to the object
+    /// that owns these links
+    async fn delete_links<C: ConnectionTrait>(
+        conn: &C,
+        typ: Typ,
+        ids: &[DbId],
+    ) -> anyhow::Result<()> {
+        let _ = link::Entity::delete_many()
+            .filter(link::Column::FromTyp.eq(typ))
+            .filter(
+                link::Column::FromRefid.in_subquery(
+                    sea_orm::sea_query::Query::select()
+                        .column(refid::Column::Refid)
+                        .from(refid::Entity)
+                        .and_where(refid::Column::ObjectId.is_in(ids.iter().copied()))
+                        .to_owned(),
+                ),
+            )
+            .exec(conn)
+            .await?;
+        Ok(())
+    }
+
+    // - [FTS5](https://www.sqlite.org/fts5.html)
+    // rowid of this table is the object id
+    // trigram tokenizer so that any 3 chars of the query are enough to pick a candidate.
+    // candidates then get ranked using the fuzzy matcher
+    pub(crate) const SEARCH_INDEX_TABLE: &str = "search_index";
+
+    async fn index_haystack<C: ConnectionTrait>(
+        conn: &C,
+        id: DbId,
+        haystack: Vec<String>,
+    ) -> anyhow::Result<()> {
+        if haystack.is_empty() {
+            return Ok(());
+        }
+
+        let stmt = sea_orm::Statement::from_sql_and_values(
+            conn.get_database_backend(),
+            format!("INSERT INTO {SEARCH_INDEX_TABLE} (rowid, haystack) VALUES (?, ?);"),
+            [id.into(), haystack.join("\n").into()],
+        );
+        let _ = conn.execute(stmt).await?;
+        Ok(())
+    }
+
+    /// any trigram of any word in the needle is enough to be a candidate.
+    /// None if the needle is too short for the trigram tokenizer
+    fn search_index_query(needle: &str) -> Option<String> {
+        let trigrams = needle
+            .split_whitespace()
+            .map(|w| w.to_lowercase().chars().collect::<Vec<_>>())
+            .flat_map(|w| {
+                w.windows(3)
+                    .map(|t| t.iter().collect::<String>())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<HashSet<_>>();
+        if trigrams.is_empty() {
+            return None;
+        }
+
+        let query = trigrams
+            .into_iter()
+            .map(|t| format!("\"{}\"", t.replace('"', "\"\"")))
+            .collect::<Vec<_>>()
+            .join(" OR ");
+        Some(query)
+    }
+
+    /// filter for objects with the word anywhere in their haystack. '%' and '_' match
+    /// themselves
+    pub(crate) fn haystack_contains(word: &str) -> sea_orm::sea_query::SimpleExpr {
+        // trigram tokenizer can use the index for LIKE patterns with 3 or more chars, but not
+        // with an ESCAPE clause. so only words that need it get one
+        // - [trigram](https://www.sqlite.org/fts5.html#the_trigram_tokenizer)
+        if word.contains(['%', '_', '\\']) {
+            let word = word
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            sea_orm::sea_query::Expr::cust_with_values(
+                format!(
+                    "id IN (SELECT rowid FROM {SEARCH_INDEX_TABLE} WHERE haystack LIKE ? ESCAPE '\\')"
+                ),
+                [format!("%{}%", word)],
+            )
+        } else {
+            sea_orm::sea_query::Expr::cust_with_values(
+                format!("id IN (SELECT rowid FROM {SEARCH_INDEX_TABLE} WHERE haystack LIKE ?)"),
+                [format!("%{}%", word)],
+            )
+        }
+    }
+
+    /// which part of an item a haystack string is. field qualifiers in search queries
+    /// only look at their field
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum HaystackField {
+        Title,
+        Artist,
+        Album,
+    }
+
+    /// how much popular items rank up in search results
+    #[derive(Clone, Copy, Debug)]
+    pub struct SearchBoost {
+        // per like
+        pub likes: u32,
+        // per doubling of interactions
+        pub interactions: u32,
+    }
+    impl Default for SearchBoost {
+        fn default() -> Self {
+            Self {
+                likes: 20,
+                interactions: 10,
+            }
+        }
+    }
+    impl SearchBoost {
+        pub fn score(&self, m: &DbMetadata) -> isize {
+            let likes = self.likes as isize * m.likes as isize;
+            let interactions =
+                self.interactions as isize * m.interactions.saturating_add(1).ilog2() as isize;
+            likes + interactions
+        }
+    }
+
+    pub struct Retention {
+        pub trash: Duration,
+        pub history: Duration,
+        // per object
+        pub max_revisions: u32,
+    }
+
+    fn json_diff(
+        path: String,
+        old: Option<&serde_json::Value>,
+        new: Option<&serde_json::Value>,
+        changes: &mut Vec<JsonChange>,
+    ) {
+        use serde_json::Value;
+
+        // - [JSON Pointer](https://datatracker.ietf.org/doc/html/rfc6901)
+        let child = |key: &str| format!("{}/{}", path, key.replace('~', "~0").replace('/', "~1"));
+        match (old, new) {
+            (Some(Value::Object(o)), Some(Value::Object(n))) => {
+                let keys = o
+                    .keys()
+                    .chain(n.keys())
+                    .collect::<std::collections::BTreeSet<_>>();
+                for k in keys {
+                    json_diff(child(k), o.get(k), n.get(k), changes);
+                }
+            }
+            (Some(Value::Array(o)), Some(Value::Array(n))) => {
+                for i in 0..o.len().max(n.len()) {
+                    json_diff(child(&i.to_string()), o.get(i), n.get(i), changes);
+                }
+            }
+            (o, n) if o == n => (),
+            (o, n) => changes.push(JsonChange {
+                path,
+                old: o.map(|v| v.to_string()),
+                new: n.map(|v| v.to_string()),
+            }),
+        }
+    }
+
+    /// max items per 'IN (...)' list
+    pub(crate) const BATCH_SIZE: usize = 500;
+
+    // queries always filter on typ. so typ goes first
+    const METADATA_INDICES: [(&str, object::Column); 6] = [
+        ("done_index", object::Column::Done),
+        ("likes_index", object::Column::Likes),
+        ("dislikes_index", object::Column::Dislikes),
+        ("interactions_index", object::Column::Interactions),
+        ("added_ts_index", object::Column::AddedTs),
+        ("updated_ts_index", object::Column::UpdatedTs),
+    ];
+
+    pub trait Linked<To> {}
+
+    pub trait AutoDbAble {
+        fn typ() -> Typ;
+        fn haystack(&self) -> impl IntoIterator<Item = String>;
+        // everything is a title unless said otherwise
+        fn haystack_fields(&self) -> impl IntoIterator<Item = (HaystackField, String)> {
+            self.haystack()
+                .into_iter()
+                .map(|h| (HaystackField::Title, h))
+                .collect::<Vec<_>>()
+        }
+        fn refids(&self) -> impl IntoIterator<Item = String> {
+            []
+        }
+        fn links(&self) -> impl IntoIterator<Item = Link> {
+            []
+        }
+        fn remap_ids(&mut self, _ids: &HashMap<DbId, DbId>) {}
+        fn referenced_ids(&self) -> impl IntoIterator<Item = DbId> {
+            []
+        }
+    }
+    impl<T> DbAble for T
+    where
+        T: Serialize
+            + for<'de> Deserialize<'de>
+            + std::fmt::Debug
+            + Clone
+            + AutoDbAble
+            + Sync
+            + Send
+            + 'static,
+    {
+        fn to_json(&self) -> String {
+            serde_json::to_string(self).expect("won't fail")
+        }
+        fn typ() -> Typ {
+            <Self as AutoDbAble>::typ()
+        }
+        fn haystack(&self) -> impl IntoIterator<Item = String> {
+            <Self as AutoDbAble>::haystack(self)
+        }
+        fn haystack_fields(&self) -> impl IntoIterator<Item = (HaystackField, String)> {
+            <Self as AutoDbAble>::haystack_fields(self)
+        }
+        fn refids(&self) -> impl IntoIterator<Item = String> {
+            <Self as AutoDbAble>::refids(self)
+        }
+        fn links(&self) -> impl IntoIterator<Item = Link> {
+            <Self as AutoDbAble>::links(self)
+        }
+        fn remap_ids(&mut self, ids: &HashMap<DbId, DbId>) {
+            <Self as AutoDbAble>::remap_ids(self, ids)
+        }
+        fn referenced_ids(&self) -> impl IntoIterator<Item = DbId> {
+            <Self as AutoDbAble>::referenced_ids(self)
+        }
+    }
+
+    mod covau_types {
+        use std::collections::{HashMap, HashSet};
+
+        use super::{AutoDbAble, DbId, HaystackField, Link, Linked, Typ};
+        use crate::covau_types::*;
+
+        impl AutoDbAble for LocalState {
+            fn typ() -> Typ {
+                Typ::LocalState
+            }
+
+            fn haystack(&self) -> impl IntoIterator<Item = String> {
                 []
             }
+
+            fn remap_ids(&mut self, ids: &HashMap<DbId, DbId>) {
+                self.queue = self.queue.and_then(|id| ids.get(&id).copied());
+            }
+
+            fn referenced_ids(&self) -> impl IntoIterator<Item = DbId> {
+                self.queue
+            }
         }
 
         impl Linked<crate::yt::song_tube::Song> for Song {}
@@ -434,6 +1181,16 @@ pub mod db {
                 h
             }
 
+            fn haystack_fields(&self) -> impl IntoIterator<Item = (HaystackField, String)> {
+                let mut h = self
+                    .artists
+                    .iter()
+                    .map(|a| (HaystackField::Artist, a.name.clone()))
+                    .collect::<Vec<_>>();
+                h.push((HaystackField::Title, self.title.clone()));
+                h
+            }
+
             fn refids(&self) -> impl IntoIterator<Item = String> {
                 let mut hs = vec![];
 
@@ -476,8 +1233,13 @@ pub mod db {
                                     to_typ: crate::yt::song_tube::Song::typ(),
                                 });
                             }
-                            InfoSource::MbzId(_) => {
-                                // hs.push(id.to_owned());
+                            InfoSource::MbzId(id) => {
+                                links.push(Link {
+                                    from_refid: from_id.clone(),
+                                    from_typ: Self::typ(),
+                                    to_refid: id.to_owned(),
+                                    to_typ: crate::mbz::RecordingWithInfo::typ(),
+                                });
                             }
                         }
                     }
@@ -515,6 +1277,18 @@ pub mod db {
             fn haystack(&self) -> impl IntoIterator<Item = String> {
                 [self.title.clone()]
             }
+
+            fn remap_ids(&mut self, ids: &HashMap<DbId, DbId>) {
+                self.songs = self
+                    .songs
+                    .iter()
+                    .filter_map(|id| ids.get(id).copied())
+                    .collect();
+            }
+
+            fn referenced_ids(&self) -> impl IntoIterator<Item = DbId> {
+                self.songs.clone()
+            }
         }
 
         impl AutoDbAble for Queue {
@@ -525,6 +1299,68 @@ pub mod db {
             fn haystack(&self) -> impl IntoIterator<Item = String> {
                 [self.queue.queue.title.clone()]
             }
+
+            fn remap_ids(&mut self, ids: &HashMap<DbId, DbId>) {
+                // dropped songs before the current one shift it
+                let current = self.queue.current_index;
+                let dropped = self
+                    .queue
+                    .queue
+                    .songs
+                    .iter()
+                    .take(current.unwrap_or(0) as usize)
+                    .filter(|id| !ids.contains_key(id))
+                    .count() as u32;
+                AutoDbAble::remap_ids(&mut self.queue.queue, ids);
+                self.queue.current_index = current
+                    .map(|i| i - dropped)
+                    .filter(|&i| (i as usize) < self.queue.queue.songs.len());
+
+                self.blacklist = self.blacklist.and_then(|id| ids.get(&id).copied());
+                self.seen = self.seen.and_then(|id| ids.get(&id).copied());
+                self.seed = self.seed.and_then(|id| ids.get(&id).copied());
+            }
+
+            fn referenced_ids(&self) -> impl IntoIterator<Item = DbId> {
+                let mut ids = self.queue.queue.songs.clone();
+                ids.extend(self.blacklist);
+                ids.extend(self.seen);
+                ids.extend(self.seed);
+                ids
+            }
+        }
+
+        impl AutoDbAble for SmartPlaylist {
+            fn typ() -> Typ {
+                Typ::SmartPlaylist
+            }
+
+            fn haystack(&self) -> impl IntoIterator<Item = String> {
+                [self.title.clone()]
+            }
+
+            fn remap_ids(&mut self, ids: &HashMap<DbId, DbId>) {
+                for rule in self.rules.iter_mut() {
+                    if let SmartRule::InPlaylist(playlists) = rule {
+                        // an empty rule matches nothing. so dropped playlists don't widen it
+                        *playlists = playlists
+                            .iter()
+                            .filter_map(|id| ids.get(id).copied())
+                            .collect();
+                    }
+                }
+            }
+
+            fn referenced_ids(&self) -> impl IntoIterator<Item = DbId> {
+                self.rules
+                    .iter()
+                    .filter_map(|rule| match rule {
+                        SmartRule::InPlaylist(playlists) => Some(playlists.clone()),
+                        _ => None,
+                    })
+                    .flatten()
+                    .collect::<Vec<_>>()
+            }
         }
 
         // impl Linked<Updater, crate::mbz::Artist> {}
@@ -589,7 +1425,7 @@ pub mod db {
     }
 
     mod yt {
-        use super::{db, AutoDbAble, Link, Linked};
+        use super::{db, AutoDbAble, HaystackField, Link, Linked};
         use crate::yt::song_tube::*;
 
         impl Linked<Album> for Song {}
@@ -620,6 +1456,23 @@ pub mod db {
                 hs
             }
 
+            fn haystack_fields(&self) -> impl IntoIterator<Item = (HaystackField, String)> {
+                let mut hs = vec![];
+                hs.extend(self.title.clone().map(|t| (HaystackField::Title, t)));
+                hs.extend(
+                    self.authors
+                        .iter()
+                        .map(|a| (HaystackField::Artist, a.name.clone())),
+                );
+                hs.extend(
+                    self.album
+                        .as_ref()
+                        .and_then(|a| a.name.clone())
+                        .map(|n| (HaystackField::Album, n)),
+                );
+                hs
+            }
+
             fn refids(&self) -> impl IntoIterator<Item = String> {
                 [self.id.to_owned()]
             }
@@ -671,6 +1524,17 @@ pub mod db {
                 hs
             }
 
+            fn haystack_fields(&self) -> impl IntoIterator<Item = (HaystackField, String)> {
+                let mut hs = vec![];
+                hs.extend(self.title.clone().map(|t| (HaystackField::Title, t)));
+                hs.extend(
+                    self.author
+                        .as_ref()
+                        .map(|a| (HaystackField::Artist, a.name.clone())),
+                );
+                hs
+            }
+
             fn refids(&self) -> impl IntoIterator<Item = String> {
                 [self.id.clone()]
             }
@@ -711,6 +1575,17 @@ pub mod db {
                 hs
             }
 
+            fn haystack_fields(&self) -> impl IntoIterator<Item = (HaystackField, String)> {
+                let mut hs = vec![];
+                hs.extend(self.title.clone().map(|t| (HaystackField::Title, t)));
+                hs.extend(
+                    self.author
+                        .as_ref()
+                        .map(|a| (HaystackField::Artist, a.name.clone())),
+                );
+                hs
+            }
+
             fn refids(&self) -> impl IntoIterator<Item = String> {
                 [self.id.clone()]
             }
@@ -731,6 +1606,10 @@ pub mod db {
                 hs
             }
 
+            fn haystack_fields(&self) -> impl IntoIterator<Item = (HaystackField, String)> {
+                self.name.clone().map(|n| (HaystackField::Artist, n))
+            }
+
             fn refids(&self) -> impl IntoIterator<Item = String> {
                 [self.id.clone()]
             }
@@ -738,7 +1617,7 @@ pub mod db {
     }
 
     mod mbz {
-        use super::{AutoDbAble, Link, Linked, Typ};
+        use super::{AutoDbAble, HaystackField, Link, Linked, Typ};
         use crate::mbz::*;
 
         impl Linked<Artist> for RecordingWithInfo {}
@@ -757,6 +1636,16 @@ pub mod db {
                 hs
             }
 
+            fn haystack_fields(&self) -> impl IntoIterator<Item = (HaystackField, String)> {
+                let mut hs = vec![(HaystackField::Title, self.recording.title.clone())];
+                hs.extend(
+                    self.credit
+                        .iter()
+                        .map(|a| (HaystackField::Artist, a.name.clone())),
+                );
+                hs
+            }
+
             fn refids(&self) -> impl IntoIterator<Item = String> {
                 [self.recording.id.clone()]
             }
@@ -785,6 +1674,10 @@ pub mod db {
                 [self.name.clone()]
             }
 
+            fn haystack_fields(&self) -> impl IntoIterator<Item = (HaystackField, String)> {
+                [(HaystackField::Artist, self.name.clone())]
+            }
+
             fn refids(&self) -> impl IntoIterator<Item = String> {
                 [self.id.clone()]
             }
@@ -792,7 +1685,7 @@ pub mod db {
     }
 
     mod musimanager {
-        use super::{AutoDbAble, Link, Linked, Typ};
+        use super::{AutoDbAble, HaystackField, Link, Linked, Typ};
         use crate::covau_types::SourcePath;
         use crate::musimanager::*;
 
@@ -813,6 +1706,12 @@ pub mod db {
                 hs
             }
 
+            fn haystack_fields(&self) -> impl IntoIterator<Item = (HaystackField, String)> {
+                let mut hs = vec![(HaystackField::Title, self.title.clone())];
+                hs.extend(self.artist_name.clone().map(|a| (HaystackField::Artist, a)));
+                hs
+            }
+
             fn refids(&self) -> impl IntoIterator<Item = String> {
                 [self.key.clone()]
             }
@@ -842,6 +1741,13 @@ pub mod db {
                 [self.name.clone(), self.artist_name.clone()]
             }
 
+            fn haystack_fields(&self) -> impl IntoIterator<Item = (HaystackField, String)> {
+                [
+                    (HaystackField::Title, self.name.clone()),
+                    (HaystackField::Artist, self.artist_name.clone()),
+                ]
+            }
+
             fn refids(&self) -> impl IntoIterator<Item = String> {
                 [self.browse_id.clone()]
             }
@@ -870,6 +1776,10 @@ pub mod db {
                 [self.name.clone()]
             }
 
+            fn haystack_fields(&self) -> impl IntoIterator<Item = (HaystackField, String)> {
+                [(HaystackField::Artist, self.name.clone())]
+            }
+
             // fn refids(&self) -> impl IntoIterator<Item = String> {
             //     self.keys.iter().map(String::from).collect::<Vec<_>>()
             // }
@@ -894,7 +1804,7 @@ pub mod db {
         }
     }
 
-    mod object {
+    pub(crate) mod object {
         use super::*;
 
         #[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
@@ -903,13 +1813,28 @@ pub mod db {
             #[sea_orm(primary_key, auto_increment = true)]
             pub id: i32,
             pub data: String,
-            pub metadata: String,
             pub typ: Typ,
+
+            // [DbMetadata] lives in columns so that it can be filtered and sorted on
+            pub done: bool,
+            pub likes: i64,
+            pub dislikes: i64,
+            pub interactions: i64,
+            pub update_counter: i64,
+            pub added_ts: i64,
+            pub updated_ts: i64,
         }
         impl Model {
-            pub fn parsed_assume<T: for<'de> Deserialize<'de>>(&self) -> T {
-                let t: T = serde_json::from_str(&self.data).expect("parsing Model json failed");
-                t
+            pub fn try_parsed<T: for<'de> Deserialize<'de>>(&self) -> Result<T, BadRow> {
+                serde_json::from_str(&self.data).map_err(|error| BadRow {
+                    id: self.id,
+                    typ: self.typ.clone(),
+                    error,
+                })
+            }
+            /// bad rows are logged and skipped
+            pub fn parsed_or_skip<T: for<'de> Deserialize<'de>>(&self) -> Option<T> {
+                self.try_parsed().inspect_err(BadRow::log).ok()
             }
             pub fn parsed<T: DbAble>(&self) -> anyhow::Result<T> {
                 if T::typ() != self.typ {
@@ -918,10 +1843,31 @@ pub mod db {
                 let t: T = serde_json::from_str(&self.data)?;
                 Ok(t)
             }
-            pub fn parse_assume_metadata<T: for<'de> Deserialize<'de>>(&self) -> T {
-                let t: T = serde_json::from_str(&self.metadata)
-                    .expect("parsing Model metadata json failed");
-                t
+            pub fn db_metadata(&self) -> DbMetadata {
+                DbMetadata {
+                    done: self.done,
+                    likes: self.likes as _,
+                    dislikes: self.dislikes as _,
+                    interactions: self.interactions as _,
+                    update_counter: self.update_counter as _,
+                    added_ts: self.added_ts as _,
+                    updated_ts: self.updated_ts as _,
+                }
+            }
+        }
+        impl ActiveModel {
+            /// only the metadata columns are set
+            pub fn with_metadata(mdata: &DbMetadata) -> Self {
+                Self {
+                    done: sea_orm::ActiveValue::Set(mdata.done),
+                    likes: sea_orm::ActiveValue::Set(mdata.likes as _),
+                    dislikes: sea_orm::ActiveValue::Set(mdata.dislikes as _),
+                    interactions: sea_orm::ActiveValue::Set(mdata.interactions as _),
+                    update_counter: sea_orm::ActiveValue::Set(mdata.update_counter as _),
+                    added_ts: sea_orm::ActiveValue::Set(mdata.added_ts as _),
+                    updated_ts: sea_orm::ActiveValue::Set(mdata.updated_ts as _),
+                    ..Default::default()
+                }
             }
         }
 
@@ -957,7 +1903,7 @@ pub mod db {
             }
         }
     }
-    mod refid {
+    pub(crate) mod refid {
         use super::*;
 
         /// any other kind of id that we might need to match on
@@ -991,7 +1937,7 @@ pub mod db {
         impl ActiveModelBehavior for ActiveModel {}
     }
     pub use link::Model as Link;
-    mod link {
+    pub(crate) mod link {
         use super::*;
 
         // - objecs just add self_refid, self_type, link_refid.
@@ -1002,7 +1948,7 @@ pub mod db {
         //    gimme 'Song' linked to this artistid
         //    or gimme 'YtSong' linked to this Song's id
 
-        #[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+        #[derive(Clone, Debug, PartialEq, Eq, Hash, DeriveEntityModel)]
         #[sea_orm(table_name = "links")]
         pub struct Model {
             #[sea_orm(primary_key)]
@@ -1034,14 +1980,148 @@ pub mod db {
         impl ActiveModelBehavior for ActiveModel {}
     }
 
+    pub(crate) mod trash {
+        use super::*;
+
+        /// deleted objects. covau_objects is AUTOINCREMENT so these ids are never reused
+        /// and restored objects get their old id back
+        #[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+        #[sea_orm(table_name = "covau_trash")]
+        pub struct Model {
+            #[sea_orm(primary_key, auto_increment = false)]
+            pub id: i32,
+            pub typ: Typ,
+            pub data: String,
+            // [DbMetadata] json
+            pub metadata: String,
+            pub trashed_ts: i64,
+        }
+
+        #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+        pub enum Relation {}
+
+        impl ActiveModelBehavior for ActiveModel {}
+    }
+
+    pub(crate) mod history {
+        use super::*;
+
+        /// old versions of objects. only [DbItem::update] adds to this (not metadata updates)
+        #[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+        #[sea_orm(table_name = "covau_history")]
+        pub struct Model {
+            #[sea_orm(primary_key, auto_increment = false)]
+            pub object_id: i32,
+            #[sea_orm(primary_key, auto_increment = false)]
+            pub update_counter: i64,
+            pub typ: Typ,
+            pub data: String,
+            // [DbMetadata] json
+            pub metadata: String,
+            pub replaced_ts: i64,
+        }
+
+        #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+        pub enum Relation {}
+
+        impl ActiveModelBehavior for ActiveModel {}
+    }
+
+    pub(crate) mod quarantine {
+        use super::*;
+
+        /// objects that `covau db fsck --repair` could not parse. kept for manual recovery
+        #[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+        #[sea_orm(table_name = "covau_quarantine")]
+        pub struct Model {
+            #[sea_orm(primary_key, auto_increment = false)]
+            pub id: i32,
+            pub typ: Typ,
+            pub data: String,
+            // [DbMetadata] json
+            pub metadata: String,
+            pub reason: String,
+            pub quarantined_ts: i64,
+        }
+
+        #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+        pub enum Relation {}
+
+        impl ActiveModelBehavior for ActiveModel {}
+    }
+
+    pub(crate) mod play {
+        use super::*;
+
+        /// songs played by the ui. see [crate::plays]
+        #[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+        #[sea_orm(table_name = "covau_plays")]
+        pub struct Model {
+            #[sea_orm(primary_key)]
+            pub id: i32,
+            pub song_id: i32,
+            pub queue_id: Option<i32>,
+            pub started_ts: i64,
+            pub listened_secs: i64,
+            pub skipped: bool,
+            // [crate::covau_types::PlaySource] json
+            pub source: String,
+            pub autoplay: bool,
+        }
+
+        #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+        pub enum Relation {}
+
+        impl ActiveModelBehavior for ActiveModel {}
+    }
+
     pub type TransactionId = u32;
+    // (connection, request id) -> stops the subscription
+    pub type Subscriptions = HashMap<(ConnectionId, u32), Arc<Notify>>;
+
+    pub struct ActiveTransaction {
+        pub id: TransactionId,
+        pub txn: sea_orm::DatabaseTransaction,
+        /// websocket connection that began this transaction
+        pub owner: Option<ConnectionId>,
+        pub last_used: tokio::time::Instant,
+    }
+    impl ActiveTransaction {
+        pub fn access(
+            &mut self,
+            id: TransactionId,
+            owner: Option<ConnectionId>,
+        ) -> Result<&sea_orm::DatabaseTransaction, &'static str> {
+            if self.id != id {
+                return Err("Transaction Inactive");
+            }
+            // transactions without an owner are only for callers without one (rest, server tasks)
+            if self.owner != owner {
+                return Err("Transaction Owned By Another Connection");
+            }
+            self.last_used = tokio::time::Instant::now();
+            Ok(&self.txn)
+        }
+    }
 
     #[derive(Clone)]
     pub struct Db {
         pub db: sea_orm::DatabaseConnection,
         pub transaction_id: Arc<AtomicU32>,
-        pub transaction: Arc<Mutex<Option<(TransactionId, sea_orm::DatabaseTransaction)>>>,
+        pub transaction: Arc<Mutex<Option<ActiveTransaction>>>,
         pub notif: Arc<Notify>,
+
+        // idle transactions get rolled back after this
+        transaction_timeout: Duration,
+        // how long begin waits for the active transaction to finish
+        transaction_wait: Duration,
+
+        search_boost: SearchBoost,
+
+        // events of the active transaction. sent to subscribers on commit
+        pending_events: Arc<std::sync::Mutex<Vec<DbEvent>>>,
+        events: tokio::sync::broadcast::Sender<DbEvent>,
+        pub subscriptions: Arc<Mutex<Subscriptions>>,
     }
     impl Db {
         pub async fn new(path: impl AsRef<str>) -> anyhow::Result<Self> {
@@ -1063,10 +2143,27 @@ pub mod db {
                 transaction_id: Arc::new(0.into()),
                 transaction: Arc::new(Mutex::new(None)),
                 notif: Arc::new(Notify::new()),
+                transaction_timeout: Duration::from_secs(30),
+                transaction_wait: Duration::from_secs(30),
+                search_boost: SearchBoost::default(),
+                pending_events: Arc::new(std::sync::Mutex::new(Vec::new())),
+                events: tokio::sync::broadcast::channel(256).0,
+                subscriptions: Arc::new(Mutex::new(HashMap::new())),
             };
             Ok(db)
         }
 
+        pub fn with_transaction_timeouts(mut self, idle: Duration, wait: Duration) -> Self {
+            self.transaction_timeout = idle;
+            self.transaction_wait = wait;
+            self
+        }
+
+        pub fn with_search_boost(mut self, boost: SearchBoost) -> Self {
+            self.search_boost = boost;
+            self
+        }
+
         fn new_id(&self) -> TransactionId {
             let id = self
                 .transaction_id
@@ -1074,96 +2171,753 @@ pub mod db {
             id
         }
 
-        pub async fn begin(&self) -> anyhow::Result<TransactionId> {
+        pub async fn begin(&self, owner: Option<ConnectionId>) -> anyhow::Result<TransactionId> {
             let id = self.new_id();
+            let deadline = tokio::time::Instant::now() + self.transaction_wait;
+            loop {
+                // register before checking so that a commit in between is not missed
+                let notified = self.notif.notified();
+                tokio::pin!(notified);
+                notified.as_mut().enable();
+
+                let mut locked = self.lock_transaction_until(deadline).await?;
+                if locked.is_none() {
+                    let txn = self.db.begin().await?;
+                    *locked = Some(ActiveTransaction {
+                        id,
+                        txn,
+                        owner,
+                        last_used: tokio::time::Instant::now(),
+                    });
+                    self.pending_events.lock().expect("poisoned").clear();
+                    break;
+                }
+                drop(locked);
+
+                if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                    return Err(ErrorMessage::new(
+                        ErrorKind::Busy,
+                        "Timed out waiting for another transaction to finish",
+                    )
+                    .into());
+                }
+            }
+            self.watch_idle(id);
+            Ok(id)
+        }
+
+        /// the active transaction slot. server tasks (see [Self::in_transaction]) hold it
+        /// while they run, so this waits at most [Self::transaction_wait]
+        pub async fn lock_transaction(
+            &self,
+        ) -> anyhow::Result<tokio::sync::MutexGuard<'_, Option<ActiveTransaction>>> {
+            self.lock_transaction_until(tokio::time::Instant::now() + self.transaction_wait)
+                .await
+        }
+
+        async fn lock_transaction_until(
+            &self,
+            deadline: tokio::time::Instant,
+        ) -> anyhow::Result<tokio::sync::MutexGuard<'_, Option<ActiveTransaction>>> {
+            tokio::time::timeout_at(deadline, self.transaction.lock())
+                .await
+                .map_err(|_| {
+                    ErrorMessage::new(
+                        ErrorKind::Busy,
+                        "Timed out waiting for another transaction to finish",
+                    )
+                    .into()
+                })
+        }
+
+        /// rolls back the transaction if it is not used for [Self::transaction_timeout]
+        fn watch_idle(&self, id: TransactionId) {
+            let db = self.clone();
+            let _j = tokio::task::spawn(async move {
+                loop {
+                    let deadline = match db.transaction.lock().await.as_ref() {
+                        Some(t) if t.id == id => t.last_used + db.transaction_timeout,
+                        _ => return,
+                    };
+                    tokio::time::sleep_until(deadline).await;
+
+                    let mut locked = db.transaction.lock().await;
+                    match locked.as_ref() {
+                        Some(t) if t.id == id => {
+                            if t.last_used + db.transaction_timeout > tokio::time::Instant::now() {
+                                continue;
+                            }
+                        }
+                        _ => return,
+                    }
+                    let t = locked.take().expect("checked above");
+                    if let Err(e) = db.finish_rollback(t).await {
+                        eprintln!("could not roll back idle transaction {}: {}", id, e);
+                    } else {
+                        eprintln!("rolled back transaction {} after being idle", id);
+                    }
+                    return;
+                }
+            });
+        }
+
+        async fn take_transaction(
+            &self,
+            id: TransactionId,
+            owner: Option<ConnectionId>,
+        ) -> anyhow::Result<ActiveTransaction> {
+            let mut locked = self.lock_transaction().await?;
+            let Some(mut txn) = locked.take() else {
+                return Err(ErrorMessage::new(ErrorKind::Conflict, "No active transaction").into());
+            };
+            if let Err(e) = txn.access(id, owner) {
+                *locked = Some(txn);
+                return Err(ErrorMessage::new(ErrorKind::Conflict, e).into());
+            }
+            Ok(txn)
+        }
+
+        pub async fn commit(
+            &self,
+            id: TransactionId,
+            owner: Option<ConnectionId>,
+        ) -> anyhow::Result<()> {
+            let txn = self.take_transaction(id, owner).await?;
+            self.finish_commit(txn).await
+        }
+
+        async fn finish_commit(&self, txn: ActiveTransaction) -> anyhow::Result<()> {
+            let res = txn.txn.commit().await;
+
+            let events = std::mem::take(&mut *self.pending_events.lock().expect("poisoned"));
+            if res.is_ok() {
+                for e in events {
+                    // only fails if nobody is subscribed
+                    let _ = self.events.send(e);
+                }
+            }
+
+            self.notif.notify_waiters();
+            res?;
+            Ok(())
+        }
+
+        pub async fn rollback(
+            &self,
+            id: TransactionId,
+            owner: Option<ConnectionId>,
+        ) -> anyhow::Result<()> {
+            let txn = self.take_transaction(id, owner).await?;
+            self.finish_rollback(txn).await
+        }
+
+        /// rolls back the transaction owned by this connection (if any)
+        pub async fn rollback_owned_by(&self, owner: ConnectionId) -> anyhow::Result<()> {
+            let mut locked = self.lock_transaction().await?;
+            if locked.as_ref().map(|t| t.owner) != Some(Some(owner)) {
+                return Ok(());
+            }
+            let txn = locked.take().expect("checked above");
+            self.finish_rollback(txn).await
+        }
+
+        async fn finish_rollback(&self, txn: ActiveTransaction) -> anyhow::Result<()> {
+            let res = txn.txn.rollback().await;
+            self.pending_events.lock().expect("poisoned").clear();
+            self.notif.notify_waiters();
+            res?;
+            Ok(())
+        }
+
+        /// runs f in a transaction of its own. commits if f succeeds.
+        /// holds the transaction slot while f runs, so others wait for it like for any
+        /// other writer (at most [Self::transaction_wait])
+        pub async fn in_transaction<R, F>(&self, f: F) -> anyhow::Result<R>
+        where
+            F: for<'a> FnOnce(
+                &'a sea_orm::DatabaseTransaction,
+            ) -> futures::future::BoxFuture<'a, anyhow::Result<R>>,
+        {
+            let id = self.begin(None).await?;
+            let (res, txn) = {
+                let mut locked = match self.lock_transaction().await {
+                    Ok(locked) => locked,
+                    Err(e) => {
+                        let _ = self.rollback(id, None).await;
+                        return Err(e);
+                    }
+                };
+                let res = match locked.as_mut().map(|t| t.access(id, None)) {
+                    Some(Ok(txn)) => f(txn).await,
+                    Some(Err(e)) => Err(ErrorMessage::new(ErrorKind::Conflict, e).into()),
+                    None => {
+                        Err(ErrorMessage::new(ErrorKind::Conflict, "No active transaction").into())
+                    }
+                };
+                // taken before unlocking so that the idle timeout can't roll it back in between
+                let txn = match locked.take() {
+                    Some(t) if t.id == id => Some(t),
+                    t => {
+                        *locked = t;
+                        None
+                    }
+                };
+                (res, txn)
+            };
+            let Some(txn) = txn else {
+                return Err(res.err().unwrap_or_else(|| {
+                    ErrorMessage::new(ErrorKind::Conflict, "Transaction Inactive").into()
+                }));
+            };
+            match res {
+                Ok(r) => {
+                    self.finish_commit(txn).await?;
+                    Ok(r)
+                }
+                Err(e) => {
+                    let _ = self.finish_rollback(txn).await;
+                    Err(e)
+                }
+            }
+        }
+
+        /// must only be called while holding the active transaction
+        pub fn push_event(&self, event: DbEvent) {
+            self.pending_events.lock().expect("poisoned").push(event);
+        }
+
+        /// for changes made outside of [Db::begin] transactions (after they are committed)
+        pub fn send_event(&self, event: DbEvent) {
+            let _ = self.events.send(event);
+        }
+
+        pub fn subscribe(&self) -> tokio::sync::broadcast::Receiver<DbEvent> {
+            self.events.subscribe()
+        }
+
+        pub async fn init_tables(&self) -> anyhow::Result<()> {
+            let builder = self.db.get_database_backend();
+            let schema = Schema::new(builder);
+
+            let s = builder.build(&schema.create_table_from_entity(object::Entity));
+            let _ = self.db.execute(s).await?;
+            let s = builder.build(
+                &sea_orm::sea_query::Index::create()
+                    .name("id_index")
+                    .table(object::Entity)
+                    .col(object::Column::Id)
+                    .to_owned(),
+            );
+            let _ = self.db.execute(s).await?;
+            for (name, col) in METADATA_INDICES {
+                let s = builder.build(
+                    &sea_orm::sea_query::Index::create()
+                        .name(name)
+                        .table(object::Entity)
+                        .col(object::Column::Typ)
+                        .col(col)
+                        .to_owned(),
+                );
+                let _ = self.db.execute(s).await?;
+            }
+
+            let s = builder.build(&schema.create_table_from_entity(refid::Entity));
+            let _ = self.db.execute(s).await?;
+            let s = builder.build(
+                &sea_orm::sea_query::Index::create()
+                    .name("refid_index")
+                    .table(refid::Entity)
+                    .col(refid::Column::Refid)
+                    .to_owned(),
+            );
+            let _ = self.db.execute(s).await?;
+
+            let s = builder.build(&schema.create_table_from_entity(link::Entity));
+            let _ = self.db.execute(s).await?;
+            let s = builder.build(
+                &sea_orm::sea_query::Index::create()
+                    .name("from_link_index")
+                    .table(link::Entity)
+                    .col(link::Column::FromRefid)
+                    .to_owned(),
+            );
+            let _ = self.db.execute(s).await?;
+            let s = builder.build(
+                &sea_orm::sea_query::Index::create()
+                    .name("to_link_index")
+                    .table(link::Entity)
+                    .col(link::Column::ToRefid)
+                    .to_owned(),
+            );
+            let _ = self.db.execute(s).await?;
+
+            Self::create_search_index(&self.db).await?;
+
+            let s = builder.build(&schema.create_table_from_entity(history::Entity));
+            let _ = self.db.execute(s).await?;
+            let s = builder.build(
+                &sea_orm::sea_query::Index::create()
+                    .name("replaced_ts_index")
+                    .table(history::Entity)
+                    .col(history::Column::ReplacedTs)
+                    .to_owned(),
+            );
+            let _ = self.db.execute(s).await?;
+
+            let s = builder.build(&schema.create_table_from_entity(trash::Entity));
+            let _ = self.db.execute(s).await?;
+            let s = builder.build(
+                &sea_orm::sea_query::Index::create()
+                    .name("trashed_ts_index")
+                    .table(trash::Entity)
+                    .col(trash::Column::TrashedTs)
+                    .to_owned(),
+            );
+            let _ = self.db.execute(s).await?;
+
+            let s = builder.build(&schema.create_table_from_entity(quarantine::Entity));
+            let _ = self.db.execute(s).await?;
+
+            let s = builder.build(&schema.create_table_from_entity(play::Entity));
+            let _ = self.db.execute(s).await?;
+            let s = builder.build(
+                &sea_orm::sea_query::Index::create()
+                    .name("started_ts_index")
+                    .table(play::Entity)
+                    .col(play::Column::StartedTs)
+                    .to_owned(),
+            );
+            let _ = self.db.execute(s).await?;
+            let s = builder.build(
+                &sea_orm::sea_query::Index::create()
+                    .name("song_started_ts_index")
+                    .table(play::Entity)
+                    .col(play::Column::SongId)
+                    .col(play::Column::StartedTs)
+                    .to_owned(),
+            );
+            let _ = self.db.execute(s).await?;
+
+            crate::migrations::init_schema_version(&self.db).await?;
+
+            Ok(())
+        }
+
+        pub async fn create_search_index<C: ConnectionTrait>(conn: &C) -> anyhow::Result<()> {
+            conn.execute_unprepared(&format!(
+                "CREATE VIRTUAL TABLE IF NOT EXISTS {SEARCH_INDEX_TABLE} USING fts5(haystack, tokenize = 'trigram');"
+            ))
+            .await?;
+            Ok(())
+        }
+
+        /// rebuilds everything derived from [`DbAble`] impls (links and search index)
+        /// from the objects. needed whenever haystack / links / json shape of some type changes
+        pub async fn reindex<C: ConnectionTrait>(conn: &C) -> anyhow::Result<()> {
+            let _ = link::Entity::delete_many().exec(conn).await?;
+            let _ = conn
+                .execute_unprepared(&format!("DELETE FROM {SEARCH_INDEX_TABLE};"))
+                .await?;
+
+            use crate::musimanager as mm;
+            type MmSong = mm::Song<Option<mm::SongInfo>, crate::covau_types::SourcePath>;
+
+            Self::reindex_typ::<MmSong, _>(conn).await?;
+            Self::reindex_typ::<mm::Album<crate::yt::VideoId>, _>(conn).await?;
+            Self::reindex_typ::<mm::Artist<crate::yt::VideoId, crate::yt::AlbumId>, _>(conn)
+                .await?;
+            Self::reindex_typ::<mm::Playlist<crate::yt::VideoId>, _>(conn).await?;
+            Self::reindex_typ::<mm::Queue<crate::yt::VideoId>, _>(conn).await?;
+            Self::reindex_typ::<crate::covau_types::LocalState, _>(conn).await?;
+            Self::reindex_typ::<crate::covau_types::Song, _>(conn).await?;
+            Self::reindex_typ::<crate::covau_types::Playlist, _>(conn).await?;
+            Self::reindex_typ::<crate::covau_types::Queue, _>(conn).await?;
+            Self::reindex_typ::<crate::covau_types::ArtistBlacklist, _>(conn).await?;
+            Self::reindex_typ::<crate::covau_types::SongBlacklist, _>(conn).await?;
+            Self::reindex_typ::<crate::covau_types::Updater, _>(conn).await?;
+            Self::reindex_typ::<crate::covau_types::SmartPlaylist, _>(conn).await?;
+            Self::reindex_typ::<crate::yt::song_tube::Song, _>(conn).await?;
+            Self::reindex_typ::<crate::yt::song_tube::Album, _>(conn).await?;
+            Self::reindex_typ::<crate::yt::song_tube::Playlist, _>(conn).await?;
+            Self::reindex_typ::<crate::yt::song_tube::Artist, _>(conn).await?;
+            Self::reindex_typ::<crate::mbz::RecordingWithInfo, _>(conn).await?;
+            Self::reindex_typ::<crate::mbz::Artist, _>(conn).await?;
+
+            Ok(())
+        }
+
+        async fn reindex_typ<T: DbAble, C: ConnectionTrait>(conn: &C) -> anyhow::Result<()> {
+            // can't stream here. conn might be a transaction that we also need to write to
+            // only id and data. migrations run this before the rest of the columns exist
+            let models = object::Entity::find()
+                .select_only()
+                .columns([object::Column::Id, object::Column::Data])
+                .filter(object::Column::Typ.eq(T::typ()))
+                .into_tuple::<(DbId, String)>()
+                .all(conn)
+                .await?;
+
+            let mut links = HashSet::new();
+            for (id, data) in models {
+                let t: T = match serde_json::from_str(&data) {
+                    Ok(t) => t,
+                    Err(error) => {
+                        // not indexed till fsck quarantines it or a migration fixes it
+                        BadRow {
+                            id,
+                            typ: T::typ(),
+                            error,
+                        }
+                        .log();
+                        continue;
+                    }
+                };
+                links.extend(t.links());
+                let haystack = t.haystack().into_iter().collect::<Vec<_>>();
+                index_haystack(conn, id, haystack).await?;
+            }
+
+            let links = links.into_iter().collect::<Vec<_>>();
+            for chunk in links.chunks(BATCH_SIZE) {
+                insert_links(conn, chunk.iter().cloned().collect()).await?;
+            }
+            Ok(())
+        }
+
+        /// newest first
+        pub async fn search_trash<T: DbAble>(&self) -> anyhow::Result<Vec<TrashedItem<T>>> {
+            let items = trash::Entity::find()
+                .filter(trash::Column::Typ.eq(T::typ()))
+                .order_by_desc(trash::Column::TrashedTs)
+                .all(&self.db)
+                .await?
+                .into_iter()
+                .filter_map(|m| {
+                    let item = DbItem {
+                        metadata: bad_row_or(m.id, T::typ(), &m.metadata)?,
+                        t: bad_row_or(m.id, T::typ(), &m.data)?,
+                        id: m.id,
+                        typ: T::typ(),
+                    };
+                    Some(TrashedItem {
+                        item,
+                        trashed_ts: m.trashed_ts as _,
+                    })
+                })
+                .collect();
+            Ok(items)
+        }
+
+        /// moves the item out of the trash with the same id
+        pub async fn restore<T: DbAble, C: ConnectionTrait>(
+            conn: &C,
+            id: DbId,
+        ) -> anyhow::Result<DbItem<T>> {
+            let m = trash::Entity::find_by_id(id)
+                .filter(trash::Column::Typ.eq(T::typ()))
+                .one(conn)
+                .await?
+                .ok_or(ErrorMessage::new(ErrorKind::NotFound, "item not in trash"))?;
+            let t: T = serde_json::from_str(&m.data)?;
+            let metadata: DbMetadata = serde_json::from_str(&m.metadata)?;
+
+            // get_by_refid matches anything if there are no refids
+            if t.refids().into_iter().next().is_some() {
+                if let Some(other) = t.get_by_refid(conn).await? {
+                    return Err(ErrorMessage::new(
+                        ErrorKind::Conflict,
+                        format!("item {} has the same refids as the trashed item", other.id),
+                    )
+                    .into());
+                }
+            }
+
+            insert_object(conn, &t, sea_orm::ActiveValue::Set(id), &metadata).await?;
+            let _ = trash::Entity::delete_by_id(id).exec(conn).await?;
+
+            Ok(DbItem {
+                metadata,
+                t,
+                id,
+                typ: T::typ(),
+            })
+        }
+
+        /// deletes a trashed item for good. fails if anything (even in the trash) still refers to it
+        pub async fn purge<C: ConnectionTrait>(conn: &C, id: DbId) -> anyhow::Result<()> {
+            let referrers = Self::referrers(conn, &HashSet::from([id])).await?;
+            if let Some(referrers) = referrers.get(&id) {
+                return Err(ErrorMessage::new(
+                    ErrorKind::Conflict,
+                    format!("item {} is still referred to by {:?}", id, referrers),
+                )
+                .into());
+            }
+
+            let res = trash::Entity::delete_by_id(id).exec(conn).await?;
+            if res.rows_affected == 0 {
+                return Err(ErrorMessage::new(ErrorKind::NotFound, "item not in trash").into());
+            }
+            // plays stay. listening history outlives the song. ids are never reused
+            let _ = history::Entity::delete_many()
+                .filter(history::Column::ObjectId.eq(id))
+                .exec(conn)
+                .await?;
+            Ok(())
+        }
+
+        /// purges everything trashed before the given timestamp that nothing refers to.
+        /// returns number of items purged
+        pub async fn purge_trash<C: ConnectionTrait>(conn: &C, before: u64) -> anyhow::Result<u32> {
+            let mut expired = trash::Entity::find()
+                .select_only()
+                .column(trash::Column::Id)
+                .filter(trash::Column::TrashedTs.lt(before as i64))
+                .into_tuple::<DbId>()
+                .all(conn)
+                .await?
+                .into_iter()
+                .collect::<HashSet<_>>();
+
+            // an item can only go once everything referring to it is gone.
+            // (LocalState -> Queue -> Song)
+            let mut purged = 0;
+            loop {
+                let referrers = Self::referrers(conn, &expired).await?;
+                let free = expired
+                    .iter()
+                    .copied()
+                    .filter(|id| !referrers.contains_key(id))
+                    .collect::<Vec<_>>();
+                if free.is_empty() {
+                    break;
+                }
+                for chunk in free.chunks(BATCH_SIZE) {
+                    let res = trash::Entity::delete_many()
+                        .filter(trash::Column::Id.is_in(chunk.iter().copied()))
+                        .exec(conn)
+                        .await?;
+                    purged += res.rows_affected as u32;
+                    // plays stay. see [Self::purge]
+                    let _ = history::Entity::delete_many()
+                        .filter(history::Column::ObjectId.is_in(chunk.iter().copied()))
+                        .exec(conn)
+                        .await?;
+                }
+                for id in free {
+                    expired.remove(&id);
+                }
+            }
+            Ok(purged)
+        }
+
+        /// keeps the newest max_revisions revisions of every object that are not older than 'before'.
+        /// returns number of revisions purged
+        pub async fn purge_history<C: ConnectionTrait>(
+            conn: &C,
+            before: u64,
+            max_revisions: u32,
+        ) -> anyhow::Result<u32> {
+            let res = history::Entity::delete_many()
+                .filter(history::Column::ReplacedTs.lt(before as i64))
+                .exec(conn)
+                .await?;
+            let mut purged = res.rows_affected as u32;
+
+            let stmt = sea_orm::Statement::from_sql_and_values(
+                conn.get_database_backend(),
+                "
+                DELETE FROM covau_history WHERE rowid IN (
+                    SELECT rowid FROM (
+                        SELECT rowid, ROW_NUMBER() OVER (
+                            PARTITION BY object_id ORDER BY update_counter DESC
+                        ) AS n FROM covau_history
+                    ) WHERE n > ?
+                );
+                ",
+                [max_revisions.into()],
+            );
+            let res = conn.execute(stmt).await?;
+            purged += res.rows_affected() as u32;
+
+            Ok(purged)
+        }
+
+        /// purges expired trash and history every hour
+        pub async fn purge_loop(self, retention: Retention) {
             loop {
-                let mut locked = self.transaction.lock().await;
-                if locked.is_none() {
-                    let txn = self.db.begin().await?;
-                    *locked = Some((id, txn));
-                    break;
+                match self.purge_once(&retention).await {
+                    Ok((0, 0)) => (),
+                    Ok((trash, history)) => println!(
+                        "purged {} items from trash and {} revisions from history",
+                        trash, history
+                    ),
+                    Err(e) => eprintln!("could not purge trash and history: {}", e),
                 }
-                drop(locked);
-                self.notif.notified().await;
+                tokio::time::sleep(Duration::from_secs(60 * 60)).await;
             }
-            Ok(id)
         }
 
-        pub async fn commit(&self, id: TransactionId) -> anyhow::Result<()> {
-            let mut locked = self.transaction.lock().await;
-            let Some(txn) = locked.take() else {
-                return Err(anyhow::anyhow!("No active transaction"));
-            };
-            if txn.0 != id {
-                *locked = Some(txn);
-                return Err(anyhow::anyhow!("Wrong transaction Id"));
-            }
-            txn.1.commit().await?;
+        async fn purge_once(&self, retention: &Retention) -> anyhow::Result<(u32, u32)> {
+            let now = Db::timestamp();
+            let trash = now.saturating_sub(retention.trash.as_secs());
+            let history = now.saturating_sub(retention.history.as_secs());
+            let max_revisions = retention.max_revisions;
+            self.in_transaction(|txn| {
+                Box::pin(async move {
+                    let trash = Self::purge_trash(txn, trash).await?;
+                    let history = Self::purge_history(txn, history, max_revisions).await?;
+                    Ok((trash, history))
+                })
+            })
+            .await
+        }
 
-            self.notif.notify_waiters();
-            Ok(())
+        /// newest first. the current version is not included
+        pub async fn revisions<T: DbAble>(&self, id: DbId) -> anyhow::Result<Vec<Revision<T>>> {
+            let items = history::Entity::find()
+                .filter(history::Column::ObjectId.eq(id))
+                .filter(history::Column::Typ.eq(T::typ()))
+                .order_by_desc(history::Column::UpdateCounter)
+                .all(&self.db)
+                .await?
+                .into_iter()
+                .filter_map(|m| {
+                    let item = DbItem {
+                        metadata: bad_row_or(m.object_id, T::typ(), &m.metadata)?,
+                        t: bad_row_or(m.object_id, T::typ(), &m.data)?,
+                        id: m.object_id,
+                        typ: T::typ(),
+                    };
+                    Some(Revision {
+                        item,
+                        replaced_ts: m.replaced_ts as _,
+                    })
+                })
+                .collect();
+            Ok(items)
         }
 
-        pub async fn rollback(&self, id: TransactionId) -> anyhow::Result<()> {
-            let mut locked = self.transaction.lock().await;
-            let Some(txn) = locked.take() else {
-                return Err(anyhow::anyhow!("No active transaction"));
+        /// changes in data going from revision 'from' to revision 'to' (None for the current version)
+        pub async fn diff_revisions(
+            &self,
+            id: DbId,
+            from: u32,
+            to: Option<u32>,
+        ) -> anyhow::Result<Vec<JsonChange>> {
+            let old = self.revision_data(id, from).await?;
+            let new = match to {
+                Some(to) => self.revision_data(id, to).await?,
+                None => {
+                    object::Entity::find_by_id(id)
+                        .one(&self.db)
+                        .await?
+                        .ok_or(ErrorMessage::new(ErrorKind::NotFound, "item not found"))?
+                        .data
+                }
             };
-            if txn.0 != id {
-                *locked = Some(txn);
-                return Err(anyhow::anyhow!("Wrong transaction Id"));
-            }
-            txn.1.rollback().await?;
+            let old: serde_json::Value = serde_json::from_str(&old)?;
+            let new: serde_json::Value = serde_json::from_str(&new)?;
 
-            self.notif.notify_waiters();
-            Ok(())
+            let mut changes = vec![];
+            json_diff(String::new(), Some(&old), Some(&new), &mut changes);
+            Ok(changes)
         }
 
-        pub async fn init_tables(&self) -> anyhow::Result<()> {
-            let builder = self.db.get_database_backend();
-            let schema = Schema::new(builder);
+        async fn revision_data(&self, id: DbId, update_counter: u32) -> anyhow::Result<String> {
+            let m = history::Entity::find_by_id((id, update_counter as i64))
+                .one(&self.db)
+                .await?
+                .ok_or_else(|| {
+                    ErrorMessage::new(
+                        ErrorKind::NotFound,
+                        format!("revision {} not found", update_counter),
+                    )
+                })?;
+            Ok(m.data)
+        }
 
-            let s = builder.build(&schema.create_table_from_entity(object::Entity));
-            let _ = self.db.execute(s).await?;
-            let s = builder.build(
-                &sea_orm::sea_query::Index::create()
-                    .name("id_index")
-                    .table(object::Entity)
-                    .col(object::Column::Id)
-                    .to_owned(),
-            );
-            let _ = self.db.execute(s).await?;
+        /// updates the item with the data of an older revision. metadata is not touched.
+        /// the version being replaced goes into history too. so this can be reverted as well
+        pub async fn revert<T: DbAble, C: ConnectionTrait>(
+            conn: &C,
+            id: DbId,
+            update_counter: u32,
+        ) -> anyhow::Result<DbItem<T>> {
+            let rev = history::Entity::find_by_id((id, update_counter as i64))
+                .filter(history::Column::Typ.eq(T::typ()))
+                .one(conn)
+                .await?
+                .ok_or_else(|| {
+                    ErrorMessage::new(
+                        ErrorKind::NotFound,
+                        format!("revision {} not found", update_counter),
+                    )
+                })?;
+            let current = T::get(conn, id)
+                .await?
+                .ok_or(ErrorMessage::new(ErrorKind::NotFound, "item not found"))?;
 
-            let s = builder.build(&schema.create_table_from_entity(refid::Entity));
-            let _ = self.db.execute(s).await?;
-            let s = builder.build(
-                &sea_orm::sea_query::Index::create()
-                    .name("refid_index")
-                    .table(refid::Entity)
-                    .col(refid::Column::Refid)
-                    .to_owned(),
-            );
-            let _ = self.db.execute(s).await?;
+            let item = DbItem {
+                t: serde_json::from_str(&rev.data)?,
+                ..current
+            };
+            let metadata = item.update(conn).await?;
+            Ok(DbItem { metadata, ..item })
+        }
 
-            let s = builder.build(&schema.create_table_from_entity(link::Entity));
-            let _ = self.db.execute(s).await?;
-            let s = builder.build(
-                &sea_orm::sea_query::Index::create()
-                    .name("from_link_index")
-                    .table(link::Entity)
-                    .col(link::Column::FromRefid)
-                    .to_owned(),
-            );
-            let _ = self.db.execute(s).await?;
-            let s = builder.build(
-                &sea_orm::sea_query::Index::create()
-                    .name("to_link_index")
-                    .table(link::Entity)
-                    .col(link::Column::ToRefid)
-                    .to_owned(),
-            );
-            let _ = self.db.execute(s).await?;
+        /// referred id -> ids of objects (live or trashed) that refer to it
+        async fn referrers<C: ConnectionTrait>(
+            conn: &C,
+            ids: &HashSet<DbId>,
+        ) -> anyhow::Result<HashMap<DbId, Vec<DbId>>> {
+            let mut referrers = HashMap::new();
+            if ids.is_empty() {
+                return Ok(referrers);
+            }
+
+            // only these refer to other objects using [DbId]s
+            Self::referrers_typ::<crate::covau_types::LocalState, _>(conn, ids, &mut referrers)
+                .await?;
+            Self::referrers_typ::<crate::covau_types::Playlist, _>(conn, ids, &mut referrers)
+                .await?;
+            Self::referrers_typ::<crate::covau_types::Queue, _>(conn, ids, &mut referrers).await?;
+            Self::referrers_typ::<crate::covau_types::SmartPlaylist, _>(conn, ids, &mut referrers)
+                .await?;
+
+            Ok(referrers)
+        }
+
+        async fn referrers_typ<T: DbAble, C: ConnectionTrait>(
+            conn: &C,
+            ids: &HashSet<DbId>,
+            referrers: &mut HashMap<DbId, Vec<DbId>>,
+        ) -> anyhow::Result<()> {
+            let live = object::Entity::find()
+                .select_only()
+                .columns([object::Column::Id, object::Column::Data])
+                .filter(object::Column::Typ.eq(T::typ()))
+                .into_tuple::<(DbId, String)>()
+                .all(conn)
+                .await?;
+            let trashed = trash::Entity::find()
+                .select_only()
+                .columns([trash::Column::Id, trash::Column::Data])
+                .filter(trash::Column::Typ.eq(T::typ()))
+                .into_tuple::<(DbId, String)>()
+                .all(conn)
+                .await?;
 
+            for (id, data) in live.into_iter().chain(trashed) {
+                let Some(t) = bad_row_or::<T>(id, T::typ(), &data) else {
+                    continue;
+                };
+                for rid in t.referenced_ids() {
+                    if ids.contains(&rid) && rid != id {
+                        referrers.entry(rid).or_default().push(id);
+                    }
+                }
+            }
             Ok(())
         }
 
@@ -1268,6 +3022,24 @@ pub mod db {
             Ok(a)
         }
 
+        /// models that might match the needle. everything if the search index can't help
+        async fn stream_search_candidates<T: DbAble>(
+            &self,
+            needle: &str,
+        ) -> anyhow::Result<impl futures::Stream<Item = Result<object::Model, DbErr>> + '_>
+        {
+            let mut q = object::Entity::find()
+                .filter(object::Column::Typ.eq(T::typ().to_value().to_string()));
+            if let Some(query) = search_index_query(needle) {
+                q = q.filter(sea_orm::sea_query::Expr::cust_with_values(
+                    format!("id IN (SELECT rowid FROM {SEARCH_INDEX_TABLE} WHERE {SEARCH_INDEX_TABLE} MATCH ?)"),
+                    [query],
+                ));
+            }
+            let a = q.stream(&self.db).await?;
+            Ok(a)
+        }
+
         pub async fn search<T: DbAble>(
             &self,
             query: SearchQuery,
@@ -1276,58 +3048,76 @@ pub mod db {
                 SearchQuery::Query { page_size, query } => (query, page_size, None),
                 SearchQuery::Continuation(c) => {
                     if c.typ != T::typ() {
-                        return Err(anyhow::anyhow!("continuation typ vs search typ mismatch"));
+                        return Err(ErrorMessage::new(
+                            ErrorKind::Invalid,
+                            "continuation typ vs search typ mismatch",
+                        )
+                        .into());
                     }
                     (c.query, c.page_size, Some(c.cont))
                 }
             };
+            let page_size = check_page_size(page_size)?;
+            let cont = cont.as_deref().map(parse_search_cursor).transpose()?;
 
-            let cont = cont
-                .as_deref()
-                .map(|c| c.split_once('|'))
-                .flatten()
-                .map(|(s, id)| -> anyhow::Result<(_, _)> {
-                    let (s, id) = (s.parse::<isize>()?, id.parse::<i32>()?);
-                    Ok((s, id))
-                })
-                .transpose()?;
+            let matches = self.search_scored::<T>(&needle, page_size, cont).await?;
+
+            let cont = matches
+                .last()
+                .map(|(s, item)| search_cursor(*s, item.id))
+                .map(|cont| SearchContinuation {
+                    typ: T::typ(),
+                    page_size,
+                    query: needle,
+                    cont,
+                });
+            let full = matches.len() == page_size as usize;
+
+            Ok(SearchMatches {
+                items: matches.into_iter().map(|(_, item)| item).collect(),
+                continuation: cont.filter(|_| full),
+            })
+        }
 
+        /// the best page_size matches that rank below the cursor. best first
+        pub(crate) async fn search_scored<T: DbAble>(
+            &self,
+            needle: &str,
+            page_size: u32,
+            cursor: Option<(isize, DbId)>,
+        ) -> anyhow::Result<Vec<(isize, DbItem<T>)>> {
+            let terms = crate::search::SearchTerms::parse(needle)?;
             let mut tree = intrusive_collections::RBTree::new(BAdapter::<T>::new());
             let cap = page_size;
 
-            let mut it = self.stream_models::<T>().await?;
+            let mut it = self
+                .stream_search_candidates::<T>(&terms.index_needle())
+                .await?;
 
             let mut len = 0;
             while let Some(m) = it.next().await {
                 let m = m?;
-                let t: T = m.parsed_assume();
-                let score: isize = t
-                    .haystack()
-                    .into_iter()
-                    .map(|h| {
-                        let s = sublime_fuzzy::best_match(needle.as_str(), &h)
-                            .map(|m| m.score())
-                            .unwrap_or(0);
-                        s
-                    })
-                    .sum();
-
-                if score <= 0 && !needle.is_empty() {
+                let Some(t) = m.parsed_or_skip::<T>() else {
                     continue;
-                }
+                };
+                let metadata = m.db_metadata();
+                let Some(score) = terms.score(&t, &metadata) else {
+                    continue;
+                };
+                let score = score + self.search_boost.score(&metadata);
 
                 // check if this element should be in the tree
                 // eject any elements if needed
                 // insert a Node<T> into tree
-                if cont.map(|c| c < (score, m.id)).unwrap_or(false) {
-                    // ignore
+                if cursor.map(|c| c <= (score, m.id)).unwrap_or(false) {
+                    // already sent in an earlier page
                 } else if len < cap {
                     tree.insert(Box::new(Node {
                         link: Default::default(),
                         val: DbItem {
                             id: m.id,
                             typ: T::typ(),
-                            metadata: m.parse_assume_metadata(),
+                            metadata,
                             t,
                         },
                         score,
@@ -1343,7 +3133,7 @@ pub mod db {
                             val: DbItem {
                                 id: m.id,
                                 typ: T::typ(),
-                                metadata: m.parse_assume_metadata(),
+                                metadata,
                                 t,
                             },
                             score,
@@ -1352,25 +3142,101 @@ pub mod db {
                 }
             }
 
-            let cont = tree
-                .front()
-                .get()
-                .map(|n| (n.score, n.val.id))
-                .map(|(s, id)| s.to_string() + "|" + &id.to_string())
-                .map(|cont| SearchContinuation {
-                    typ: T::typ(),
-                    page_size,
-                    query: needle,
-                    cont,
-                });
-            let matches = tree.into_iter().map(|n| n.val).rev().collect();
+            let matches = tree.into_iter().map(|n| (n.score, n.val)).rev().collect();
+            Ok(matches)
+        }
 
-            Ok(SearchMatches {
-                items: matches,
-                continuation: (len == cap).then(|| cont).flatten(),
+        pub async fn query<T: DbAble>(&self, query: DbQuery) -> anyhow::Result<QueryMatches<T>> {
+            let query = DbQuery {
+                page_size: check_page_size(query.page_size)?,
+                ..query
+            };
+            let mut q = object::Entity::find().filter(object::Column::Typ.eq(T::typ()));
+
+            for f in query.filters.iter() {
+                q = match *f {
+                    MetadataFilter::Done(done) => q.filter(object::Column::Done.eq(done)),
+                    MetadataFilter::Cmp { field, op, value } => {
+                        let col = metadata_column(field);
+                        let value = value as i64;
+                        q.filter(match op {
+                            FilterOp::Eq => col.eq(value),
+                            FilterOp::Gt => col.gt(value),
+                            FilterOp::Gte => col.gte(value),
+                            FilterOp::Lt => col.lt(value),
+                            FilterOp::Lte => col.lte(value),
+                        })
+                    }
+                };
+            }
+
+            for word in query.text.as_deref().unwrap_or("").split_whitespace() {
+                q = q.filter(haystack_contains(word));
+            }
+
+            let order = if query.desc {
+                sea_orm::Order::Desc
+            } else {
+                sea_orm::Order::Asc
+            };
+            let col = query.sort_by.map(metadata_column);
+
+            // keyset pagination on (sort value, id). stable even if items get inserted between pages
+            if let Some(cursor) = query.cursor.as_deref() {
+                let invalid = || ErrorMessage::new(ErrorKind::Invalid, "invalid query cursor");
+                let (value, id) = cursor.split_once('|').ok_or_else(invalid)?;
+                let value = value.parse::<i64>().map_err(|_| invalid())?;
+                let id = id.parse::<DbId>().map_err(|_| invalid())?;
+                let after = match (col, query.desc) {
+                    (Some(col), false) => Condition::any()
+                        .add(col.gt(value))
+                        .add(col.eq(value).and(object::Column::Id.gt(id))),
+                    (Some(col), true) => Condition::any()
+                        .add(col.lt(value))
+                        .add(col.eq(value).and(object::Column::Id.lt(id))),
+                    (None, false) => Condition::all().add(object::Column::Id.gt(id)),
+                    (None, true) => Condition::all().add(object::Column::Id.lt(id)),
+                };
+                q = q.filter(after);
+            }
+
+            if let Some(col) = col {
+                q = q.order_by(col, order.clone());
+            }
+            let models = q
+                .order_by(object::Column::Id, order)
+                .limit(query.page_size as u64)
+                .all(&self.db)
+                .await?;
+
+            let cursor = models.last().map(|m| {
+                let value = query
+                    .sort_by
+                    .map(|f| metadata_value(m, f))
+                    .unwrap_or(m.id as _);
+                format!("{}|{}", value, m.id)
+            });
+            let full = models.len() == query.page_size as usize;
+            let items = models
+                .into_iter()
+                .filter_map(|e| {
+                    Some(DbItem {
+                        metadata: e.db_metadata(),
+                        t: e.parsed_or_skip()?,
+                        id: e.id,
+                        typ: T::typ(),
+                    })
+                })
+                .collect();
+
+            Ok(QueryMatches {
+                items,
+                continuation: full.then_some(DbQuery { cursor, ..query }),
             })
         }
 
+        /// items of typ `To` that items of typ `From` (with these refids) link to
+        /// linked items that are not in the db are skipped
         pub async fn search_linked_from<From, To>(
             &self,
             from: Vec<String>,
@@ -1379,25 +3245,17 @@ pub mod db {
             From: DbAble + Linked<To>,
             To: DbAble,
         {
-            let mut condition = Condition::any();
-            for id in from {
-                condition = condition.add(link::Column::FromRefid.eq(id));
-            }
-            let links = link::Entity::find()
-                .filter(link::Column::FromTyp.eq(From::typ()))
-                .filter(link::Column::ToTyp.eq(To::typ()))
-                .filter(condition)
-                .all(&self.db)
-                .await?
-                .into_iter()
-                .collect::<Vec<_>>();
-            let items = links.into_iter().map(|e| e.to_refid).collect();
-
-            let items = self.search_many_by_ref_id(items).await?;
-
-            Ok(items)
+            let links = sea_orm::sea_query::Query::select()
+                .column(link::Column::ToRefid)
+                .from(link::Entity)
+                .and_where(link::Column::FromTyp.eq(From::typ()))
+                .and_where(link::Column::ToTyp.eq(To::typ()))
+                .and_where(link::Column::FromRefid.is_in(from))
+                .to_owned();
+            self.search_by_ref_id_subquery(links).await
         }
 
+        /// items of typ `From` that link to items of typ `To` (with these refids)
         pub async fn search_linked_to<From, To>(
             &self,
             to: Vec<String>,
@@ -1406,22 +3264,40 @@ pub mod db {
             From: DbAble + Linked<To>,
             To: DbAble,
         {
-            let mut condition = Condition::any();
-            for id in to {
-                condition = condition.add(link::Column::ToRefid.eq(id));
-            }
-            let links = link::Entity::find()
-                .filter(link::Column::FromTyp.eq(From::typ()))
-                .filter(link::Column::ToTyp.eq(To::typ()))
-                .filter(condition)
+            let links = sea_orm::sea_query::Query::select()
+                .column(link::Column::FromRefid)
+                .from(link::Entity)
+                .and_where(link::Column::FromTyp.eq(From::typ()))
+                .and_where(link::Column::ToTyp.eq(To::typ()))
+                .and_where(link::Column::ToRefid.is_in(to))
+                .to_owned();
+            self.search_by_ref_id_subquery(links).await
+        }
+
+        async fn search_by_ref_id_subquery<T: DbAble>(
+            &self,
+            refids: sea_orm::sea_query::SelectStatement,
+        ) -> anyhow::Result<Vec<DbItem<T>>> {
+            let mut seen = HashSet::new();
+            let items = refid::Entity::find()
+                .filter(refid::Column::Typ.eq(T::typ()))
+                .filter(refid::Column::Refid.in_subquery(refids))
+                .find_also_related(object::Entity)
                 .all(&self.db)
                 .await?
                 .into_iter()
-                .collect::<Vec<_>>();
-            let items = links.into_iter().map(|e| e.from_refid).collect();
-
-            let items = self.search_many_by_ref_id(items).await?;
-
+                .filter_map(|(_refid, obj)| obj)
+                // an object can be reachable using multiple of it's refids
+                .filter(|e| seen.insert(e.id))
+                .filter_map(|e| {
+                    Some(DbItem {
+                        metadata: e.db_metadata(),
+                        t: e.parsed_or_skip()?,
+                        id: e.id,
+                        typ: T::typ(),
+                    })
+                })
+                .collect();
             Ok(items)
         }
 
@@ -1434,7 +3310,7 @@ pub mod db {
                 .one(&self.db)
                 .await?
                 .map(|e| DbItem {
-                    metadata: e.parse_assume_metadata(),
+                    metadata: e.db_metadata(),
                     t: e.data,
                     id: e.id,
                     typ: e.typ,
@@ -1442,27 +3318,29 @@ pub mod db {
             Ok(e)
         }
 
+        /// same order as ids. None for ids that are not in the db
         pub async fn search_many_untyped_by_id(
             &self,
             ids: Vec<DbId>,
-        ) -> anyhow::Result<Vec<DbItem<String>>> {
-            let mut condition = Condition::any();
-            for id in ids {
-                condition = condition.add(object::Column::Id.eq(id));
+        ) -> anyhow::Result<Vec<Option<DbItem<String>>>> {
+            let mut found = HashMap::new();
+            for chunk in ids.chunks(BATCH_SIZE) {
+                let models = object::Entity::find()
+                    .filter(object::Column::Id.is_in(chunk.iter().copied()))
+                    .all(&self.db)
+                    .await?;
+                for e in models {
+                    let item = DbItem {
+                        metadata: e.db_metadata(),
+                        t: e.data,
+                        id: e.id,
+                        typ: e.typ,
+                    };
+                    found.insert(item.id, item);
+                }
             }
-            let e = object::Entity::find()
-                .filter(condition)
-                .all(&self.db)
-                .await?
-                .into_iter()
-                .map(|e| DbItem {
-                    metadata: e.parse_assume_metadata(),
-                    t: e.data,
-                    id: e.id,
-                    typ: e.typ,
-                })
-                .collect();
-            Ok(e)
+            let items = ids.iter().map(|id| found.get(id).cloned()).collect();
+            Ok(items)
         }
 
         pub async fn search_by_id<T: DbAble>(&self, id: DbId) -> anyhow::Result<Option<DbItem<T>>> {
@@ -1471,44 +3349,47 @@ pub mod db {
                 .filter(object::Column::Id.eq(id))
                 .one(&self.db)
                 .await?
-                .map(|e| DbItem {
-                    metadata: e.parse_assume_metadata(),
-                    t: e.parsed_assume(),
-                    id: e.id,
-                    typ: T::typ(),
-                });
+                .map(|e| -> anyhow::Result<_> {
+                    Ok(DbItem {
+                        metadata: e.db_metadata(),
+                        t: e.try_parsed()?,
+                        id: e.id,
+                        typ: T::typ(),
+                    })
+                })
+                .transpose()?;
             Ok(e)
         }
 
+        /// same order as ids. None for ids that are not in the db (or are of some other typ)
         pub async fn search_many_by_id<T: DbAble>(
             &self,
             ids: Vec<DbId>,
-        ) -> anyhow::Result<Vec<DbItem<T>>> {
-            // let mut condition = Condition::any();
-            // for id in ids {
-            //     condition = condition.add(object::Column::Id.eq(id));
-            // }
-            // let e = object::Entity::find()
-            //     .filter(object::Column::Typ.eq(T::typ()))
-            //     .filter(condition)
-            //     .all(&self.db)
-            //     .await?
-            //     .into_iter()
-            //     .map(|e| DbItem {
-            //         metadata: e.parse_assume_metadata(),
-            //         t: e.parsed_assume(),
-            //         id: e.id,
-            //         typ: T::typ(),
-            //     })
-            //     .collect();
-            // Ok(e)
-
-            let mut items = vec![];
-            for id in ids {
-                let item = self.search_by_id::<T>(id).await?;
-                let item = item.context("item with refid not found")?;
-                items.push(item);
+        ) -> anyhow::Result<Vec<Option<DbItem<T>>>> {
+            // building a Condition::any() with ~1000 items crashes. is_in builds a flat list.
+            // chunked to stay well under sqlite's bound variable limit
+            let mut found = HashMap::new();
+            for chunk in ids.chunks(BATCH_SIZE) {
+                let models = object::Entity::find()
+                    .filter(object::Column::Typ.eq(T::typ()))
+                    .filter(object::Column::Id.is_in(chunk.iter().copied()))
+                    .all(&self.db)
+                    .await?;
+                for e in models {
+                    let Some(t) = e.parsed_or_skip() else {
+                        continue;
+                    };
+                    let item = DbItem {
+                        metadata: e.db_metadata(),
+                        t,
+                        id: e.id,
+                        typ: T::typ(),
+                    };
+                    found.insert(item.id, item);
+                }
             }
+            // same id can be requested multiple times (songs in a queue)
+            let items = ids.iter().map(|id| found.get(id).cloned()).collect();
             Ok(items)
         }
 
@@ -1524,53 +3405,60 @@ pub mod db {
                 .await?
                 .map(|(_refid, obj)| obj)
                 .flatten()
-                .map(|e| DbItem {
-                    metadata: e.parse_assume_metadata(),
-                    t: e.parsed_assume(),
-                    id: e.id,
-                    typ: T::typ(),
-                });
+                .map(|e| -> anyhow::Result<_> {
+                    Ok(DbItem {
+                        metadata: e.db_metadata(),
+                        t: e.try_parsed()?,
+                        id: e.id,
+                        typ: T::typ(),
+                    })
+                })
+                .transpose()?;
             Ok(e)
         }
 
+        /// same order as ref_ids. None for refids that are not in the db
         pub async fn search_many_by_ref_id<T: DbAble>(
             &self,
             ref_ids: Vec<String>,
-        ) -> anyhow::Result<Vec<DbItem<T>>> {
-            // TODO: crash on building query if this condition has a lot of items (1000 ish)
-            // - maybe create a temporary table for refids and join on it
-            // - maybe just use a rust hashmap (slow tho :/)
-            // let mut condition = Condition::any();
-            // for id in ref_ids {
-            //     condition = condition.add(refid::Column::Refid.eq(id));
-            // }
-            // let e = refid::Entity::find()
-            //     .filter(refid::Column::Typ.eq(T::typ()))
-            //     .filter(condition)
-            //     .find_also_related(object::Entity)
-            //     .all(&self.db)
-            //     .await?
-            //     .into_iter()
-            //     .map(|(_refid, obj)| obj)
-            //     .flatten()
-            //     .map(|e| DbItem {
-            //         metadata: e.parse_assume_metadata(),
-            //         t: e.parsed_assume(),
-            //         id: e.id,
-            //         typ: T::typ(),
-            //     })
-            //     .collect();
-            // Ok(e)
-
-            let mut items = vec![];
-            for refid in ref_ids {
-                let item = self.search_by_ref_id::<T>(refid).await?;
-                let item = item.context("item with refid not found")?;
-                items.push(item);
-            }
+        ) -> anyhow::Result<Vec<Option<DbItem<T>>>> {
+            let found = Self::get_many_by_ref_id::<T, _>(&self.db, &ref_ids).await?;
+            let items = ref_ids.iter().map(|id| found.get(id).cloned()).collect();
             Ok(items)
         }
 
+        /// refid -> item. refids that are not in the db are missing
+        pub async fn get_many_by_ref_id<T: DbAble, C: ConnectionTrait>(
+            conn: &C,
+            ref_ids: &[String],
+        ) -> anyhow::Result<HashMap<String, DbItem<T>>> {
+            let mut found = HashMap::new();
+            for chunk in ref_ids.chunks(BATCH_SIZE) {
+                let models = refid::Entity::find()
+                    .filter(refid::Column::Typ.eq(T::typ()))
+                    .filter(refid::Column::Refid.is_in(chunk.iter().cloned()))
+                    .find_also_related(object::Entity)
+                    .all(conn)
+                    .await?;
+                for (refid, e) in models {
+                    let Some(e) = e else {
+                        continue;
+                    };
+                    let Some(t) = e.parsed_or_skip() else {
+                        continue;
+                    };
+                    let item = DbItem {
+                        metadata: e.db_metadata(),
+                        t,
+                        id: e.id,
+                        typ: T::typ(),
+                    };
+                    found.insert(refid.refid, item);
+                }
+            }
+            Ok(found)
+        }
+
         pub fn timestamp() -> u64 {
             let secs = std::time::SystemTime::now()
                 .duration_since(std::time::UNIX_EPOCH)
@@ -1580,6 +3468,50 @@ pub mod db {
         }
     }
 
+    pub(crate) fn metadata_column(field: MetadataField) -> object::Column {
+        match field {
+            MetadataField::Likes => object::Column::Likes,
+            MetadataField::Dislikes => object::Column::Dislikes,
+            MetadataField::Interactions => object::Column::Interactions,
+            MetadataField::AddedTs => object::Column::AddedTs,
+            MetadataField::UpdatedTs => object::Column::UpdatedTs,
+        }
+    }
+
+    pub(crate) fn metadata_value(m: &object::Model, field: MetadataField) -> i64 {
+        match field {
+            MetadataField::Likes => m.likes,
+            MetadataField::Dislikes => m.dislikes,
+            MetadataField::Interactions => m.interactions,
+            MetadataField::AddedTs => m.added_ts,
+            MetadataField::UpdatedTs => m.updated_ts,
+        }
+    }
+
+    /// largest page a search or query returns. bigger requests get this many
+    const MAX_PAGE_SIZE: u32 = 500;
+
+    /// an empty page would never get past the cursor
+    pub(crate) fn check_page_size(page_size: u32) -> anyhow::Result<u32> {
+        if page_size == 0 {
+            return Err(ErrorMessage::new(ErrorKind::Invalid, "page_size must not be 0").into());
+        }
+        Ok(page_size.min(MAX_PAGE_SIZE))
+    }
+
+    pub(crate) fn search_cursor(score: isize, id: DbId) -> String {
+        score.to_string() + "|" + &id.to_string()
+    }
+
+    pub(crate) fn parse_search_cursor(cont: &str) -> anyhow::Result<(isize, DbId)> {
+        let invalid = || ErrorMessage::new(ErrorKind::Invalid, "invalid search cursor");
+        let (s, id) = cont.split_once('|').ok_or_else(invalid)?;
+        Ok((
+            s.parse().map_err(|_| invalid())?,
+            id.parse().map_err(|_| invalid())?,
+        ))
+    }
+
     struct Node<T> {
         link: RBTreeLink,
         val: DbItem<T>,
@@ -1614,6 +3546,38 @@ pub mod db {
         types += ";\n";
         types += &specta::ts::export::<SearchContinuation>(config)?;
         types += ";\n";
+        types += &specta::ts::export::<UniversalMatches<()>>(config)?;
+        types += ";\n";
+        types += &specta::ts::export::<TypMatches<()>>(config)?;
+        types += ";\n";
+        types += &specta::ts::export::<ScoredItem<()>>(config)?;
+        types += ";\n";
+        types += &specta::ts::export::<UniversalContinuation>(config)?;
+        types += ";\n";
+        types += &specta::ts::export::<UniversalQuery>(config)?;
+        types += ";\n";
+        types += &specta::ts::export::<MetadataField>(config)?;
+        types += ";\n";
+        types += &specta::ts::export::<FilterOp>(config)?;
+        types += ";\n";
+        types += &specta::ts::export::<MetadataFilter>(config)?;
+        types += ";\n";
+        types += &specta::ts::export::<DbQuery>(config)?;
+        types += ";\n";
+        types += &specta::ts::export::<QueryMatches<()>>(config)?;
+        types += ";\n";
+        types += &specta::ts::export::<TrashedItem<()>>(config)?;
+        types += ";\n";
+        types += &specta::ts::export::<Revision<()>>(config)?;
+        types += ";\n";
+        types += &specta::ts::export::<JsonChange>(config)?;
+        types += ";\n";
+        types += &specta::ts::export::<DuplicateGroup>(config)?;
+        types += ";\n";
+        types += &specta::ts::export::<DbEvent>(config)?;
+        types += ";\n";
+        types += &specta::ts::export::<DbSubscription>(config)?;
+        types += ";\n";
 
         Ok(types)
     }
@@ -0,0 +1,381 @@
+use std::collections::{HashMap, HashSet};
+
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, DatabaseTransaction, EntityTrait, QueryFilter, QueryOrder,
+    TransactionTrait,
+};
+
+use crate::covau_types::{InfoSource, PlaySource, Playlist, Queue, Song, SourcePath};
+use crate::db::{object, Db, DbAble, DbEvent, DbId, DbItem, DbMetadata, DuplicateGroup, Typ};
+use crate::musimanager as mm;
+
+type MmSong = mm::Song<Option<mm::SongInfo>, SourcePath>;
+
+// same title + artist is only a duplicate if the durations (when known) are this close
+const DURATION_TOLERANCE_SECS: f32 = 3.0;
+
+struct Candidate {
+    id: DbId,
+    typ: Typ,
+    metadata: DbMetadata,
+    refids: Vec<String>,
+    // normalized title + artist
+    key: Option<String>,
+    duration: Option<f32>,
+}
+
+/// groups of [Song]s (and [MmSong]s) that share a refid, or have the same normalized
+/// title + artist and a similar duration. groups without any [Song] are not reported
+pub async fn find_duplicates(db: &Db) -> anyhow::Result<Vec<DuplicateGroup>> {
+    let candidates = candidates(&db.db).await?;
+
+    let mut groups = UnionFind::new(candidates.iter().map(|c| c.duration).collect());
+    let mut by_refid = HashMap::new();
+    let mut by_key: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, c) in candidates.iter().enumerate() {
+        for r in c.refids.iter() {
+            let first = *by_refid.entry(r.as_str()).or_insert(i);
+            groups.union(first, i);
+        }
+
+        let Some(key) = c.key.as_deref() else {
+            continue;
+        };
+        // compared per group. else songs with unknown duration bridge very different ones
+        let same = by_key.entry(key).or_default();
+        let similar =
+            same.iter()
+                .copied()
+                .find(|&j| match (groups.duration(i), groups.duration(j)) {
+                    (Some(a), Some(b)) => (a - b).abs() <= DURATION_TOLERANCE_SECS,
+                    _ => true,
+                });
+        if let Some(j) = similar {
+            groups.union(j, i);
+        }
+        same.push(i);
+    }
+
+    let mut members: HashMap<usize, Vec<&Candidate>> = HashMap::new();
+    for (i, c) in candidates.iter().enumerate() {
+        members.entry(groups.find(i)).or_default().push(c);
+    }
+
+    let mut dups = members
+        .into_values()
+        .filter(|m| m.len() > 1)
+        .filter_map(|m| {
+            // most used song survives. oldest one if it's a tie
+            let survivor = m
+                .iter()
+                .filter(|c| c.typ == Typ::Song)
+                .max_by_key(|c| {
+                    (
+                        c.metadata.likes + c.metadata.interactions,
+                        std::cmp::Reverse(c.id),
+                    )
+                })?
+                .id;
+            let mut duplicates = m
+                .iter()
+                .map(|c| c.id)
+                .filter(|&id| id != survivor)
+                .collect::<Vec<_>>();
+            duplicates.sort();
+            Some(DuplicateGroup {
+                survivor,
+                duplicates,
+            })
+        })
+        .collect::<Vec<_>>();
+    dups.sort_by_key(|g| g.survivor);
+
+    Ok(dups)
+}
+
+async fn candidates<C: ConnectionTrait>(conn: &C) -> anyhow::Result<Vec<Candidate>> {
+    let models = object::Entity::find()
+        .filter(object::Column::Typ.is_in([Typ::Song, Typ::MmSong]))
+        .order_by_asc(object::Column::Id)
+        .all(conn)
+        .await?;
+
+    // yt id -> duration
+    let mut durations = HashMap::new();
+    let mut candidates = vec![];
+    for m in models {
+        let (refids, title, artist, duration) = match m.typ {
+            Typ::Song => {
+                let Some(t) = m.parsed_or_skip::<Song>() else {
+                    continue;
+                };
+                let artist = t.artists.first().map(|a| a.name.clone());
+                (t.refids().into_iter().collect(), t.title, artist, None)
+            }
+            _ => {
+                let Some(t) = m.parsed_or_skip::<MmSong>() else {
+                    continue;
+                };
+                let info = t.info.as_ref();
+                let duration = info.and_then(|i| i.duration);
+                if let Some(d) = duration {
+                    durations.insert(t.key.clone(), d);
+                }
+                let artist = t
+                    .artist_name
+                    .clone()
+                    .or_else(|| info.and_then(|i| i.artist_names.first().cloned()));
+                (t.refids().into_iter().collect(), t.title, artist, duration)
+            }
+        };
+
+        let title = normalize(&title);
+        let artist = artist.map(|a| artist_key(&a)).unwrap_or_default();
+        let key = (!title.is_empty() && !artist.is_empty()).then(|| format!("{title}|{artist}"));
+
+        candidates.push(Candidate {
+            id: m.id,
+            typ: m.typ.clone(),
+            metadata: m.db_metadata(),
+            refids,
+            key,
+            duration,
+        });
+    }
+
+    // songs only know their duration through the MmSong with the same yt id
+    for c in candidates.iter_mut().filter(|c| c.duration.is_none()) {
+        c.duration = c.refids.iter().find_map(|r| durations.get(r)).copied();
+    }
+
+    Ok(candidates)
+}
+
+/// normalized artist name. yt auto generated channels are named "<artist> - Topic"
+pub(crate) fn artist_key(name: &str) -> String {
+    let name = normalize(name);
+    match name.strip_suffix(" topic") {
+        Some(name) => name.to_string(),
+        None => name,
+    }
+}
+
+/// lowercase words. anything in brackets ("(Official Video)", "[HD]") is dropped
+fn normalize(s: &str) -> String {
+    let mut out = String::new();
+    let mut depth = 0u32;
+    for c in s.chars() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            _ if depth > 0 => {}
+            c if c.is_alphanumeric() => out.extend(c.to_lowercase()),
+            _ => {
+                if !out.is_empty() && !out.ends_with(' ') {
+                    out.push(' ');
+                }
+            }
+        }
+    }
+    out.trim_end().to_string()
+}
+
+struct UnionFind {
+    parent: Vec<usize>,
+    // any known duration of the group. only meaningful for roots
+    duration: Vec<Option<f32>>,
+}
+impl UnionFind {
+    fn new(duration: Vec<Option<f32>>) -> Self {
+        Self {
+            parent: (0..duration.len()).collect(),
+            duration,
+        }
+    }
+
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parent[i] != i {
+            self.parent[i] = self.parent[self.parent[i]];
+            i = self.parent[i];
+        }
+        i
+    }
+
+    fn duration(&mut self, i: usize) -> Option<f32> {
+        let root = self.find(i);
+        self.duration[root]
+    }
+
+    // smaller index wins so that roots stay stable
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            let (root, child) = (a.min(b), a.max(b));
+            self.parent[child] = root;
+            self.duration[root] = self.duration[root].or(self.duration[child]);
+        }
+    }
+}
+
+/// folds the sources and metadata of duplicates (Song or MmSong) into survivor and moves them
+/// to the trash. playlists, queues and plays that referred to a duplicate refer to survivor instead.
+/// all of it or nothing: runs in a savepoint of txn (events are pushed using [Db::push_event])
+pub async fn merge(
+    db: &Db,
+    txn: &DatabaseTransaction,
+    survivor: DbId,
+    duplicates: Vec<DbId>,
+) -> anyhow::Result<DbItem<Song>> {
+    let mut events = vec![];
+    let sp = txn.begin().await?;
+    let item = merge_into(&sp, survivor, duplicates, &mut events).await?;
+    sp.commit().await?;
+    for e in events {
+        db.push_event(e);
+    }
+    Ok(item)
+}
+
+async fn merge_into<C: ConnectionTrait>(
+    conn: &C,
+    survivor: DbId,
+    duplicates: Vec<DbId>,
+    events: &mut Vec<DbEvent>,
+) -> anyhow::Result<DbItem<Song>> {
+    if duplicates.is_empty() {
+        return Err(anyhow::anyhow!("nothing to merge"));
+    }
+    if duplicates.contains(&survivor) {
+        return Err(anyhow::anyhow!(
+            "song {} can't be merged into itself",
+            survivor
+        ));
+    }
+
+    if typ_of(conn, survivor).await? != Typ::Song {
+        return Err(anyhow::anyhow!("survivor {} is not a Song", survivor));
+    }
+    let mut item = Song::get(conn, survivor)
+        .await?
+        .ok_or(anyhow::anyhow!("song {} not found", survivor))?;
+
+    let duplicates = duplicates.into_iter().collect::<HashSet<_>>();
+    for &id in duplicates.iter() {
+        // the loser has to go first. refids are unique per typ
+        let typ = typ_of(conn, id).await?;
+        let (info_sources, play_sources, metadata) = match typ {
+            Typ::Song => {
+                let dup = Song::get(conn, id)
+                    .await?
+                    .ok_or(anyhow::anyhow!("song {} not found", id))?;
+                dup.delete(conn).await?;
+                (dup.t.info_sources, dup.t.play_sources, dup.metadata)
+            }
+            Typ::MmSong => {
+                let dup = MmSong::get(conn, id)
+                    .await?
+                    .ok_or(anyhow::anyhow!("song {} not found", id))?;
+                dup.delete(conn).await?;
+
+                // same as what the ui creates out of a MmSong
+                let mut play_sources = vec![];
+                play_sources.extend(dup.t.last_known_path.map(PlaySource::File));
+                play_sources.push(PlaySource::YtId(dup.t.key.clone()));
+                let info_sources = vec![InfoSource::YtId(dup.t.key)];
+                (info_sources, play_sources, dup.metadata)
+            }
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "only Song and MmSong can be merged (item {} is {:?})",
+                    id,
+                    typ
+                ));
+            }
+        };
+        events.push(DbEvent::Deleted { id, typ });
+
+        for s in info_sources {
+            if !item.t.info_sources.contains(&s) {
+                item.t.info_sources.push(s);
+            }
+        }
+        for s in play_sources {
+            if !item.t.play_sources.contains(&s) {
+                item.t.play_sources.push(s);
+            }
+        }
+
+        let m = &mut item.metadata;
+        m.done |= metadata.done;
+        m.likes += metadata.likes;
+        m.dislikes += metadata.dislikes;
+        m.interactions += metadata.interactions;
+        m.added_ts = m.added_ts.min(metadata.added_ts);
+    }
+
+    item.metadata = item.update(conn).await?;
+    events.push(DbEvent::Updated {
+        id: item.id,
+        typ: Typ::Song,
+        metadata: item.metadata.clone(),
+    });
+
+    let ids = duplicates
+        .into_iter()
+        .map(|id| (id, survivor))
+        .collect::<HashMap<_, _>>();
+    redirect_references::<Playlist, _>(conn, &ids, events).await?;
+    redirect_references::<Queue, _>(conn, &ids, events).await?;
+    crate::plays::redirect(conn, &ids).await?;
+
+    Ok(item)
+}
+
+async fn typ_of<C: ConnectionTrait>(conn: &C, id: DbId) -> anyhow::Result<Typ> {
+    let m = object::Entity::find_by_id(id)
+        .one(conn)
+        .await?
+        .ok_or(anyhow::anyhow!("item {} not found", id))?;
+    Ok(m.typ)
+}
+
+/// rewrites references in live items. trashed ones still point to the (also trashed) duplicate
+async fn redirect_references<T: DbAble, C: ConnectionTrait>(
+    conn: &C,
+    ids: &HashMap<DbId, DbId>,
+    events: &mut Vec<DbEvent>,
+) -> anyhow::Result<()> {
+    let models = object::Entity::find()
+        .filter(object::Column::Typ.eq(T::typ()))
+        .all(conn)
+        .await?;
+    for m in models {
+        let Some(t) = m.parsed_or_skip::<T>() else {
+            continue;
+        };
+        let referenced = t.referenced_ids().into_iter().collect::<Vec<_>>();
+        if !referenced.iter().any(|id| ids.contains_key(id)) {
+            continue;
+        }
+
+        // remap_ids drops anything that is not in the map
+        let remap = referenced
+            .into_iter()
+            .map(|id| (id, ids.get(&id).copied().unwrap_or(id)))
+            .collect();
+        let mut item = DbItem {
+            metadata: m.db_metadata(),
+            id: m.id,
+            typ: T::typ(),
+            t,
+        };
+        item.t.remap_ids(&remap);
+        let metadata = item.update(conn).await?;
+        events.push(DbEvent::Updated {
+            id: item.id,
+            typ: T::typ(),
+            metadata,
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,381 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use sea_orm::{ConnectionTrait, EntityTrait, QueryOrder, QuerySelect, TransactionTrait};
+use serde::{Deserialize, Serialize};
+
+use crate::db::{
+    insert_links, link, object, quarantine, refid, remove_object, trash, Db, DbAble, DbEvent, DbId,
+    Link, Typ,
+};
+use crate::migrations;
+
+// refids, links and search index are all derived from object data.
+// so object data is what everything else gets checked (and repaired) against
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct FsckReport {
+    pub objects: u32,
+    pub unparseable: Vec<UnparseableObject>,
+    // refid rows that no object declares
+    pub orphan_refids: Vec<RefidRow>,
+    // refids declared by an object that have no row
+    pub missing_refids: Vec<RefidRow>,
+    // refids declared by more than 1 object of the same typ
+    pub duplicate_refids: Vec<DuplicateRefid>,
+    pub orphan_links: Vec<LinkRow>,
+    pub missing_links: Vec<LinkRow>,
+    // ids that are neither in the db (parseable) nor in the trash
+    pub dangling_references: Vec<DanglingReference>,
+    pub repaired: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct UnparseableObject {
+    pub id: DbId,
+    pub typ: Typ,
+    pub error: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RefidRow {
+    pub refid: String,
+    pub typ: Typ,
+    pub object_id: DbId,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DuplicateRefid {
+    pub refid: String,
+    pub typ: Typ,
+    pub ids: Vec<DbId>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LinkRow {
+    pub from_refid: String,
+    pub from_typ: Typ,
+    pub to_refid: String,
+    pub to_typ: Typ,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DanglingReference {
+    pub id: DbId,
+    pub typ: Typ,
+    pub missing: Vec<DbId>,
+}
+
+impl FsckReport {
+    pub fn is_clean(&self) -> bool {
+        self.unparseable.is_empty()
+            && self.orphan_refids.is_empty()
+            && self.missing_refids.is_empty()
+            && self.duplicate_refids.is_empty()
+            && self.orphan_links.is_empty()
+            && self.missing_links.is_empty()
+            && self.dangling_references.is_empty()
+    }
+}
+
+/// read only check of the whole db
+pub async fn check(db: &Db) -> anyhow::Result<FsckReport> {
+    // everything from 1 snapshot of the db
+    let txn = db.db.begin().await?;
+    let report = run(&txn).await?;
+    txn.commit().await?;
+    Ok(report)
+}
+
+/// checks and fixes whatever it can (duplicate refids are only reported).
+/// unparseable objects are moved to the quarantine table. the db is backed up first
+pub async fn repair(db: &Db, backup_path: impl AsRef<Path>) -> anyhow::Result<FsckReport> {
+    migrations::backup(db, backup_path).await?;
+
+    let report = db
+        .in_transaction(|txn| {
+            Box::pin(async move {
+                let mut report = run(txn).await?;
+                fix(txn, &report).await?;
+                report.repaired = true;
+                Ok(report)
+            })
+        })
+        .await?;
+    db.send_event(DbEvent::Resync);
+    Ok(report)
+}
+
+struct Parsed {
+    refids: HashSet<String>,
+    links: HashSet<Link>,
+    referenced: Vec<DbId>,
+}
+
+fn parse<T: DbAble>(data: &str) -> anyhow::Result<Parsed> {
+    let t: T = serde_json::from_str(data)?;
+    Ok(Parsed {
+        refids: t.refids().into_iter().collect(),
+        links: t.links().into_iter().collect(),
+        referenced: t.referenced_ids().into_iter().collect(),
+    })
+}
+
+fn parse_typ(typ: &Typ, data: &str) -> anyhow::Result<Parsed> {
+    use crate::{covau_types as covau, mbz, musimanager as mm, yt::song_tube as st};
+    type MmSong = mm::Song<Option<mm::SongInfo>, covau::SourcePath>;
+
+    match typ {
+        Typ::MmSong => parse::<MmSong>(data),
+        Typ::MmAlbum => parse::<mm::Album<crate::yt::VideoId>>(data),
+        Typ::MmArtist => parse::<mm::Artist<crate::yt::VideoId, crate::yt::AlbumId>>(data),
+        Typ::MmPlaylist => parse::<mm::Playlist<crate::yt::VideoId>>(data),
+        Typ::MmQueue => parse::<mm::Queue<crate::yt::VideoId>>(data),
+        Typ::LocalState => parse::<covau::LocalState>(data),
+        Typ::Song => parse::<covau::Song>(data),
+        Typ::Playlist => parse::<covau::Playlist>(data),
+        Typ::Queue => parse::<covau::Queue>(data),
+        Typ::ArtistBlacklist => parse::<covau::ArtistBlacklist>(data),
+        Typ::SongBlacklist => parse::<covau::SongBlacklist>(data),
+        Typ::Updater => parse::<covau::Updater>(data),
+        Typ::SmartPlaylist => parse::<covau::SmartPlaylist>(data),
+        Typ::StSong => parse::<st::Song>(data),
+        Typ::StAlbum => parse::<st::Album>(data),
+        Typ::StPlaylist => parse::<st::Playlist>(data),
+        Typ::StArtist => parse::<st::Artist>(data),
+        Typ::MbzRecording => parse::<mbz::RecordingWithInfo>(data),
+        Typ::MbzArtist => parse::<mbz::Artist>(data),
+    }
+}
+
+async fn run<C: ConnectionTrait>(conn: &C) -> anyhow::Result<FsckReport> {
+    let mut report = FsckReport::default();
+
+    let objects = object::Entity::find()
+        .select_only()
+        .columns([
+            object::Column::Id,
+            object::Column::Typ,
+            object::Column::Data,
+        ])
+        .order_by_asc(object::Column::Id)
+        .into_tuple::<(DbId, Typ, String)>()
+        .all(conn)
+        .await?;
+    let mut existing = trash::Entity::find()
+        .select_only()
+        .column(trash::Column::Id)
+        .into_tuple::<DbId>()
+        .all(conn)
+        .await?
+        .into_iter()
+        .collect::<HashSet<_>>();
+    let mut declared: HashMap<(String, Typ), Vec<DbId>> = HashMap::new();
+    let mut expected_links = HashSet::new();
+    let mut unparseable = HashSet::new();
+    let mut referencing = vec![];
+    for (id, typ, data) in objects {
+        report.objects += 1;
+        let parsed = match parse_typ(&typ, &data) {
+            Ok(p) => p,
+            Err(e) => {
+                unparseable.insert(id);
+                report.unparseable.push(UnparseableObject {
+                    id,
+                    typ,
+                    error: e.to_string(),
+                });
+                continue;
+            }
+        };
+        existing.insert(id);
+
+        for r in parsed.refids {
+            declared.entry((r, typ.clone())).or_default().push(id);
+        }
+        expected_links.extend(parsed.links);
+        if !parsed.referenced.is_empty() {
+            referencing.push((id, typ, parsed.referenced));
+        }
+    }
+
+    // unparseable objects are as good as missing (repair quarantines them)
+    for (id, typ, referenced) in referencing {
+        let missing = referenced
+            .into_iter()
+            .filter(|rid| !existing.contains(rid))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            report
+                .dangling_references
+                .push(DanglingReference { id, typ, missing });
+        }
+    }
+
+    // rows of unparseable objects go away with them. so they are not reported
+    let mut rows = HashSet::new();
+    let mut unparseable_refids = HashSet::new();
+    for r in refid::Entity::find().all(conn).await? {
+        if unparseable.contains(&r.object_id) {
+            unparseable_refids.insert((r.refid, r.typ));
+            continue;
+        }
+        let key = (r.refid, r.typ);
+        let ok = declared
+            .get(&key)
+            .map(|ids| ids.contains(&r.object_id))
+            .unwrap_or(false);
+        if ok {
+            rows.insert(key);
+        } else {
+            report.orphan_refids.push(RefidRow {
+                refid: key.0,
+                typ: key.1,
+                object_id: r.object_id,
+            });
+        }
+    }
+
+    let mut declared = declared.into_iter().collect::<Vec<_>>();
+    declared.sort_by_key(|(_, ids)| ids[0]);
+    for ((refid, typ), ids) in declared {
+        if ids.len() > 1 {
+            report.duplicate_refids.push(DuplicateRefid {
+                refid: refid.clone(),
+                typ: typ.clone(),
+                ids: ids.clone(),
+            });
+        }
+        let key = (refid, typ);
+        if !rows.contains(&key) {
+            report.missing_refids.push(RefidRow {
+                refid: key.0,
+                typ: key.1,
+                object_id: ids[0],
+            });
+        }
+    }
+
+    let mut found_links = HashSet::new();
+    for l in link::Entity::find().all(conn).await? {
+        if unparseable_refids.contains(&(l.from_refid.clone(), l.from_typ.clone())) {
+            continue;
+        }
+        if expected_links.contains(&l) {
+            found_links.insert(l);
+        } else {
+            report.orphan_links.push(LinkRow {
+                from_refid: l.from_refid,
+                from_typ: l.from_typ,
+                to_refid: l.to_refid,
+                to_typ: l.to_typ,
+            });
+        }
+    }
+    report.missing_links = expected_links
+        .into_iter()
+        .filter(|l| !found_links.contains(l))
+        .map(|l| LinkRow {
+            from_refid: l.from_refid,
+            from_typ: l.from_typ,
+            to_refid: l.to_refid,
+            to_typ: l.to_typ,
+        })
+        .collect();
+
+    Ok(report)
+}
+
+async fn fix<C: ConnectionTrait>(conn: &C, report: &FsckReport) -> anyhow::Result<()> {
+    for u in report.unparseable.iter() {
+        let Some(m) = object::Entity::find_by_id(u.id).one(conn).await? else {
+            continue;
+        };
+        let am = quarantine::ActiveModel {
+            id: sea_orm::ActiveValue::Set(m.id),
+            typ: sea_orm::ActiveValue::Set(m.typ.clone()),
+            metadata: sea_orm::ActiveValue::Set(m.db_metadata().to_json()),
+            data: sea_orm::ActiveValue::Set(m.data),
+            reason: sea_orm::ActiveValue::Set(u.error.clone()),
+            quarantined_ts: sea_orm::ActiveValue::Set(Db::timestamp() as _),
+        };
+        quarantine::Entity::insert(am).exec(conn).await?;
+        remove_object(conn, m.typ, m.id).await?;
+    }
+
+    for r in report.orphan_refids.iter() {
+        let _ = refid::Entity::delete_by_id((r.refid.clone(), r.typ.clone()))
+            .exec(conn)
+            .await?;
+    }
+    // duplicates can't get more than 1 row anyway
+    for r in report.missing_refids.iter() {
+        let am = refid::ActiveModel {
+            refid: sea_orm::ActiveValue::Set(r.refid.clone()),
+            typ: sea_orm::ActiveValue::Set(r.typ.clone()),
+            object_id: sea_orm::ActiveValue::Set(r.object_id),
+        };
+        refid::Entity::insert(am).exec(conn).await?;
+    }
+
+    for l in report.orphan_links.iter() {
+        let _ = link::Entity::delete_by_id((
+            l.from_refid.clone(),
+            l.from_typ.clone(),
+            l.to_refid.clone(),
+            l.to_typ.clone(),
+        ))
+        .exec(conn)
+        .await?;
+    }
+    let links = report
+        .missing_links
+        .iter()
+        .map(|l| Link {
+            from_refid: l.from_refid.clone(),
+            from_typ: l.from_typ.clone(),
+            to_refid: l.to_refid.clone(),
+            to_typ: l.to_typ.clone(),
+        })
+        .collect();
+    insert_links(conn, links).await?;
+
+    for d in report.dangling_references.iter() {
+        use crate::covau_types as covau;
+
+        match d.typ {
+            Typ::LocalState => drop_missing::<covau::LocalState, _>(conn, d).await?,
+            Typ::Playlist => drop_missing::<covau::Playlist, _>(conn, d).await?,
+            Typ::Queue => drop_missing::<covau::Queue, _>(conn, d).await?,
+            Typ::SmartPlaylist => drop_missing::<covau::SmartPlaylist, _>(conn, d).await?,
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "{:?} does not refer to other objects",
+                    d.typ
+                ))
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// goes through [crate::db::DbItem::update]. so the old version stays in history
+async fn drop_missing<T: DbAble, C: ConnectionTrait>(
+    conn: &C,
+    d: &DanglingReference,
+) -> anyhow::Result<()> {
+    let Some(mut item) = T::get(conn, d.id).await? else {
+        return Ok(());
+    };
+    let ids = item
+        .t
+        .referenced_ids()
+        .into_iter()
+        .filter(|id| !d.missing.contains(id))
+        .map(|id| (id, id))
+        .collect();
+    item.t.remap_ids(&ids);
+    item.update(conn).await?;
+    Ok(())
+}
@@ -6,6 +6,10 @@ use std::path::PathBuf;
 
 use anyhow::Result;
 
+#[cfg(feature = "appdeps")]
+pub mod analytics;
+#[cfg(feature = "appdeps")]
+pub mod archive;
 #[cfg(feature = "appdeps")]
 pub mod config;
 #[cfg(feature = "appdeps")]
@@ -13,12 +17,26 @@ pub mod covau_types;
 #[cfg(feature = "appdeps")]
 pub mod db;
 #[cfg(feature = "appdeps")]
+pub mod dedup;
+#[cfg(feature = "appdeps")]
+pub mod fsck;
+#[cfg(feature = "appdeps")]
 pub mod mbz;
 #[cfg(feature = "appdeps")]
+pub mod migrations;
+#[cfg(feature = "appdeps")]
 pub mod musimanager;
 #[cfg(feature = "appdeps")]
+pub mod plays;
+#[cfg(feature = "appdeps")]
+pub mod search;
+#[cfg(feature = "appdeps")]
 pub mod server;
 #[cfg(feature = "appdeps")]
+pub mod smart;
+#[cfg(feature = "appdeps")]
+pub mod stats;
+#[cfg(feature = "appdeps")]
 pub mod yt;
 
 #[cfg(all(feature = "appdeps", feature = "native-player"))]
@@ -0,0 +1,345 @@
+use std::path::Path;
+
+use sea_orm::{ActiveEnum, ConnectionTrait, Statement, TransactionTrait};
+
+use crate::db::{Db, Typ};
+
+// - never edit a migration that has been released. add a new one instead
+// - versions must be consecutive
+// - [Db::init_tables] always creates the latest schema, so new dbs skip all of these
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "persist links and fts5 search index",
+        steps: &[
+            Step::Sql(
+                "CREATE VIRTUAL TABLE IF NOT EXISTS search_index USING fts5(haystack, tokenize = 'trigram');",
+            ),
+            Step::Reindex,
+        ],
+    },
+    Migration {
+        version: 2,
+        name: "move metadata json into columns",
+        steps: &[
+            Step::Sql(
+                "
+                ALTER TABLE covau_objects ADD COLUMN done BOOLEAN NOT NULL DEFAULT FALSE;
+                ALTER TABLE covau_objects ADD COLUMN likes BIGINT NOT NULL DEFAULT 0;
+                ALTER TABLE covau_objects ADD COLUMN dislikes BIGINT NOT NULL DEFAULT 0;
+                ALTER TABLE covau_objects ADD COLUMN interactions BIGINT NOT NULL DEFAULT 0;
+                ALTER TABLE covau_objects ADD COLUMN update_counter BIGINT NOT NULL DEFAULT 0;
+                ALTER TABLE covau_objects ADD COLUMN added_ts BIGINT NOT NULL DEFAULT 0;
+                ALTER TABLE covau_objects ADD COLUMN updated_ts BIGINT NOT NULL DEFAULT 0;
+                ",
+            ),
+            // timestamps are stored as strings in the json
+            Step::Sql(
+                "
+                UPDATE covau_objects SET
+                    done = json_extract(metadata, '$.done'),
+                    likes = json_extract(metadata, '$.likes'),
+                    dislikes = json_extract(metadata, '$.dislikes'),
+                    interactions = json_extract(metadata, '$.interactions'),
+                    update_counter = json_extract(metadata, '$.update_counter'),
+                    added_ts = CAST(json_extract(metadata, '$.added_ts') AS INTEGER),
+                    updated_ts = CAST(json_extract(metadata, '$.updated_ts') AS INTEGER);
+                ",
+            ),
+            Step::Sql("ALTER TABLE covau_objects DROP COLUMN metadata;"),
+            Step::Sql(
+                "
+                CREATE INDEX IF NOT EXISTS done_index ON covau_objects (typ, done);
+                CREATE INDEX IF NOT EXISTS likes_index ON covau_objects (typ, likes);
+                CREATE INDEX IF NOT EXISTS dislikes_index ON covau_objects (typ, dislikes);
+                CREATE INDEX IF NOT EXISTS interactions_index ON covau_objects (typ, interactions);
+                CREATE INDEX IF NOT EXISTS added_ts_index ON covau_objects (typ, added_ts);
+                CREATE INDEX IF NOT EXISTS updated_ts_index ON covau_objects (typ, updated_ts);
+                ",
+            ),
+        ],
+    },
+    Migration {
+        version: 3,
+        name: "trash bin",
+        steps: &[Step::Sql(
+            "
+            CREATE TABLE IF NOT EXISTS covau_trash (
+                id integer NOT NULL PRIMARY KEY,
+                typ integer NOT NULL,
+                data text NOT NULL,
+                metadata text NOT NULL,
+                trashed_ts bigint NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS trashed_ts_index ON covau_trash (trashed_ts);
+            ",
+        )],
+    },
+    Migration {
+        version: 4,
+        name: "object revision history",
+        steps: &[Step::Sql(
+            "
+            CREATE TABLE IF NOT EXISTS covau_history (
+                object_id integer NOT NULL,
+                update_counter bigint NOT NULL,
+                typ integer NOT NULL,
+                data text NOT NULL,
+                metadata text NOT NULL,
+                replaced_ts bigint NOT NULL,
+                CONSTRAINT \"pk-covau_history\" PRIMARY KEY (object_id, update_counter)
+            );
+            CREATE INDEX IF NOT EXISTS replaced_ts_index ON covau_history (replaced_ts);
+            ",
+        )],
+    },
+    Migration {
+        version: 5,
+        name: "fsck quarantine",
+        steps: &[Step::Sql(
+            "
+            CREATE TABLE IF NOT EXISTS covau_quarantine (
+                id integer NOT NULL PRIMARY KEY,
+                typ integer NOT NULL,
+                data text NOT NULL,
+                metadata text NOT NULL,
+                reason text NOT NULL,
+                quarantined_ts bigint NOT NULL
+            );
+            ",
+        )],
+    },
+    Migration {
+        version: 6,
+        name: "play history",
+        steps: &[Step::Sql(
+            "
+            CREATE TABLE IF NOT EXISTS covau_plays (
+                id integer NOT NULL PRIMARY KEY AUTOINCREMENT,
+                song_id integer NOT NULL,
+                queue_id integer NULL,
+                started_ts bigint NOT NULL,
+                listened_secs bigint NOT NULL,
+                skipped boolean NOT NULL,
+                source text NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS started_ts_index ON covau_plays (started_ts);
+            CREATE INDEX IF NOT EXISTS song_started_ts_index ON covau_plays (song_id, started_ts);
+            ",
+        )],
+    },
+    Migration {
+        version: 7,
+        name: "mark autoplayed plays",
+        steps: &[Step::Sql(
+            "ALTER TABLE covau_plays ADD COLUMN autoplay BOOLEAN NOT NULL DEFAULT FALSE;",
+        )],
+    },
+];
+
+pub struct Migration {
+    pub version: u32,
+    pub name: &'static str,
+    pub steps: &'static [Step],
+}
+
+pub enum Step {
+    /// schema changes (DDL) or any other plain sql
+    Sql(&'static str),
+
+    /// rewrite the json 'data' of every object of this type
+    Json {
+        typ: Typ,
+        rewrite: fn(serde_json::Value) -> anyhow::Result<serde_json::Value>,
+    },
+
+    /// rebuild links and search index from objects.
+    /// add this after any Json step that changes haystack / refids / links of a type
+    Reindex,
+}
+
+#[derive(Debug, Clone)]
+pub struct MigrationReport {
+    pub version: u32,
+    pub name: &'static str,
+    pub log: Vec<String>,
+}
+
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+/// for dbs created using [Db::init_tables]
+pub async fn init_schema_version<C: ConnectionTrait>(conn: &C) -> anyhow::Result<()> {
+    create_schema_version_table(conn).await?;
+    set_schema_version(conn, latest_version()).await?;
+    Ok(())
+}
+
+async fn create_schema_version_table<C: ConnectionTrait>(conn: &C) -> anyhow::Result<()> {
+    conn.execute_unprepared(
+        "CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL);",
+    )
+    .await?;
+    Ok(())
+}
+
+/// dbs created before migrations existed do not have a schema_version. those are version 0
+pub async fn schema_version<C: ConnectionTrait>(conn: &C) -> anyhow::Result<u32> {
+    create_schema_version_table(conn).await?;
+    let stmt = Statement::from_string(
+        conn.get_database_backend(),
+        "SELECT version FROM schema_version LIMIT 1;",
+    );
+    let version = conn
+        .query_one(stmt)
+        .await?
+        .map(|r| r.try_get::<i64>("", "version"))
+        .transpose()?
+        .unwrap_or(0);
+    Ok(version as u32)
+}
+
+async fn set_schema_version<C: ConnectionTrait>(conn: &C, version: u32) -> anyhow::Result<()> {
+    conn.execute_unprepared("DELETE FROM schema_version;")
+        .await?;
+    let stmt = Statement::from_sql_and_values(
+        conn.get_database_backend(),
+        "INSERT INTO schema_version (version) VALUES (?);",
+        [(version as i64).into()],
+    );
+    let _ = conn.execute(stmt).await?;
+    Ok(())
+}
+
+pub async fn pending(db: &Db) -> anyhow::Result<Vec<&'static Migration>> {
+    let version = schema_version(&db.db).await?;
+    if version > latest_version() {
+        return Err(anyhow::anyhow!(
+            "database schema version {} is newer than what this covau knows ({})",
+            version,
+            latest_version()
+        ));
+    }
+    let pending = MIGRATIONS.iter().filter(|m| m.version > version).collect();
+    Ok(pending)
+}
+
+/// runs all pending migrations in a single transaction.
+/// the db is copied to backup_path before anything is touched
+pub async fn migrate(
+    db: &Db,
+    backup_path: impl AsRef<Path>,
+) -> anyhow::Result<Vec<MigrationReport>> {
+    let pending = pending(db).await?;
+    if pending.is_empty() {
+        return Ok(vec![]);
+    }
+
+    backup(db, backup_path).await?;
+
+    let txn = db.db.begin().await?;
+    let reports = run(&txn, &pending).await?;
+    txn.commit().await?;
+
+    for r in reports.iter() {
+        println!("migrated database to version {}: {}", r.version, r.name);
+    }
+
+    Ok(reports)
+}
+
+/// copies the db to backup_path
+pub async fn backup(db: &Db, backup_path: impl AsRef<Path>) -> anyhow::Result<()> {
+    // - [VACUUM INTO](https://www.sqlite.org/lang_vacuum.html#vacuuminto)
+    // plain file copy misses whatever is still in the wal
+    let backup_path = backup_path.as_ref();
+    let stmt = Statement::from_sql_and_values(
+        db.db.get_database_backend(),
+        "VACUUM INTO ?;",
+        [backup_path.to_string_lossy().to_string().into()],
+    );
+    let _ = db.db.execute(stmt).await?;
+    println!("database backed up at {}", backup_path.to_string_lossy());
+    Ok(())
+}
+
+/// runs all pending migrations and rolls them back
+pub async fn dry_run(db: &Db) -> anyhow::Result<Vec<MigrationReport>> {
+    let pending = pending(db).await?;
+
+    let txn = db.db.begin().await?;
+    let reports = run(&txn, &pending).await;
+    txn.rollback().await?;
+
+    reports
+}
+
+async fn run<C: ConnectionTrait>(
+    conn: &C,
+    pending: &[&'static Migration],
+) -> anyhow::Result<Vec<MigrationReport>> {
+    let mut reports = vec![];
+    for m in pending {
+        let mut log = vec![];
+        for step in m.steps {
+            match step {
+                Step::Sql(sql) => {
+                    let res = conn.execute_unprepared(sql).await?;
+                    log.push(format!("sql: {} rows affected", res.rows_affected()));
+                }
+                Step::Json { typ, rewrite } => {
+                    let n = rewrite_json(conn, typ.clone(), *rewrite)
+                        .await
+                        .map_err(|e| {
+                            anyhow::anyhow!("migration {} ({:?}): {}", m.version, typ, e)
+                        })?;
+                    log.push(format!("json: {} {:?} objects rewritten", n, typ));
+                }
+                Step::Reindex => {
+                    Db::reindex(conn).await?;
+                    log.push("reindex: links and search index rebuilt".to_string());
+                }
+            }
+        }
+        set_schema_version(conn, m.version).await?;
+
+        reports.push(MigrationReport {
+            version: m.version,
+            name: m.name,
+            log,
+        });
+    }
+    Ok(reports)
+}
+
+async fn rewrite_json<C: ConnectionTrait>(
+    conn: &C,
+    typ: Typ,
+    rewrite: fn(serde_json::Value) -> anyhow::Result<serde_json::Value>,
+) -> anyhow::Result<u32> {
+    let backend = conn.get_database_backend();
+    let stmt = Statement::from_sql_and_values(
+        backend,
+        "SELECT id, data FROM covau_objects WHERE typ = ?;",
+        [typ.to_value().into()],
+    );
+    let rows = conn.query_all(stmt).await?;
+
+    let mut n = 0;
+    for row in rows {
+        let id: i32 = row.try_get("", "id")?;
+        let data: String = row.try_get("", "data")?;
+        let value = serde_json::from_str(&data)?;
+        let value = rewrite(value).map_err(|e| anyhow::anyhow!("object {}: {}", id, e))?;
+
+        let stmt = Statement::from_sql_and_values(
+            backend,
+            "UPDATE covau_objects SET data = ? WHERE id = ?;",
+            [serde_json::to_string(&value)?.into(), id.into()],
+        );
+        let _ = conn.execute(stmt).await?;
+        n += 1;
+    }
+    Ok(n)
+}
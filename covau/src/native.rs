@@ -1,6 +1,6 @@
 use std::{path::PathBuf, sync::Arc};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 
 pub use anyhow;
 pub use clap;
@@ -10,14 +10,41 @@ pub use serde;
 pub use serde_json;
 pub use tokio;
 
-use crate::{config, covau_types, db, mbz, musimanager, server, yt};
+use crate::{
+    analytics, archive, config, covau_types, db, fsck, mbz, migrations, musimanager, server, stats,
+    yt,
+};
 
 pub async fn server_start(conf: Arc<config::DerivedConfig>) -> Result<()> {
-    server::start("127.0.0.1".parse()?, conf.server_port, conf).await?;
+    let addr = conf
+        .config
+        .bind_address
+        .as_deref()
+        .unwrap_or("127.0.0.1")
+        .parse()
+        .context("invalid bind_address")?;
+    server::start(addr, conf.server_port, conf).await?;
     Ok(())
 }
 
-pub async fn run_command(server_port: u16, debug: bool, command: config::FeCommand) -> anyhow::Result<()> {
+/// the install token of the running server. only works on the same machine
+async fn local_token(client: &reqwest::Client, server_port: u16) -> Result<String> {
+    let token = client
+        .post(format!("http://localhost:{}/auth/local", server_port))
+        .timeout(std::time::Duration::from_secs(5))
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<String>()
+        .await?;
+    Ok(token)
+}
+
+pub async fn run_command(
+    server_port: u16,
+    debug: bool,
+    command: config::FeCommand,
+) -> anyhow::Result<()> {
     use server::routes::FeRequest;
     use server::ErrorMessage;
 
@@ -46,8 +73,10 @@ pub async fn run_command(server_port: u16, debug: bool, command: config::FeComma
 
     let client = reqwest::Client::new();
     let port = server_port;
+    let token = local_token(&client, port).await?;
     let req = client
         .post(format!("http://localhost:{}/cli", port))
+        .bearer_auth(token)
         .body(serde_json::to_string(&fereq)?)
         .timeout(std::time::Duration::from_secs(5))
         .build()?;
@@ -90,6 +119,286 @@ pub async fn run_command(server_port: u16, debug: bool, command: config::FeComma
     Ok(())
 }
 
+pub async fn run_auth_command(server_port: u16, command: config::AuthCommand) -> Result<()> {
+    use server::auth::{PairedDevice, PairingCode};
+    use server::ErrorMessage;
+
+    let client = reqwest::Client::new();
+    let token = local_token(&client, server_port).await?;
+    let request = |path: &str, body: serde_json::Value| {
+        client
+            .post(format!("http://localhost:{}/auth/{}", server_port, path))
+            .bearer_auth(&token)
+            .json(&body)
+            .timeout(std::time::Duration::from_secs(5))
+            .send()
+    };
+    async fn check(resp: reqwest::Response) -> Result<reqwest::Response> {
+        if resp.status().is_success() {
+            return Ok(resp);
+        }
+        let err = resp.json::<ErrorMessage>().await?;
+        Err(anyhow::anyhow!(format!("{}", err)))
+    }
+
+    match command {
+        config::AuthCommand::Pair => {
+            let resp = check(request("pair", serde_json::Value::Null).await?).await?;
+            let code = resp.json::<PairingCode>().await?;
+            println!("pairing code: {}", code.code);
+            println!(
+                "enter it in the ui on the new device (valid for {} minutes)",
+                code.expires_ts.saturating_sub(db::Db::timestamp()) / 60
+            );
+        }
+        config::AuthCommand::Devices => {
+            let resp = check(request("devices", serde_json::Value::Null).await?).await?;
+            let devices = resp.json::<Vec<PairedDevice>>().await?;
+            if devices.is_empty() {
+                println!("no paired devices");
+            }
+            for d in devices {
+                println!("{} (paired on {})", d.name, analytics::date(d.paired_ts));
+            }
+        }
+        config::AuthCommand::Revoke { name } => {
+            check(request("revoke", serde_json::json!(name)).await?).await?;
+            println!("revoked {}", name);
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn run_db_command(
+    conf: Arc<config::DerivedConfig>,
+    command: config::DbCommand,
+) -> anyhow::Result<()> {
+    let db_path = conf.db_path.join("music.db");
+    if matches!(command, config::DbCommand::Import { .. }) && !db_path.exists() {
+        let db = db::Db::new(format!("sqlite:{}?mode=rwc", db_path.to_string_lossy())).await?;
+        db.init_tables().await?;
+        db.init_state().await?;
+    }
+    if !db_path.exists() {
+        return Err(anyhow::anyhow!(
+            "no database at {}",
+            db_path.to_string_lossy()
+        ));
+    }
+    let db = db::Db::new(format!("sqlite:{}?mode=rw", db_path.to_string_lossy())).await?;
+    let version = migrations::schema_version(&db.db).await?;
+    // json output should be the only thing on stdout
+    if !matches!(
+        command,
+        config::DbCommand::Stats { json: true } | config::DbCommand::Report { path: None, .. }
+    ) {
+        println!(
+            "schema version: {} (latest: {})",
+            version,
+            migrations::latest_version()
+        );
+    }
+
+    let reports = match command {
+        config::DbCommand::Check => {
+            for m in migrations::pending(&db).await? {
+                println!("pending: {} - {}", m.version, m.name);
+            }
+            return Ok(());
+        }
+        config::DbCommand::DryRun => migrations::dry_run(&db).await?,
+        config::DbCommand::Migrate => {
+            let backup_path = conf.db_path.join(format!(
+                "music.db.backup-v{}-{}",
+                version,
+                db::Db::timestamp()
+            ));
+            migrations::migrate(&db, backup_path).await?
+        }
+        config::DbCommand::Export { path } => {
+            let file = std::io::BufWriter::new(std::fs::File::create(&path)?);
+            let report = archive::export(&db, file).await?;
+            println!(
+                "exported {} objects to {} ({} could not be parsed and were skipped)",
+                report.exported,
+                path.to_string_lossy(),
+                report.skipped
+            );
+            return Ok(());
+        }
+        config::DbCommand::Import { path } => {
+            let file = std::io::BufReader::new(std::fs::File::open(&path)?);
+            let report = archive::import(&db, file).await?;
+            println!(
+                "imported {} new objects ({} already existed)",
+                report.inserted, report.existing
+            );
+            return Ok(());
+        }
+        config::DbCommand::Fsck { repair } => {
+            let report = if repair {
+                let backup_path = conf
+                    .db_path
+                    .join(format!("music.db.backup-fsck-{}", db::Db::timestamp()));
+                fsck::repair(&db, backup_path).await?
+            } else {
+                fsck::check(&db).await?
+            };
+            print_fsck_report(&report);
+            return Ok(());
+        }
+        config::DbCommand::Stats { json } => {
+            let stats = stats::collect(&db, &conf).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&stats)?);
+            } else {
+                print_stats(&stats);
+            }
+            return Ok(());
+        }
+        config::DbCommand::Report {
+            days,
+            year,
+            format,
+            path,
+        } => {
+            let (after, before) = match year {
+                Some(y) => (analytics::year_start(y), analytics::year_start(y + 1)),
+                None => {
+                    let now = db::Db::timestamp();
+                    (now.saturating_sub(days as u64 * 24 * 60 * 60), now + 1)
+                }
+            };
+            let stats = analytics::collect(&db.db, after, before).await?;
+            let report = match format {
+                config::ReportFormat::Markdown => analytics::markdown(&stats),
+                config::ReportFormat::Html => analytics::html(&stats),
+                config::ReportFormat::Json => serde_json::to_string_pretty(&stats)?,
+            };
+            match path {
+                Some(path) => {
+                    std::fs::write(&path, report)?;
+                    println!("report written to {}", path.to_string_lossy());
+                }
+                None => println!("{}", report),
+            }
+            return Ok(());
+        }
+    };
+
+    if reports.is_empty() {
+        println!("nothing to migrate");
+    }
+    for r in reports {
+        println!("{} - {}", r.version, r.name);
+        for l in r.log {
+            println!("    {}", l);
+        }
+    }
+
+    Ok(())
+}
+
+fn print_fsck_report(report: &fsck::FsckReport) {
+    println!("checked {} objects", report.objects);
+    for u in report.unparseable.iter() {
+        println!("unparseable: {} ({:?}): {}", u.id, u.typ, u.error);
+    }
+    for r in report.orphan_refids.iter() {
+        println!("orphan refid: {} ({:?}) -> {}", r.refid, r.typ, r.object_id);
+    }
+    for r in report.missing_refids.iter() {
+        println!(
+            "missing refid: {} ({:?}) -> {}",
+            r.refid, r.typ, r.object_id
+        );
+    }
+    for r in report.duplicate_refids.iter() {
+        println!("duplicate refid: {} ({:?}) in {:?}", r.refid, r.typ, r.ids);
+    }
+    for l in report.orphan_links.iter() {
+        println!(
+            "orphan link: {} ({:?}) -> {} ({:?})",
+            l.from_refid, l.from_typ, l.to_refid, l.to_typ
+        );
+    }
+    for l in report.missing_links.iter() {
+        println!(
+            "missing link: {} ({:?}) -> {} ({:?})",
+            l.from_refid, l.from_typ, l.to_refid, l.to_typ
+        );
+    }
+    for d in report.dangling_references.iter() {
+        println!(
+            "dangling references: {} ({:?}) -> {:?}",
+            d.id, d.typ, d.missing
+        );
+    }
+
+    if report.is_clean() {
+        println!("no problems found");
+    } else if report.repaired {
+        println!("repaired (duplicate refids need to be fixed by hand)");
+    } else {
+        println!("run with --repair to fix these");
+    }
+}
+
+fn print_stats(stats: &stats::LibraryStats) {
+    println!("objects:");
+    for c in stats.objects.iter() {
+        println!("    {:?}: {}", c.typ, c.count);
+    }
+    println!(
+        "songs: {} with a local file, {} without",
+        stats.songs_with_file, stats.songs_without_file
+    );
+    println!("disk usage:");
+    for d in stats.disk_usage.iter() {
+        if d.exists {
+            println!(
+                "    {}: {} in {} files ({})",
+                d.name,
+                stats::human_bytes(d.bytes),
+                d.files,
+                d.path.to_string_lossy()
+            );
+        } else {
+            println!("    {}: missing ({})", d.name, d.path.to_string_lossy());
+        }
+    }
+    println!("most liked:");
+    for i in stats.most_liked.iter() {
+        println!(
+            "    {} likes: {} ({:?} {})",
+            i.likes,
+            i.name.as_deref().unwrap_or("-"),
+            i.typ,
+            i.id
+        );
+    }
+    println!("most interacted:");
+    for i in stats.most_interacted.iter() {
+        println!(
+            "    {} interactions: {} ({:?} {})",
+            i.interactions,
+            i.name.as_deref().unwrap_or("-"),
+            i.typ,
+            i.id
+        );
+    }
+    println!(
+        "updaters: {} enabled ({} stale), {} disabled",
+        stats.updaters.enabled, stats.updaters.stale, stats.updaters.disabled
+    );
+    println!(
+        "database: {} (wal: {})",
+        stats::human_bytes(stats.db_bytes),
+        stats::human_bytes(stats.wal_bytes)
+    );
+}
+
 #[cfg(target_os = "android")]
 pub fn command(cmd: config::FeCommand) -> anyhow::Result<()> {
     tokio::runtime::Runtime::new()?.block_on(async move {
@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+
+use sea_orm::{
+    sea_query::Expr, ActiveValue, ColumnTrait, Condition, ConnectionTrait, EntityTrait,
+    QueryFilter, QueryOrder, QuerySelect,
+};
+
+use crate::covau_types::{Play, PlayEvent, PlayMatches, PlayQuery};
+use crate::db::{object, play, DbId, Typ};
+
+/// adds a play to the history. returns its id
+pub async fn log<C: ConnectionTrait>(conn: &C, event: &PlayEvent) -> anyhow::Result<DbId> {
+    let song = object::Entity::find_by_id(event.song)
+        .one(conn)
+        .await?
+        .ok_or(anyhow::anyhow!("song {} not found", event.song))?;
+    if !matches!(song.typ, Typ::Song | Typ::MmSong) {
+        return Err(anyhow::anyhow!(
+            "item {} is not a song ({:?})",
+            event.song,
+            song.typ
+        ));
+    }
+
+    let am = play::ActiveModel {
+        id: ActiveValue::NotSet,
+        song_id: ActiveValue::Set(event.song),
+        queue_id: ActiveValue::Set(event.queue),
+        started_ts: ActiveValue::Set(event.started_ts as _),
+        listened_secs: ActiveValue::Set(event.listened_secs as _),
+        skipped: ActiveValue::Set(event.skipped),
+        source: ActiveValue::Set(serde_json::to_string(&event.source)?),
+        autoplay: ActiveValue::Set(event.autoplay),
+    };
+    let res = play::Entity::insert(am).exec(conn).await?;
+    Ok(res.last_insert_id)
+}
+
+pub async fn query<C: ConnectionTrait>(conn: &C, query: PlayQuery) -> anyhow::Result<PlayMatches> {
+    let mut q = play::Entity::find()
+        .filter(play::Column::StartedTs.gte(query.after as i64))
+        .filter(play::Column::StartedTs.lt(query.before as i64));
+    if let Some(song) = query.song {
+        q = q.filter(play::Column::SongId.eq(song));
+    }
+
+    // keyset pagination on (started_ts, id). same as [crate::db::Db::query]
+    if let Some(cursor) = query.cursor.as_deref() {
+        let (ts, id) = cursor
+            .split_once('|')
+            .ok_or(anyhow::anyhow!("invalid play cursor"))?;
+        let (ts, id) = (ts.parse::<i64>()?, id.parse::<DbId>()?);
+        q = q.filter(
+            Condition::any()
+                .add(play::Column::StartedTs.lt(ts))
+                .add(play::Column::StartedTs.eq(ts).and(play::Column::Id.lt(id))),
+        );
+    }
+
+    let models = q
+        .order_by_desc(play::Column::StartedTs)
+        .order_by_desc(play::Column::Id)
+        .limit(query.page_size as u64)
+        .all(conn)
+        .await?;
+
+    let cursor = models.last().map(|m| format!("{}|{}", m.started_ts, m.id));
+    let full = models.len() == query.page_size as usize;
+    let items = models
+        .into_iter()
+        .filter_map(|m| match serde_json::from_str(&m.source) {
+            Ok(source) => Some(Play {
+                id: m.id,
+                event: PlayEvent {
+                    song: m.song_id,
+                    queue: m.queue_id,
+                    started_ts: m.started_ts as _,
+                    listened_secs: m.listened_secs as _,
+                    skipped: m.skipped,
+                    source,
+                    autoplay: m.autoplay,
+                },
+            }),
+            Err(e) => {
+                eprintln!("skipping bad play {}: {}", m.id, e);
+                None
+            }
+        })
+        .collect();
+
+    Ok(PlayMatches {
+        items,
+        continuation: full.then_some(PlayQuery { cursor, ..query }),
+    })
+}
+
+/// plays of the keys now count as plays of the values
+pub async fn redirect<C: ConnectionTrait>(
+    conn: &C,
+    ids: &HashMap<DbId, DbId>,
+) -> anyhow::Result<()> {
+    for (&from, &to) in ids.iter() {
+        let _ = play::Entity::update_many()
+            .col_expr(play::Column::SongId, Expr::value(to))
+            .filter(play::Column::SongId.eq(from))
+            .exec(conn)
+            .await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,515 @@
+use sea_orm::Iterable;
+
+use crate::covau_types::{
+    ArtistBlacklist, LocalState, Playlist, Queue, SmartPlaylist, Song, SongBlacklist, SourcePath,
+    Updater,
+};
+use crate::db::{
+    check_page_size, parse_search_cursor, search_cursor, Db, DbAble, DbId, DbItem, DbMetadata,
+    HaystackField, ScoredItem, Typ, TypMatches, UniversalContinuation, UniversalMatches,
+    UniversalQuery,
+};
+use crate::mbz::{self, RecordingWithInfo};
+use crate::musimanager as mm;
+use crate::yt::song_tube as st;
+
+type MmSong = mm::Song<Option<mm::SongInfo>, SourcePath>;
+type MmAlbum = mm::Album<crate::yt::VideoId>;
+type MmArtist = mm::Artist<crate::yt::VideoId, crate::yt::AlbumId>;
+type MmPlaylist = mm::Playlist<crate::yt::VideoId>;
+type MmQueue = mm::Queue<crate::yt::VideoId>;
+
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+/// a parsed search query. eg: artist:"arijit" title:tum -live liked:yes added:>2024-01-01
+///
+/// - plain words and "quoted phrases" fuzzy match anything
+/// - title: artist: album: fuzzy match only that field
+/// - a leading - drops items that contain the word (or the field value)
+/// - liked:yes|no
+/// - added: and updated: take a date with an optional >, >=, <, <= in front
+///
+/// unknown qualifiers are plain words
+#[derive(Clone, Debug, Default)]
+pub struct SearchTerms {
+    text: String,
+    fields: Vec<(HaystackField, String)>,
+    // lowercase
+    excluded: Vec<(Option<HaystackField>, String)>,
+    liked: Option<bool>,
+    // [start, end) and if items inside are dropped instead
+    added: Vec<(u64, u64, bool)>,
+    updated: Vec<(u64, u64, bool)>,
+}
+
+impl SearchTerms {
+    pub fn parse(query: &str) -> anyhow::Result<Self> {
+        let mut terms = Self::default();
+        let mut words = vec![];
+        for (neg, key, value) in tokens(query) {
+            let field = match key.as_deref() {
+                Some("title") => Some(HaystackField::Title),
+                Some("artist") => Some(HaystackField::Artist),
+                Some("album") => Some(HaystackField::Album),
+                _ => None,
+            };
+            match key.as_deref() {
+                Some("liked") => {
+                    let liked = match value.to_lowercase().as_str() {
+                        "yes" | "true" => true,
+                        "no" | "false" => false,
+                        _ => return Err(anyhow::anyhow!("invalid value for liked: '{}'", value)),
+                    };
+                    terms.liked = Some(liked != neg);
+                }
+                Some("added") => terms.added.push(date_range(&value, neg)?),
+                Some("updated") => terms.updated.push(date_range(&value, neg)?),
+                _ if neg => terms.excluded.push((field, value.to_lowercase())),
+                _ => match field {
+                    Some(field) => terms.fields.push((field, value)),
+                    None => words.push(value),
+                },
+            }
+        }
+        terms.text = words.join(" ");
+        Ok(terms)
+    }
+
+    /// what the search index can use to find candidates
+    pub fn index_needle(&self) -> String {
+        let mut needle = self.text.clone();
+        for (_, value) in self.fields.iter() {
+            needle.push(' ');
+            needle.push_str(value);
+        }
+        needle
+    }
+
+    /// None if the item does not match. text that does not fuzzy match at all is not a match
+    pub fn score<T: DbAble>(&self, t: &T, m: &DbMetadata) -> Option<isize> {
+        if self.liked.is_some_and(|liked| liked != (m.likes > 0)) {
+            return None;
+        }
+        let in_range = |ranges: &[(u64, u64, bool)], ts: u64| {
+            ranges
+                .iter()
+                .all(|&(start, end, neg)| (start <= ts && ts < end) != neg)
+        };
+        if !in_range(&self.added, m.added_ts) || !in_range(&self.updated, m.updated_ts) {
+            return None;
+        }
+
+        let hs = t.haystack_fields().into_iter().collect::<Vec<_>>();
+        let dropped = self.excluded.iter().any(|(field, word)| {
+            hs.iter()
+                .filter(|(f, _)| field.is_none_or(|field| field == *f))
+                .any(|(_, h)| h.to_lowercase().contains(word))
+        });
+        if dropped {
+            return None;
+        }
+
+        let fuzzy = |needle: &str, field: Option<HaystackField>| -> isize {
+            hs.iter()
+                .filter(|(f, _)| field.is_none_or(|field| field == *f))
+                .map(|(_, h)| {
+                    sublime_fuzzy::best_match(needle, h)
+                        .map(|m| m.score())
+                        .unwrap_or(0)
+                })
+                .sum()
+        };
+        let mut score = 0;
+        if !self.text.is_empty() {
+            score += Some(fuzzy(&self.text, None)).filter(|&s| s > 0)?;
+        }
+        for (field, value) in self.fields.iter() {
+            score += Some(fuzzy(value, Some(*field))).filter(|&s| s > 0)?;
+        }
+        Some(score)
+    }
+}
+
+/// (negated, qualifier, value) per word. quotes keep spaces in a value
+fn tokens(query: &str) -> Vec<(bool, Option<String>, String)> {
+    const KEYS: [&str; 6] = ["title", "artist", "album", "liked", "added", "updated"];
+
+    let mut tokens = vec![];
+    let mut chars = query.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let neg = chars.next_if_eq(&'-').is_some();
+        let mut key = None;
+        let mut value = String::new();
+        let mut quoted = false;
+        let mut seen_quote = false;
+        while let Some(c) = chars.next_if(|&c| quoted || !c.is_whitespace()) {
+            match c {
+                '"' => {
+                    quoted = !quoted;
+                    seen_quote = true;
+                }
+                ':' if key.is_none()
+                    && !seen_quote
+                    && KEYS.contains(&value.to_lowercase().as_str()) =>
+                {
+                    key = Some(value.to_lowercase());
+                    value.clear();
+                }
+                c => value.push(c),
+            }
+        }
+        if !value.is_empty() {
+            tokens.push((neg, key, value));
+        }
+    }
+    tokens
+}
+
+/// [start, end) of the timestamps that match a date like >=2024-01-01
+fn date_range(value: &str, neg: bool) -> anyhow::Result<(u64, u64, bool)> {
+    let (op, date) = ["<=", ">=", "<", ">", "="]
+        .into_iter()
+        .find_map(|op| value.strip_prefix(op).map(|d| (op, d)))
+        .unwrap_or(("=", value));
+    let day = crate::analytics::parse_date(date)?;
+    let range = match op {
+        "<=" => (0, day + DAY_SECS),
+        ">=" => (day, u64::MAX),
+        "<" => (0, day),
+        ">" => (day + DAY_SECS, u64::MAX),
+        _ => (day, day + DAY_SECS),
+    };
+    Ok((range.0, range.1, neg))
+}
+
+/// searches all the typs at once, and ranks the matches of every typ with the same score
+/// as [Db::search]
+pub async fn search_all(
+    db: &Db,
+    query: UniversalQuery,
+) -> anyhow::Result<UniversalMatches<serde_json::Value>> {
+    let (typs, needle, page_size, cursor) = match query {
+        UniversalQuery::Query {
+            typs,
+            page_size,
+            query,
+        } => (
+            typs.unwrap_or_else(|| Typ::iter().collect()),
+            query,
+            page_size,
+            None,
+        ),
+        UniversalQuery::Continuation(c) => {
+            let cursor = parse_search_cursor(&c.cont)?;
+            (c.typs, c.query, c.page_size, Some(cursor))
+        }
+    };
+    if typs.is_empty() {
+        return Err(anyhow::anyhow!("no typs to search"));
+    }
+    let page_size = check_page_size(page_size)?;
+
+    // every typ has to send its best page, as all of them might rank above the others
+    let searches = typs
+        .iter()
+        .map(|typ| search_typ(db, typ.clone(), &needle, page_size, cursor));
+    let mut matches = futures::future::try_join_all(searches)
+        .await?
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+
+    // ids are unique across typs, so (score, id) orders all of them
+    matches.sort_by_key(|(score, item)| std::cmp::Reverse((*score, item.id)));
+    matches.truncate(page_size as usize);
+
+    let continuation = (matches.len() == page_size as usize)
+        .then(|| matches.last())
+        .flatten()
+        .map(|(s, item)| UniversalContinuation {
+            typs: typs.clone(),
+            page_size,
+            query: needle.clone(),
+            cont: search_cursor(*s, item.id),
+        });
+
+    let mut groups: Vec<TypMatches<serde_json::Value>> = vec![];
+    for (score, item) in matches {
+        let scored = ScoredItem {
+            score: score.clamp(i32::MIN as _, i32::MAX as _) as _,
+            item,
+        };
+        match groups.iter_mut().find(|g| g.typ == scored.item.typ) {
+            Some(g) => g.items.push(scored),
+            None => groups.push(TypMatches {
+                typ: scored.item.typ.clone(),
+                items: vec![scored],
+            }),
+        }
+    }
+
+    Ok(UniversalMatches {
+        groups,
+        continuation,
+    })
+}
+
+async fn search_typ(
+    db: &Db,
+    typ: Typ,
+    needle: &str,
+    page_size: u32,
+    cursor: Option<(isize, DbId)>,
+) -> anyhow::Result<Vec<(isize, DbItem<serde_json::Value>)>> {
+    let matches = match typ {
+        Typ::MmSong => scored::<MmSong>(db, needle, page_size, cursor).await?,
+        Typ::MmAlbum => scored::<MmAlbum>(db, needle, page_size, cursor).await?,
+        Typ::MmArtist => scored::<MmArtist>(db, needle, page_size, cursor).await?,
+        Typ::MmPlaylist => scored::<MmPlaylist>(db, needle, page_size, cursor).await?,
+        Typ::MmQueue => scored::<MmQueue>(db, needle, page_size, cursor).await?,
+        Typ::LocalState => scored::<LocalState>(db, needle, page_size, cursor).await?,
+        Typ::Song => scored::<Song>(db, needle, page_size, cursor).await?,
+        Typ::Playlist => scored::<Playlist>(db, needle, page_size, cursor).await?,
+        Typ::Queue => scored::<Queue>(db, needle, page_size, cursor).await?,
+        Typ::ArtistBlacklist => scored::<ArtistBlacklist>(db, needle, page_size, cursor).await?,
+        Typ::SongBlacklist => scored::<SongBlacklist>(db, needle, page_size, cursor).await?,
+        Typ::Updater => scored::<Updater>(db, needle, page_size, cursor).await?,
+        Typ::SmartPlaylist => scored::<SmartPlaylist>(db, needle, page_size, cursor).await?,
+        Typ::StSong => scored::<st::Song>(db, needle, page_size, cursor).await?,
+        Typ::StAlbum => scored::<st::Album>(db, needle, page_size, cursor).await?,
+        Typ::StPlaylist => scored::<st::Playlist>(db, needle, page_size, cursor).await?,
+        Typ::StArtist => scored::<st::Artist>(db, needle, page_size, cursor).await?,
+        Typ::MbzRecording => scored::<RecordingWithInfo>(db, needle, page_size, cursor).await?,
+        Typ::MbzArtist => scored::<mbz::Artist>(db, needle, page_size, cursor).await?,
+    };
+    Ok(matches)
+}
+
+async fn scored<T: DbAble>(
+    db: &Db,
+    needle: &str,
+    page_size: u32,
+    cursor: Option<(isize, DbId)>,
+) -> anyhow::Result<Vec<(isize, DbItem<serde_json::Value>)>> {
+    let matches = db
+        .search_scored::<T>(needle, page_size, cursor)
+        .await?
+        .into_iter()
+        .map(|(s, item)| -> anyhow::Result<_> {
+            let item = DbItem {
+                metadata: item.metadata,
+                id: item.id,
+                typ: item.typ,
+                t: serde_json::to_value(item.t)?,
+            };
+            Ok((s, item))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::covau_types::Artist;
+
+    // 2024-01-01 00:00 utc
+    const DAY: u64 = 1704067200;
+
+    fn tok(neg: bool, key: Option<&str>, value: &str) -> (bool, Option<String>, String) {
+        (neg, key.map(String::from), value.into())
+    }
+
+    #[test]
+    fn tokenizer() {
+        let cases = [
+            ("", vec![]),
+            ("   ", vec![]),
+            ("-", vec![]),
+            (
+                "tum hi ho",
+                vec![
+                    tok(false, None, "tum"),
+                    tok(false, None, "hi"),
+                    tok(false, None, "ho"),
+                ],
+            ),
+            (
+                "\"tum hi\" ho",
+                vec![tok(false, None, "tum hi"), tok(false, None, "ho")],
+            ),
+            (
+                "artist:\"arijit singh\"",
+                vec![tok(false, Some("artist"), "arijit singh")],
+            ),
+            (
+                "-title:\"x y\" -live",
+                vec![tok(true, Some("title"), "x y"), tok(true, None, "live")],
+            ),
+            ("Title:Tum", vec![tok(false, Some("title"), "Tum")]),
+            ("mood:happy", vec![tok(false, None, "mood:happy")]),
+            ("\"title:tum\"", vec![tok(false, None, "title:tum")]),
+            ("artist:a:b", vec![tok(false, Some("artist"), "a:b")]),
+            ("\"open quote", vec![tok(false, None, "open quote")]),
+            (
+                "liked:yes added:>=2024-01-01",
+                vec![
+                    tok(false, Some("liked"), "yes"),
+                    tok(false, Some("added"), ">=2024-01-01"),
+                ],
+            ),
+        ];
+        for (query, expected) in cases {
+            assert_eq!(tokens(query), expected, "query: {:?}", query);
+        }
+    }
+
+    #[test]
+    fn date_ranges() {
+        let cases = [
+            ("2024-01-01", (DAY, DAY + DAY_SECS)),
+            ("=2024-01-01", (DAY, DAY + DAY_SECS)),
+            (">=2024-01-01", (DAY, u64::MAX)),
+            (">2024-01-01", (DAY + DAY_SECS, u64::MAX)),
+            ("<2024-01-01", (0, DAY)),
+            ("<=2024-01-01", (0, DAY + DAY_SECS)),
+            ("2024-02-29", (DAY + 59 * DAY_SECS, DAY + 60 * DAY_SECS)),
+        ];
+        for (value, (start, end)) in cases {
+            assert_eq!(
+                date_range(value, false).unwrap(),
+                (start, end, false),
+                "{}",
+                value
+            );
+            assert_eq!(
+                date_range(value, true).unwrap(),
+                (start, end, true),
+                "{}",
+                value
+            );
+        }
+
+        for value in [
+            "2024-02-30",
+            "2023-02-29",
+            "2024-04-31",
+            "2024-13-01",
+            "2024-00-10",
+            "1969-12-31",
+            "2024-1",
+            ">=",
+            "yesterday",
+        ] {
+            assert!(date_range(value, false).is_err(), "{}", value);
+        }
+    }
+
+    fn song(title: &str, artist: &str) -> Song {
+        Song {
+            title: title.into(),
+            artists: vec![Artist {
+                name: artist.into(),
+                source: None,
+            }],
+            thumbnails: vec![],
+            info_sources: vec![],
+            play_sources: vec![],
+        }
+    }
+
+    fn metadata(likes: u32, added_ts: u64) -> DbMetadata {
+        DbMetadata {
+            likes,
+            added_ts,
+            updated_ts: added_ts,
+            ..DbMetadata::new()
+        }
+    }
+
+    #[test]
+    fn score() {
+        let s = song("Tum Hi Ho", "Arijit Singh");
+        let liked = metadata(1, DAY + 10);
+        let not_liked = metadata(0, DAY - 10);
+
+        // (query, matches liked, matches not liked)
+        let cases = [
+            ("", true, true),
+            ("tum", true, true),
+            ("nothing", false, false),
+            ("title:tum", true, true),
+            ("artist:tum", false, false),
+            ("-live", true, true),
+            ("-tum", false, false),
+            ("-artist:arijit", false, false),
+            ("-title:arijit", true, true),
+            ("-title:\"tum hi\"", false, false),
+            ("liked:yes", true, false),
+            ("liked:no", false, true),
+            ("-liked:yes", false, true),
+            ("-liked:no", true, false),
+            ("added:>=2024-01-01", true, false),
+            ("added:<2024-01-01", false, true),
+            ("added:2024-01-01", true, false),
+            ("-added:2024-01-01", false, true),
+            ("updated:>2023-12-31 -updated:>=2024-01-02", true, false),
+            ("tum liked:yes added:2024-01-01", true, false),
+        ];
+        for (query, on_liked, on_not_liked) in cases {
+            let terms = SearchTerms::parse(query).unwrap();
+            assert_eq!(
+                terms.score(&s, &liked).is_some(),
+                on_liked,
+                "{} (liked)",
+                query
+            );
+            assert_eq!(
+                terms.score(&s, &not_liked).is_some(),
+                on_not_liked,
+                "{} (not liked)",
+                query
+            );
+        }
+
+        assert!(SearchTerms::parse("liked:maybe").is_err());
+        assert!(SearchTerms::parse("added:2024-02-31").is_err());
+    }
+
+    #[test]
+    fn boost() {
+        let boost = crate::db::SearchBoost {
+            likes: 20,
+            interactions: 10,
+        };
+        let m = |likes, interactions| DbMetadata {
+            likes,
+            interactions,
+            ..DbMetadata::new()
+        };
+
+        // (likes, interactions, score)
+        let cases = [
+            (0, 0, 0),
+            (2, 0, 40),
+            (0, 1, 10),
+            (0, 3, 20),
+            (0, u32::MAX, 310),
+            (1, 7, 50),
+        ];
+        for (likes, interactions, score) in cases {
+            assert_eq!(
+                boost.score(&m(likes, interactions)),
+                score,
+                "{} {}",
+                likes,
+                interactions
+            );
+        }
+    }
+}
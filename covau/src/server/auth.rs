@@ -0,0 +1,389 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use warp::filters::BoxedFilter;
+use warp::http::{HeaderMap, HeaderValue};
+use warp::{reply::Reply, Filter};
+
+use crate::config::DerivedConfig;
+use crate::db::Db;
+use crate::server::custom_reject;
+
+const PAIRING_SECS: u64 = 5 * 60;
+const PAIRING_ATTEMPTS: u32 = 5;
+
+/// request without a valid token
+#[derive(Debug)]
+pub struct Unauthorized;
+impl warp::reject::Reject for Unauthorized {}
+
+/// request from a web page that is not allowed to use the server
+#[derive(Debug)]
+pub struct ForbiddenOrigin(pub String);
+impl warp::reject::Reject for ForbiddenOrigin {}
+
+#[derive(Clone, Debug, Serialize, Deserialize, specta::Type)]
+pub struct PairedDevice {
+    pub name: String,
+    #[serde(with = "crate::covau_types::serde_with_string")]
+    pub paired_ts: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, specta::Type)]
+pub struct PairingCode {
+    pub code: String,
+    #[serde(with = "crate::covau_types::serde_with_string")]
+    pub expires_ts: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, specta::Type)]
+pub struct PairRequest {
+    pub code: String,
+    /// shows up in the list of paired devices. pairing again with the same name replaces it
+    pub name: String,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct AuthFile {
+    /// the install token. only local clients get it
+    token: String,
+    devices: Vec<DeviceToken>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct DeviceToken {
+    name: String,
+    token: String,
+    paired_ts: u64,
+}
+
+struct Pairing {
+    code: String,
+    expires_ts: u64,
+    attempts: u32,
+}
+
+struct AuthState {
+    path: PathBuf,
+    file: Mutex<AuthFile>,
+    pairing: Mutex<Option<Pairing>>,
+    // web pages (other than the server's own) that can use the server
+    origins: Vec<String>,
+}
+
+/// every request needs a token. either the install token (stored in the data dir) or one
+/// that a paired device got
+#[derive(Clone)]
+pub struct Auth(Arc<AuthState>);
+
+impl Auth {
+    /// reads the tokens or creates them on first start
+    pub fn load(config: &DerivedConfig) -> anyhow::Result<Self> {
+        let path = config.data_path.join("auth.json");
+        let file = if path.exists() {
+            let s = std::fs::read_to_string(&path)?;
+            serde_json::from_str(&s).context("could not parse auth.json")?
+        } else {
+            let file = AuthFile {
+                token: new_token(),
+                devices: vec![],
+            };
+            write_file(&path, &file)?;
+            file
+        };
+
+        let mut origins = vec![
+            format!("http://localhost:{}", config.server_port),
+            format!("http://127.0.0.1:{}", config.server_port),
+        ];
+        #[cfg(build_mode = "DEV")]
+        origins.extend([
+            format!("http://localhost:{}", config.dev_vite_port),
+            format!("http://127.0.0.1:{}", config.dev_vite_port),
+        ]);
+        origins.extend(config.config.allowed_origins.iter().flatten().cloned());
+
+        Ok(Self(Arc::new(AuthState {
+            path,
+            file: Mutex::new(file),
+            pairing: Mutex::new(None),
+            origins,
+        })))
+    }
+
+    async fn is_valid(&self, token: &str, local_only: bool) -> bool {
+        let file = self.0.file.lock().await;
+        eq(&file.token, token) || (!local_only && file.devices.iter().any(|d| eq(&d.token, token)))
+    }
+
+    /// rejects requests without a valid token. the token goes in the authorization header
+    /// or in the token query param (websockets, audio and images can't set headers)
+    pub fn guard(&self) -> BoxedFilter<()> {
+        self.guard_impl(false)
+    }
+
+    /// only the install token can manage devices
+    fn guard_local(&self) -> BoxedFilter<()> {
+        self.guard_impl(true)
+    }
+
+    fn guard_impl(&self, local_only: bool) -> BoxedFilter<()> {
+        let auth = self.clone();
+        warp::header::headers_cloned()
+            .and(warp::query::<HashMap<String, String>>())
+            .and(warp::any().map(move || auth.clone()))
+            .and_then(
+                move |headers: HeaderMap, query: HashMap<String, String>, auth: Auth| async move {
+                    let token = headers
+                        .get("authorization")
+                        .and_then(|h| h.to_str().ok())
+                        .and_then(|h| h.strip_prefix("Bearer "))
+                        .or(query.get("token").map(String::as_str));
+                    match token {
+                        Some(t) if auth.is_valid(t, local_only).await => Ok(()),
+                        _ => Err(warp::reject::custom(Unauthorized)),
+                    }
+                },
+            )
+            .untuple_one()
+            .boxed()
+    }
+
+    fn origin_allowed(&self, origin: &str, host: Option<&str>) -> bool {
+        // same origin. this is how devices on the lan load the ui
+        let same = host.is_some_and(|h| {
+            origin.strip_prefix("http://") == Some(h) || origin.strip_prefix("https://") == Some(h)
+        });
+        same || self.0.origins.iter().any(|o| o == origin)
+    }
+
+    /// the origin of requests from allowed web pages. rejects the rest.
+    /// requests without an origin are not from web pages (or are plain same origin GETs)
+    pub fn origin(&self) -> BoxedFilter<(Option<String>,)> {
+        let auth = self.clone();
+        warp::header::optional::<String>("origin")
+            .and(warp::header::optional::<String>("host"))
+            .and_then(move |origin: Option<String>, host: Option<String>| {
+                let allowed = origin
+                    .as_deref()
+                    .map(|o| auth.origin_allowed(o, host.as_deref()))
+                    .unwrap_or(true);
+                async move {
+                    match origin {
+                        Some(o) if !allowed => Err(warp::reject::custom(ForbiddenOrigin(o))),
+                        o => Ok(o),
+                    }
+                }
+            })
+            .boxed()
+    }
+
+    async fn start_pairing(&self) -> PairingCode {
+        let code = format!("{:06}", uuid::Uuid::new_v4().as_u128() % 1_000_000);
+        let expires_ts = Db::timestamp() + PAIRING_SECS;
+        *self.0.pairing.lock().await = Some(Pairing {
+            code: code.clone(),
+            expires_ts,
+            attempts: 0,
+        });
+        PairingCode { code, expires_ts }
+    }
+
+    /// a new token for the device if the code is right
+    async fn claim(&self, req: PairRequest) -> anyhow::Result<String> {
+        let mut pairing = self.0.pairing.lock().await;
+        let p = pairing
+            .as_mut()
+            .filter(|p| p.expires_ts > Db::timestamp())
+            .context("no device is being paired right now")?;
+        if !eq(&p.code, req.code.trim()) {
+            p.attempts += 1;
+            // a 6 digit code can't survive guessing for long
+            if p.attempts >= PAIRING_ATTEMPTS {
+                *pairing = None;
+                return Err(anyhow::anyhow!("wrong code. too many attempts, pair again"));
+            }
+            return Err(anyhow::anyhow!("wrong code"));
+        }
+        *pairing = None;
+        drop(pairing);
+
+        let token = new_token();
+        let mut file = self.0.file.lock().await;
+        file.devices.retain(|d| d.name != req.name);
+        file.devices.push(DeviceToken {
+            name: req.name,
+            token: token.clone(),
+            paired_ts: Db::timestamp(),
+        });
+        write_file(&self.0.path, &file)?;
+        Ok(token)
+    }
+
+    async fn devices(&self) -> Vec<PairedDevice> {
+        self.0
+            .file
+            .lock()
+            .await
+            .devices
+            .iter()
+            .map(|d| PairedDevice {
+                name: d.name.clone(),
+                paired_ts: d.paired_ts,
+            })
+            .collect()
+    }
+
+    async fn revoke(&self, name: &str) -> anyhow::Result<()> {
+        let mut file = self.0.file.lock().await;
+        let n = file.devices.len();
+        file.devices.retain(|d| d.name != name);
+        if file.devices.len() == n {
+            return Err(anyhow::anyhow!("no paired device named '{}'", name));
+        }
+        write_file(&self.0.path, &file)?;
+        Ok(())
+    }
+
+    pub fn routes(&self, path: &'static str) -> BoxedFilter<(impl Reply,)> {
+        let auth = self.clone();
+        let with_auth = move || {
+            let auth = auth.clone();
+            warp::any().map(move || auth.clone())
+        };
+
+        // the install token for uis on this machine. the host check stops dns rebinding
+        let local = warp::path(path)
+            .and(warp::path("local"))
+            .and(warp::path::end())
+            .and(warp::post())
+            .and(warp::addr::remote())
+            .and(warp::header::optional::<String>("host"))
+            .and(with_auth())
+            .and_then(
+                |addr: Option<SocketAddr>, host: Option<String>, auth: Auth| async move {
+                    let local_addr = addr.is_some_and(|a| a.ip().is_loopback());
+                    let local_host = host.as_deref().is_some_and(is_loopback_host);
+                    if !local_addr || !local_host {
+                        return Err(warp::reject::custom(Unauthorized));
+                    }
+                    let token = auth.0.file.lock().await.token.clone();
+                    Ok::<_, warp::Rejection>(warp::reply::json(&token))
+                },
+            );
+
+        let check = warp::path(path)
+            .and(warp::path("check"))
+            .and(warp::path::end())
+            .and(warp::post())
+            .and(self.guard())
+            .map(|| warp::reply::json(&()));
+
+        let pair = warp::path(path)
+            .and(warp::path("pair"))
+            .and(warp::path::end())
+            .and(warp::post())
+            .and(self.guard_local())
+            .and(with_auth())
+            .then(|auth: Auth| async move { warp::reply::json(&auth.start_pairing().await) });
+
+        let claim = warp::path(path)
+            .and(warp::path("claim"))
+            .and(warp::path::end())
+            .and(warp::post())
+            .and(warp::body::json())
+            .and(with_auth())
+            .and_then(|req: PairRequest, auth: Auth| async move {
+                let token = auth.claim(req).await.map_err(custom_reject)?;
+                Ok::<_, warp::Rejection>(warp::reply::json(&token))
+            });
+
+        let devices = warp::path(path)
+            .and(warp::path("devices"))
+            .and(warp::path::end())
+            .and(warp::post())
+            .and(self.guard_local())
+            .and(with_auth())
+            .then(|auth: Auth| async move { warp::reply::json(&auth.devices().await) });
+
+        let revoke = warp::path(path)
+            .and(warp::path("revoke"))
+            .and(warp::path::end())
+            .and(warp::post())
+            .and(self.guard_local())
+            .and(warp::body::json())
+            .and(with_auth())
+            .and_then(|name: String, auth: Auth| async move {
+                auth.revoke(&name).await.map_err(custom_reject)?;
+                Ok::<_, warp::Rejection>(warp::reply::json(&()))
+            });
+
+        local
+            .or(check)
+            .or(pair)
+            .or(claim)
+            .or(devices)
+            .or(revoke)
+            .boxed()
+    }
+}
+
+/// cors headers for allowed web pages
+pub fn with_cors(origin: Option<String>, reply: impl Reply) -> warp::reply::Response {
+    let mut res = reply.into_response();
+    let Some(origin) = origin.and_then(|o| HeaderValue::from_str(&o).ok()) else {
+        return res;
+    };
+    let headers = res.headers_mut();
+    headers.insert("access-control-allow-origin", origin);
+    headers.insert("vary", HeaderValue::from_static("origin"));
+    headers.insert(
+        "access-control-allow-headers",
+        HeaderValue::from_static("content-type, authorization"),
+    );
+    headers.insert(
+        "access-control-allow-methods",
+        HeaderValue::from_static("GET, POST, PUT, PATCH, DELETE"),
+    );
+    res
+}
+
+fn is_loopback_host(host: &str) -> bool {
+    let name = match host.rsplit_once(':') {
+        Some((name, port)) if port.chars().all(|c| c.is_ascii_digit()) => name,
+        _ => host,
+    };
+    matches!(name, "localhost" | "127.0.0.1" | "[::1]")
+}
+
+fn new_token() -> String {
+    format!(
+        "{}{}",
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    )
+}
+
+// does not leak how much of the token matched
+fn eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+fn write_file(path: &PathBuf, file: &AuthFile) -> anyhow::Result<()> {
+    std::fs::write(path, serde_json::to_string_pretty(file)?)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
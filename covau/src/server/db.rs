@@ -1,17 +1,22 @@
+use std::collections::{hash_map::Entry, HashMap, HashSet};
 use std::sync::{
     atomic::{AtomicU32, Ordering},
     Arc,
 };
 
-use sea_orm::ConnectionTrait;
+use sea_orm::{ConnectionTrait, DatabaseTransaction, TransactionTrait};
 use serde::{Deserialize, Serialize};
+use warp::{filters::BoxedFilter, reply::Reply, Filter};
 
 use crate::{
-    db::{Db, DbAble, DbId, DbItem, DbMetadata, SearchQuery, TransactionId, Typ},
-    server::{ErrorMessage, MessageResult},
+    db::{
+        Db, DbAble, DbEvent, DbId, DbItem, DbMetadata, DbQuery, DbSubscription, Linked,
+        SearchQuery, TransactionId, Typ, UniversalQuery,
+    },
+    server::{custom_reject, ErrorKind, ErrorMessage, MessageResult},
 };
 
-use super::message_server::MessageServerRequest;
+use super::message_server::{ConnectionId, MessageServerRequest, MessageStream};
 
 #[derive(Clone, Debug, Serialize, Deserialize, specta::Type)]
 #[serde(tag = "type", content = "content")]
@@ -20,6 +25,19 @@ pub enum InsertResponse<T> {
     Old(T),
 }
 
+// per item result of the *Many requests
+#[derive(Clone, Debug, Serialize, Deserialize, specta::Type)]
+#[serde(tag = "type", content = "content")]
+pub enum BatchResult<T> {
+    Ok(T),
+    Err(ErrorMessage),
+}
+impl<T> BatchResult<T> {
+    fn error(err: impl Into<anyhow::Error>) -> Self {
+        Self::Err(ErrorMessage::from(&err.into()))
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, specta::Type)]
 #[serde(tag = "type", content = "content")]
 pub enum DbRequest {
@@ -42,20 +60,94 @@ pub enum DbRequest {
         transaction_id: TransactionId,
         item: DbItem<String>,
     },
+    // every item is of this typ. 1 BatchResult per item (in the same order).
+    // an item that fails leaves nothing behind, the rest still go through
+    InsertManyOrGet {
+        transaction_id: TransactionId,
+        typ: Typ,
+        items: Vec<String>,
+    },
+    UpdateMany {
+        transaction_id: TransactionId,
+        typ: Typ,
+        items: Vec<DbItem<String>>,
+    },
+    DeleteMany {
+        transaction_id: TransactionId,
+        typ: Typ,
+        items: Vec<DbItem<String>>,
+    },
     UpdateMetadata {
         transaction_id: TransactionId,
         id: crate::db::DbId,
         typ: Typ,
         metadata: crate::db::DbMetadata,
     },
+    // moves the item to the trash
     Delete {
         transaction_id: TransactionId,
         item: DbItem<String>,
     },
+    GetTrash {
+        typ: Typ,
+    },
+    Restore {
+        transaction_id: TransactionId,
+        typ: Typ,
+        id: DbId,
+    },
+    // deletes a trashed item for good
+    Purge {
+        transaction_id: TransactionId,
+        id: DbId,
+    },
+    // older versions of the item. newest first
+    GetRevisions {
+        typ: Typ,
+        id: DbId,
+    },
+    // 'to' is the current version if None
+    DiffRevisions {
+        id: DbId,
+        from: u32,
+        to: Option<u32>,
+    },
+    Revert {
+        transaction_id: TransactionId,
+        typ: Typ,
+        id: DbId,
+        update_counter: u32,
+    },
+    // songs that look like the same track
+    GetDuplicates,
+    // folds duplicates (Song or MmSong) into survivor (a Song)
+    MergeSongs {
+        transaction_id: TransactionId,
+        survivor: DbId,
+        duplicates: Vec<DbId>,
+    },
+    // adds a play to the history (in a transaction of its own). responds with its id
+    LogPlay(crate::covau_types::PlayEvent),
+    GetPlays(crate::covau_types::PlayQuery),
+    // plays that started in [after, before)
+    GetListeningStats {
+        #[serde(with = "crate::covau_types::serde_with_string")]
+        after: u64,
+        #[serde(with = "crate::covau_types::serde_with_string")]
+        before: u64,
+    },
+    // songs that match the rules right now. the playlist does not have to be saved
+    EvaluateSmartPlaylist(crate::covau_types::SmartPlaylist),
     Search {
         typ: Typ,
         query: SearchQuery,
     },
+    // one ranked search over many typs
+    SearchAll(UniversalQuery),
+    Query {
+        typ: Typ,
+        query: DbQuery,
+    },
     GetByRefid {
         typ: Typ,
         refid: String,
@@ -78,6 +170,23 @@ pub enum DbRequest {
     GetManyUntypedById {
         ids: Vec<DbId>,
     },
+    // items of typ 'to' linked from items of typ 'from' with these refids
+    // (musimanager artists have no refids, so nothing can be looked up through them)
+    GetLinkedFrom {
+        from: Typ,
+        to: Typ,
+        refids: Vec<String>,
+    },
+    // items of typ 'from' that link to items of typ 'to' with these refids
+    GetLinkedTo {
+        from: Typ,
+        to: Typ,
+        refids: Vec<String>,
+    },
+    // responds with DbEvent (OkMany) after every commit that touches these items.
+    // until Unsubscribe (with the request id of this Subscribe) or the websocket closes
+    Subscribe(DbSubscription),
+    Unsubscribe(u32),
 }
 
 type LocalState = crate::covau_types::LocalState;
@@ -87,6 +196,7 @@ type Queue = crate::covau_types::Queue;
 type ArtistBlacklist = crate::covau_types::ArtistBlacklist;
 type SongBlacklist = crate::covau_types::SongBlacklist;
 type Updater = crate::covau_types::Updater;
+type SmartPlaylist = crate::covau_types::SmartPlaylist;
 type StSong = crate::yt::song_tube::Song;
 type StAlbum = crate::yt::song_tube::Album;
 type StPlaylist = crate::yt::song_tube::Playlist;
@@ -100,21 +210,22 @@ type MmArtist = crate::musimanager::Artist<crate::yt::VideoId, crate::yt::AlbumI
 type MbzRecording = crate::mbz::RecordingWithInfo;
 type MbzArtist = crate::mbz::Artist;
 
-#[async_trait::async_trait]
-impl MessageServerRequest for DbRequest {
-    type Ctx = Db;
-
-    async fn handle(
+impl DbRequest {
+    /// connection is None for requests that do not come from a websocket
+    pub(crate) async fn handle_request(
         self,
-        db: Self::Ctx,
+        db: Db,
         id_src: Arc<AtomicU32>,
+        connection: Option<ConnectionId>,
     ) -> anyhow::Result<MessageResult<String>> {
         async fn insert<T: DbAble>(
+            db: &Db,
             txn: &impl ConnectionTrait,
             data: String,
         ) -> anyhow::Result<MessageResult<String>> {
             let item: T = serde_json::from_str(&data)?;
             let id = item.insert(txn).await?;
+            db.push_event(DbEvent::Inserted { id, typ: T::typ() });
             let dbitem = DbItem {
                 metadata: crate::db::DbMetadata::new(),
                 id,
@@ -124,6 +235,7 @@ impl MessageServerRequest for DbRequest {
             Ok(MessageResult::OkOne(dbitem).json())
         }
         async fn insert_or_get<T: DbAble>(
+            db: &Db,
             txn: &impl ConnectionTrait,
             data: String,
         ) -> anyhow::Result<MessageResult<String>> {
@@ -133,6 +245,7 @@ impl MessageServerRequest for DbRequest {
                 Some(e) => InsertResponse::Old(e),
                 None => {
                     let id = item.insert(txn).await?;
+                    db.push_event(DbEvent::Inserted { id, typ: T::typ() });
                     let dbitem = DbItem {
                         metadata: crate::db::DbMetadata::new(),
                         id,
@@ -145,11 +258,17 @@ impl MessageServerRequest for DbRequest {
             Ok(MessageResult::OkOne(dbitem).json())
         }
         async fn update<T: DbAble>(
+            db: &Db,
             txn: &impl ConnectionTrait,
             data: DbItem<String>,
         ) -> anyhow::Result<MessageResult<String>> {
             let item: DbItem<T> = data.parsed()?;
             let meta = item.update(txn).await?;
+            db.push_event(DbEvent::Updated {
+                id: item.id,
+                typ: T::typ(),
+                metadata: meta.clone(),
+            });
             let dbitem = DbItem {
                 metadata: meta,
                 id: item.id,
@@ -158,22 +277,231 @@ impl MessageServerRequest for DbRequest {
             };
             Ok(MessageResult::OkOne(dbitem).json())
         }
+        // items that don't parse, are already in the db or don't match what's in the db fail
+        // on their own before anything is written. the writes for the rest are batched in
+        // one savepoint (instead of one per item), so an error from the db undoes the whole
+        // request and not just some of it
+        async fn insert_many_or_get<T: DbAble>(
+            db: &Db,
+            txn: &DatabaseTransaction,
+            data: Vec<String>,
+        ) -> anyhow::Result<MessageResult<String>> {
+            let items = data
+                .iter()
+                .map(|d| serde_json::from_str::<T>(d))
+                .collect::<Vec<_>>();
+            let refids = items
+                .iter()
+                .flatten()
+                .flat_map(|t| t.refids())
+                .collect::<HashSet<_>>()
+                .into_iter()
+                .collect::<Vec<_>>();
+            let found = Db::get_many_by_ref_id::<T, _>(txn, &refids).await?;
+
+            enum Slot<T> {
+                Done(BatchResult<InsertResponse<DbItem<T>>>),
+                // index into new items
+                New(usize),
+                // same thing as a new item before it in this batch
+                Same(usize),
+            }
+            let mut slots = vec![];
+            let mut new = vec![];
+            let mut new_refids = HashMap::new();
+            for item in items {
+                let item = match item {
+                    Ok(t) => t,
+                    Err(e) => {
+                        slots.push(Slot::Done(BatchResult::error(e)));
+                        continue;
+                    }
+                };
+                let refids = item.refids().into_iter().collect::<Vec<_>>();
+                if let Some(old) = refids.iter().find_map(|r| found.get(r)) {
+                    slots.push(Slot::Done(BatchResult::Ok(InsertResponse::Old(
+                        old.clone(),
+                    ))));
+                    continue;
+                }
+                if let Some(&i) = refids.iter().find_map(|r| new_refids.get(r)) {
+                    slots.push(Slot::Same(i));
+                    continue;
+                }
+                for r in refids {
+                    new_refids.insert(r, new.len());
+                }
+                slots.push(Slot::New(new.len()));
+                new.push(item);
+            }
+
+            let sp = txn.begin().await?;
+            let ids = crate::db::insert_objects(&sp, &new.iter().collect::<Vec<_>>()).await?;
+            sp.commit().await?;
+
+            let new = ids
+                .into_iter()
+                .zip(new)
+                .map(|(id, t)| {
+                    db.push_event(DbEvent::Inserted { id, typ: T::typ() });
+                    DbItem {
+                        metadata: crate::db::DbMetadata::new(),
+                        id,
+                        typ: T::typ(),
+                        t,
+                    }
+                })
+                .collect::<Vec<_>>();
+            let res = slots
+                .into_iter()
+                .map(|s| match s {
+                    Slot::Done(res) => res,
+                    Slot::New(i) => BatchResult::Ok(InsertResponse::New(new[i].clone())),
+                    Slot::Same(i) => BatchResult::Ok(InsertResponse::Old(new[i].clone())),
+                })
+                .collect::<Vec<_>>();
+            Ok(MessageResult::OkOne(res).json())
+        }
+        async fn update_many<T: DbAble>(
+            db: &Db,
+            txn: &DatabaseTransaction,
+            data: Vec<DbItem<String>>,
+        ) -> anyhow::Result<MessageResult<String>> {
+            let items = data
+                .into_iter()
+                .map(|item| item.parsed::<T>())
+                .collect::<Vec<_>>();
+            let parsed = items.iter().flatten().cloned().collect::<Vec<DbItem<T>>>();
+
+            let sp = txn.begin().await?;
+            let updated = DbItem::update_many(&sp, &parsed).await?;
+            sp.commit().await?;
+
+            let mut updated = parsed.into_iter().zip(updated);
+            let res = items
+                .into_iter()
+                .map(|item| {
+                    if let Err(e) = item {
+                        return BatchResult::error(e);
+                    }
+                    match updated.next().expect("one result per parsed item") {
+                        (item, Ok(meta)) => {
+                            db.push_event(DbEvent::Updated {
+                                id: item.id,
+                                typ: T::typ(),
+                                metadata: meta.clone(),
+                            });
+                            BatchResult::Ok(DbItem {
+                                metadata: meta,
+                                ..item
+                            })
+                        }
+                        (_, Err(e)) => BatchResult::error(e),
+                    }
+                })
+                .collect::<Vec<_>>();
+            Ok(MessageResult::OkOne(res).json())
+        }
+        async fn delete_many<T: DbAble>(
+            db: &Db,
+            txn: &DatabaseTransaction,
+            data: Vec<DbItem<String>>,
+        ) -> anyhow::Result<MessageResult<String>> {
+            let items = data
+                .into_iter()
+                .map(|item| item.parsed::<T>())
+                .collect::<Vec<_>>();
+            let parsed = items.iter().flatten().cloned().collect::<Vec<DbItem<T>>>();
+
+            let sp = txn.begin().await?;
+            let deleted = DbItem::delete_many(&sp, &parsed).await?;
+            sp.commit().await?;
+
+            let mut deleted = parsed.into_iter().zip(deleted);
+            let res = items
+                .into_iter()
+                .map(|item| {
+                    if let Err(e) = item {
+                        return BatchResult::error(e);
+                    }
+                    match deleted.next().expect("one result per parsed item") {
+                        (item, Ok(())) => {
+                            db.push_event(DbEvent::Deleted {
+                                id: item.id,
+                                typ: T::typ(),
+                            });
+                            BatchResult::Ok(())
+                        }
+                        (_, Err(e)) => BatchResult::error(e),
+                    }
+                })
+                .collect::<Vec<_>>();
+            Ok(MessageResult::OkOne(res).json())
+        }
         async fn update_metadata<T: DbAble>(
+            db: &Db,
             txn: &impl ConnectionTrait,
             id: DbId,
             mdata: DbMetadata,
         ) -> anyhow::Result<MessageResult<String>> {
             let mdata = T::update_mdata(txn, id, mdata).await?;
+            db.push_event(DbEvent::Updated {
+                id,
+                typ: T::typ(),
+                metadata: mdata.clone(),
+            });
             Ok(MessageResult::OkOne(mdata).json())
         }
         async fn delete<T: DbAble>(
+            db: &Db,
             txn: &impl ConnectionTrait,
             data: DbItem<String>,
         ) -> anyhow::Result<MessageResult<String>> {
             let item: DbItem<T> = data.parsed()?;
             item.delete(txn).await?;
+            db.push_event(DbEvent::Deleted {
+                id: item.id,
+                typ: T::typ(),
+            });
             Ok(MessageResult::OkOne(()).json())
         }
+        async fn restore<T: DbAble>(
+            db: &Db,
+            txn: &impl ConnectionTrait,
+            id: DbId,
+        ) -> anyhow::Result<MessageResult<String>> {
+            let item = Db::restore::<T, _>(txn, id).await?;
+            db.push_event(DbEvent::Inserted {
+                id: item.id,
+                typ: T::typ(),
+            });
+            Ok(MessageResult::OkOne(item).json())
+        }
+        async fn revert<T: DbAble>(
+            db: &Db,
+            txn: &impl ConnectionTrait,
+            id: DbId,
+            update_counter: u32,
+        ) -> anyhow::Result<MessageResult<String>> {
+            let item = Db::revert::<T, _>(txn, id, update_counter).await?;
+            db.push_event(DbEvent::Updated {
+                id: item.id,
+                typ: T::typ(),
+                metadata: item.metadata.clone(),
+            });
+            Ok(MessageResult::OkOne(item).json())
+        }
+        async fn get_revisions<T: DbAble>(
+            db: Db,
+            id: DbId,
+        ) -> anyhow::Result<MessageResult<String>> {
+            let res = db.revisions::<T>(id).await?;
+            Ok(MessageResult::OkOne(res).json())
+        }
+        async fn get_trash<T: DbAble>(db: Db) -> anyhow::Result<MessageResult<String>> {
+            let res = db.search_trash::<T>().await?;
+            Ok(MessageResult::OkOne(res).json())
+        }
         async fn search<T: DbAble>(
             db: Db,
             query: SearchQuery,
@@ -181,6 +509,13 @@ impl MessageServerRequest for DbRequest {
             let res = db.search::<T>(query).await?;
             Ok(MessageResult::OkOne(res).json())
         }
+        async fn db_query<T: DbAble>(
+            db: Db,
+            query: DbQuery,
+        ) -> anyhow::Result<MessageResult<String>> {
+            let res = db.query::<T>(query).await?;
+            Ok(MessageResult::OkOne(res).json())
+        }
         async fn get_by_refid<T: DbAble>(
             db: Db,
             refid: String,
@@ -218,6 +553,21 @@ impl MessageServerRequest for DbRequest {
             Ok(MessageResult::OkOne(res).json())
         }
 
+        async fn get_linked_from<From: DbAble + Linked<To>, To: DbAble>(
+            db: Db,
+            refids: Vec<String>,
+        ) -> anyhow::Result<MessageResult<String>> {
+            let res = db.search_linked_from::<From, To>(refids).await?;
+            Ok(MessageResult::OkOne(res).json())
+        }
+        async fn get_linked_to<From: DbAble + Linked<To>, To: DbAble>(
+            db: Db,
+            refids: Vec<String>,
+        ) -> anyhow::Result<MessageResult<String>> {
+            let res = db.search_linked_to::<From, To>(refids).await?;
+            Ok(MessageResult::OkOne(res).json())
+        }
+
         let res = match self {
             DbRequest::NewId => {
                 let id = id_src.fetch_add(1, Ordering::Relaxed);
@@ -229,15 +579,15 @@ impl MessageServerRequest for DbRequest {
                 .json()
             }
             DbRequest::Begin => {
-                let id = db.begin().await?;
+                let id = db.begin(connection).await?;
                 MessageResult::OkOne(id).json()
             }
             DbRequest::Commit(id) => {
-                db.commit(id).await?;
+                db.commit(id, connection).await?;
                 MessageResult::OkOne(()).json()
             }
             DbRequest::Rollback(id) => {
-                db.rollback(id).await?;
+                db.rollback(id, connection).await?;
                 MessageResult::OkOne(()).json()
             }
             DbRequest::Insert {
@@ -245,53 +595,41 @@ impl MessageServerRequest for DbRequest {
                 typ,
                 item,
             } => {
-                let txn = db.transaction.lock().await;
-                match txn.as_ref() {
-                    Some((tid, txn)) => {
-                        if *tid != transaction_id {
-                            let msg = "Transaction Inactive";
-                            MessageResult::Err(ErrorMessage {
-                                message: msg.into(),
-                                stack_trace: msg.into(),
-                            })
-                        } else {
-                            match typ {
-                                Typ::LocalState => {
-                                    let msg = "Operation Not Allowed";
-                                    MessageResult::Err(ErrorMessage {
-                                        message: msg.into(),
-                                        stack_trace: msg.into(),
-                                    })
-                                }
-                                Typ::MmSong => insert::<MmSong>(txn, item).await?,
-                                Typ::MmAlbum => insert::<MmAlbum>(txn, item).await?,
-                                Typ::MmArtist => insert::<MmArtist>(txn, item).await?,
-                                Typ::MmPlaylist => insert::<MmPlaylist>(txn, item).await?,
-                                Typ::MmQueue => insert::<MmQueue>(txn, item).await?,
-                                Typ::Song => insert::<Song>(txn, item).await?,
-                                Typ::Playlist => insert::<Playlist>(txn, item).await?,
-                                Typ::Queue => insert::<Queue>(txn, item).await?,
-                                Typ::ArtistBlacklist => {
-                                    insert::<ArtistBlacklist>(txn, item).await?
-                                }
-                                Typ::SongBlacklist => insert::<SongBlacklist>(txn, item).await?,
-                                Typ::Updater => insert::<Updater>(txn, item).await?,
-                                Typ::StSong => insert::<StSong>(txn, item).await?,
-                                Typ::StAlbum => insert::<StAlbum>(txn, item).await?,
-                                Typ::StPlaylist => insert::<StPlaylist>(txn, item).await?,
-                                Typ::StArtist => insert::<StArtist>(txn, item).await?,
-                                Typ::MbzRecording => insert::<MbzRecording>(txn, item).await?,
-                                Typ::MbzArtist => insert::<MbzArtist>(txn, item).await?,
+                let mut txn = db.lock_transaction().await?;
+                match txn.as_mut() {
+                    Some(t) => match t.access(transaction_id, connection) {
+                        Err(msg) => MessageResult::Err(ErrorMessage::new(ErrorKind::Conflict, msg)),
+                        Ok(txn) => match typ {
+                            Typ::LocalState => MessageResult::Err(ErrorMessage::new(
+                                ErrorKind::NotAllowed,
+                                "Operation Not Allowed",
+                            )),
+                            Typ::MmSong => insert::<MmSong>(&db, txn, item).await?,
+                            Typ::MmAlbum => insert::<MmAlbum>(&db, txn, item).await?,
+                            Typ::MmArtist => insert::<MmArtist>(&db, txn, item).await?,
+                            Typ::MmPlaylist => insert::<MmPlaylist>(&db, txn, item).await?,
+                            Typ::MmQueue => insert::<MmQueue>(&db, txn, item).await?,
+                            Typ::Song => insert::<Song>(&db, txn, item).await?,
+                            Typ::Playlist => insert::<Playlist>(&db, txn, item).await?,
+                            Typ::Queue => insert::<Queue>(&db, txn, item).await?,
+                            Typ::ArtistBlacklist => {
+                                insert::<ArtistBlacklist>(&db, txn, item).await?
                             }
-                        }
-                    }
-                    None => {
-                        let msg = "No Transaction Active";
-                        MessageResult::Err(ErrorMessage {
-                            message: msg.into(),
-                            stack_trace: msg.into(),
-                        })
-                    }
+                            Typ::SongBlacklist => insert::<SongBlacklist>(&db, txn, item).await?,
+                            Typ::Updater => insert::<Updater>(&db, txn, item).await?,
+                            Typ::SmartPlaylist => insert::<SmartPlaylist>(&db, txn, item).await?,
+                            Typ::StSong => insert::<StSong>(&db, txn, item).await?,
+                            Typ::StAlbum => insert::<StAlbum>(&db, txn, item).await?,
+                            Typ::StPlaylist => insert::<StPlaylist>(&db, txn, item).await?,
+                            Typ::StArtist => insert::<StArtist>(&db, txn, item).await?,
+                            Typ::MbzRecording => insert::<MbzRecording>(&db, txn, item).await?,
+                            Typ::MbzArtist => insert::<MbzArtist>(&db, txn, item).await?,
+                        },
+                    },
+                    None => MessageResult::Err(ErrorMessage::new(
+                        ErrorKind::Conflict,
+                        "No Transaction Active",
+                    )),
                 }
             }
             DbRequest::InsertOrGet {
@@ -299,104 +637,238 @@ impl MessageServerRequest for DbRequest {
                 typ,
                 item,
             } => {
-                let txn = db.transaction.lock().await;
-                match txn.as_ref() {
-                    Some((tid, txn)) => {
-                        if *tid != transaction_id {
-                            let msg = "Transaction Inactive";
-                            MessageResult::Err(ErrorMessage {
-                                message: msg.into(),
-                                stack_trace: msg.into(),
-                            })
-                        } else {
-                            match typ {
-                                Typ::LocalState => {
-                                    let msg = "Operation Not Allowed";
-                                    MessageResult::Err(ErrorMessage {
-                                        message: msg.into(),
-                                        stack_trace: msg.into(),
-                                    })
-                                }
-                                Typ::MmSong => insert_or_get::<MmSong>(txn, item).await?,
-                                Typ::MmAlbum => insert_or_get::<MmAlbum>(txn, item).await?,
-                                Typ::MmArtist => insert_or_get::<MmArtist>(txn, item).await?,
-                                Typ::MmPlaylist => insert_or_get::<MmPlaylist>(txn, item).await?,
-                                Typ::MmQueue => insert_or_get::<MmQueue>(txn, item).await?,
-                                Typ::Song => insert_or_get::<Song>(txn, item).await?,
-                                Typ::Playlist => insert_or_get::<Playlist>(txn, item).await?,
-                                Typ::Queue => insert_or_get::<Queue>(txn, item).await?,
-                                Typ::ArtistBlacklist => {
-                                    insert_or_get::<ArtistBlacklist>(txn, item).await?
-                                }
-                                Typ::SongBlacklist => {
-                                    insert_or_get::<SongBlacklist>(txn, item).await?
-                                }
-                                Typ::Updater => insert_or_get::<Updater>(txn, item).await?,
-                                Typ::StSong => insert_or_get::<StSong>(txn, item).await?,
-                                Typ::StAlbum => insert_or_get::<StAlbum>(txn, item).await?,
-                                Typ::StPlaylist => insert_or_get::<StPlaylist>(txn, item).await?,
-                                Typ::StArtist => insert_or_get::<StArtist>(txn, item).await?,
-                                Typ::MbzRecording => {
-                                    insert_or_get::<MbzRecording>(txn, item).await?
-                                }
-                                Typ::MbzArtist => insert_or_get::<MbzArtist>(txn, item).await?,
+                let mut txn = db.lock_transaction().await?;
+                match txn.as_mut() {
+                    Some(t) => match t.access(transaction_id, connection) {
+                        Err(msg) => MessageResult::Err(ErrorMessage::new(ErrorKind::Conflict, msg)),
+                        Ok(txn) => match typ {
+                            Typ::LocalState => MessageResult::Err(ErrorMessage::new(
+                                ErrorKind::NotAllowed,
+                                "Operation Not Allowed",
+                            )),
+                            Typ::MmSong => insert_or_get::<MmSong>(&db, txn, item).await?,
+                            Typ::MmAlbum => insert_or_get::<MmAlbum>(&db, txn, item).await?,
+                            Typ::MmArtist => insert_or_get::<MmArtist>(&db, txn, item).await?,
+                            Typ::MmPlaylist => insert_or_get::<MmPlaylist>(&db, txn, item).await?,
+                            Typ::MmQueue => insert_or_get::<MmQueue>(&db, txn, item).await?,
+                            Typ::Song => insert_or_get::<Song>(&db, txn, item).await?,
+                            Typ::Playlist => insert_or_get::<Playlist>(&db, txn, item).await?,
+                            Typ::Queue => insert_or_get::<Queue>(&db, txn, item).await?,
+                            Typ::ArtistBlacklist => {
+                                insert_or_get::<ArtistBlacklist>(&db, txn, item).await?
                             }
-                        }
-                    }
-                    None => {
-                        let msg = "No Transaction Active";
-                        MessageResult::Err(ErrorMessage {
-                            message: msg.into(),
-                            stack_trace: msg.into(),
-                        })
-                    }
+                            Typ::SongBlacklist => {
+                                insert_or_get::<SongBlacklist>(&db, txn, item).await?
+                            }
+                            Typ::Updater => insert_or_get::<Updater>(&db, txn, item).await?,
+                            Typ::SmartPlaylist => {
+                                insert_or_get::<SmartPlaylist>(&db, txn, item).await?
+                            }
+                            Typ::StSong => insert_or_get::<StSong>(&db, txn, item).await?,
+                            Typ::StAlbum => insert_or_get::<StAlbum>(&db, txn, item).await?,
+                            Typ::StPlaylist => insert_or_get::<StPlaylist>(&db, txn, item).await?,
+                            Typ::StArtist => insert_or_get::<StArtist>(&db, txn, item).await?,
+                            Typ::MbzRecording => {
+                                insert_or_get::<MbzRecording>(&db, txn, item).await?
+                            }
+                            Typ::MbzArtist => insert_or_get::<MbzArtist>(&db, txn, item).await?,
+                        },
+                    },
+                    None => MessageResult::Err(ErrorMessage::new(
+                        ErrorKind::Conflict,
+                        "No Transaction Active",
+                    )),
                 }
             }
             DbRequest::Update {
                 transaction_id,
                 item,
             } => {
-                let txn = db.transaction.lock().await;
-                match txn.as_ref() {
-                    Some((tid, txn)) => {
-                        if *tid != transaction_id {
-                            let msg = "Transaction Inactive";
-                            MessageResult::Err(ErrorMessage {
-                                message: msg.into(),
-                                stack_trace: msg.into(),
-                            })
-                        } else {
-                            match item.typ {
-                                Typ::MmSong => update::<MmSong>(txn, item).await?,
-                                Typ::MmAlbum => update::<MmAlbum>(txn, item).await?,
-                                Typ::MmArtist => update::<MmArtist>(txn, item).await?,
-                                Typ::MmPlaylist => update::<MmPlaylist>(txn, item).await?,
-                                Typ::MmQueue => update::<MmQueue>(txn, item).await?,
-                                Typ::LocalState => update::<LocalState>(txn, item).await?,
-                                Typ::Song => update::<Song>(txn, item).await?,
-                                Typ::Playlist => update::<Playlist>(txn, item).await?,
-                                Typ::Queue => update::<Queue>(txn, item).await?,
-                                Typ::ArtistBlacklist => {
-                                    update::<ArtistBlacklist>(txn, item).await?
-                                }
-                                Typ::SongBlacklist => update::<SongBlacklist>(txn, item).await?,
-                                Typ::Updater => update::<Updater>(txn, item).await?,
-                                Typ::StSong => update::<StSong>(txn, item).await?,
-                                Typ::StAlbum => update::<StAlbum>(txn, item).await?,
-                                Typ::StPlaylist => update::<StPlaylist>(txn, item).await?,
-                                Typ::StArtist => update::<StArtist>(txn, item).await?,
-                                Typ::MbzRecording => update::<MbzRecording>(txn, item).await?,
-                                Typ::MbzArtist => update::<MbzArtist>(txn, item).await?,
+                let mut txn = db.lock_transaction().await?;
+                match txn.as_mut() {
+                    Some(t) => match t.access(transaction_id, connection) {
+                        Err(msg) => MessageResult::Err(ErrorMessage::new(ErrorKind::Conflict, msg)),
+                        Ok(txn) => match item.typ {
+                            Typ::MmSong => update::<MmSong>(&db, txn, item).await?,
+                            Typ::MmAlbum => update::<MmAlbum>(&db, txn, item).await?,
+                            Typ::MmArtist => update::<MmArtist>(&db, txn, item).await?,
+                            Typ::MmPlaylist => update::<MmPlaylist>(&db, txn, item).await?,
+                            Typ::MmQueue => update::<MmQueue>(&db, txn, item).await?,
+                            Typ::LocalState => update::<LocalState>(&db, txn, item).await?,
+                            Typ::Song => update::<Song>(&db, txn, item).await?,
+                            Typ::Playlist => update::<Playlist>(&db, txn, item).await?,
+                            Typ::Queue => update::<Queue>(&db, txn, item).await?,
+                            Typ::ArtistBlacklist => {
+                                update::<ArtistBlacklist>(&db, txn, item).await?
                             }
-                        }
-                    }
-                    None => {
-                        let msg = "No Transaction Active";
-                        MessageResult::Err(ErrorMessage {
-                            message: msg.into(),
-                            stack_trace: msg.into(),
-                        })
-                    }
+                            Typ::SongBlacklist => update::<SongBlacklist>(&db, txn, item).await?,
+                            Typ::Updater => update::<Updater>(&db, txn, item).await?,
+                            Typ::SmartPlaylist => update::<SmartPlaylist>(&db, txn, item).await?,
+                            Typ::StSong => update::<StSong>(&db, txn, item).await?,
+                            Typ::StAlbum => update::<StAlbum>(&db, txn, item).await?,
+                            Typ::StPlaylist => update::<StPlaylist>(&db, txn, item).await?,
+                            Typ::StArtist => update::<StArtist>(&db, txn, item).await?,
+                            Typ::MbzRecording => update::<MbzRecording>(&db, txn, item).await?,
+                            Typ::MbzArtist => update::<MbzArtist>(&db, txn, item).await?,
+                        },
+                    },
+                    None => MessageResult::Err(ErrorMessage::new(
+                        ErrorKind::Conflict,
+                        "No Transaction Active",
+                    )),
+                }
+            }
+            DbRequest::InsertManyOrGet {
+                transaction_id,
+                typ,
+                items,
+            } => {
+                let mut txn = db.lock_transaction().await?;
+                match txn.as_mut() {
+                    Some(t) => match t.access(transaction_id, connection) {
+                        Err(msg) => MessageResult::Err(ErrorMessage::new(ErrorKind::Conflict, msg)),
+                        Ok(txn) => match typ {
+                            Typ::MmSong => insert_many_or_get::<MmSong>(&db, txn, items).await?,
+                            Typ::MmAlbum => insert_many_or_get::<MmAlbum>(&db, txn, items).await?,
+                            Typ::MmArtist => {
+                                insert_many_or_get::<MmArtist>(&db, txn, items).await?
+                            }
+                            Typ::MmPlaylist => {
+                                insert_many_or_get::<MmPlaylist>(&db, txn, items).await?
+                            }
+                            Typ::MmQueue => insert_many_or_get::<MmQueue>(&db, txn, items).await?,
+                            Typ::LocalState => MessageResult::Err(ErrorMessage::new(
+                                ErrorKind::NotAllowed,
+                                "Operation Not Allowed",
+                            )),
+                            Typ::Song => insert_many_or_get::<Song>(&db, txn, items).await?,
+                            Typ::Playlist => {
+                                insert_many_or_get::<Playlist>(&db, txn, items).await?
+                            }
+                            Typ::Queue => insert_many_or_get::<Queue>(&db, txn, items).await?,
+                            Typ::ArtistBlacklist => {
+                                insert_many_or_get::<ArtistBlacklist>(&db, txn, items).await?
+                            }
+                            Typ::SongBlacklist => {
+                                insert_many_or_get::<SongBlacklist>(&db, txn, items).await?
+                            }
+                            Typ::Updater => insert_many_or_get::<Updater>(&db, txn, items).await?,
+                            Typ::SmartPlaylist => {
+                                insert_many_or_get::<SmartPlaylist>(&db, txn, items).await?
+                            }
+                            Typ::StSong => insert_many_or_get::<StSong>(&db, txn, items).await?,
+                            Typ::StAlbum => insert_many_or_get::<StAlbum>(&db, txn, items).await?,
+                            Typ::StPlaylist => {
+                                insert_many_or_get::<StPlaylist>(&db, txn, items).await?
+                            }
+                            Typ::StArtist => {
+                                insert_many_or_get::<StArtist>(&db, txn, items).await?
+                            }
+                            Typ::MbzRecording => {
+                                insert_many_or_get::<MbzRecording>(&db, txn, items).await?
+                            }
+                            Typ::MbzArtist => {
+                                insert_many_or_get::<MbzArtist>(&db, txn, items).await?
+                            }
+                        },
+                    },
+                    None => MessageResult::Err(ErrorMessage::new(
+                        ErrorKind::Conflict,
+                        "No Transaction Active",
+                    )),
+                }
+            }
+            DbRequest::UpdateMany {
+                transaction_id,
+                typ,
+                items,
+            } => {
+                let mut txn = db.lock_transaction().await?;
+                match txn.as_mut() {
+                    Some(t) => match t.access(transaction_id, connection) {
+                        Err(msg) => MessageResult::Err(ErrorMessage::new(ErrorKind::Conflict, msg)),
+                        Ok(txn) => match typ {
+                            Typ::MmSong => update_many::<MmSong>(&db, txn, items).await?,
+                            Typ::MmAlbum => update_many::<MmAlbum>(&db, txn, items).await?,
+                            Typ::MmArtist => update_many::<MmArtist>(&db, txn, items).await?,
+                            Typ::MmPlaylist => update_many::<MmPlaylist>(&db, txn, items).await?,
+                            Typ::MmQueue => update_many::<MmQueue>(&db, txn, items).await?,
+                            Typ::LocalState => update_many::<LocalState>(&db, txn, items).await?,
+                            Typ::Song => update_many::<Song>(&db, txn, items).await?,
+                            Typ::Playlist => update_many::<Playlist>(&db, txn, items).await?,
+                            Typ::Queue => update_many::<Queue>(&db, txn, items).await?,
+                            Typ::ArtistBlacklist => {
+                                update_many::<ArtistBlacklist>(&db, txn, items).await?
+                            }
+                            Typ::SongBlacklist => {
+                                update_many::<SongBlacklist>(&db, txn, items).await?
+                            }
+                            Typ::Updater => update_many::<Updater>(&db, txn, items).await?,
+                            Typ::SmartPlaylist => {
+                                update_many::<SmartPlaylist>(&db, txn, items).await?
+                            }
+                            Typ::StSong => update_many::<StSong>(&db, txn, items).await?,
+                            Typ::StAlbum => update_many::<StAlbum>(&db, txn, items).await?,
+                            Typ::StPlaylist => update_many::<StPlaylist>(&db, txn, items).await?,
+                            Typ::StArtist => update_many::<StArtist>(&db, txn, items).await?,
+                            Typ::MbzRecording => {
+                                update_many::<MbzRecording>(&db, txn, items).await?
+                            }
+                            Typ::MbzArtist => update_many::<MbzArtist>(&db, txn, items).await?,
+                        },
+                    },
+                    None => MessageResult::Err(ErrorMessage::new(
+                        ErrorKind::Conflict,
+                        "No Transaction Active",
+                    )),
+                }
+            }
+            DbRequest::DeleteMany {
+                transaction_id,
+                typ,
+                items,
+            } => {
+                let mut txn = db.lock_transaction().await?;
+                match txn.as_mut() {
+                    Some(t) => match t.access(transaction_id, connection) {
+                        Err(msg) => MessageResult::Err(ErrorMessage::new(ErrorKind::Conflict, msg)),
+                        Ok(txn) => match typ {
+                            Typ::MmSong => delete_many::<MmSong>(&db, txn, items).await?,
+                            Typ::MmAlbum => delete_many::<MmAlbum>(&db, txn, items).await?,
+                            Typ::MmArtist => delete_many::<MmArtist>(&db, txn, items).await?,
+                            Typ::MmPlaylist => delete_many::<MmPlaylist>(&db, txn, items).await?,
+                            Typ::MmQueue => delete_many::<MmQueue>(&db, txn, items).await?,
+                            Typ::LocalState => MessageResult::Err(ErrorMessage::new(
+                                ErrorKind::NotAllowed,
+                                "Operation Not Allowed",
+                            )),
+                            Typ::Song => delete_many::<Song>(&db, txn, items).await?,
+                            Typ::Playlist => delete_many::<Playlist>(&db, txn, items).await?,
+                            Typ::Queue => delete_many::<Queue>(&db, txn, items).await?,
+                            Typ::ArtistBlacklist => {
+                                delete_many::<ArtistBlacklist>(&db, txn, items).await?
+                            }
+                            Typ::SongBlacklist => {
+                                delete_many::<SongBlacklist>(&db, txn, items).await?
+                            }
+                            Typ::Updater => delete_many::<Updater>(&db, txn, items).await?,
+                            Typ::SmartPlaylist => {
+                                delete_many::<SmartPlaylist>(&db, txn, items).await?
+                            }
+                            Typ::StSong => delete_many::<StSong>(&db, txn, items).await?,
+                            Typ::StAlbum => delete_many::<StAlbum>(&db, txn, items).await?,
+                            Typ::StPlaylist => delete_many::<StPlaylist>(&db, txn, items).await?,
+                            Typ::StArtist => delete_many::<StArtist>(&db, txn, items).await?,
+                            Typ::MbzRecording => {
+                                delete_many::<MbzRecording>(&db, txn, items).await?
+                            }
+                            Typ::MbzArtist => delete_many::<MbzArtist>(&db, txn, items).await?,
+                        },
+                    },
+                    None => MessageResult::Err(ErrorMessage::new(
+                        ErrorKind::Conflict,
+                        "No Transaction Active",
+                    )),
                 }
             }
             DbRequest::UpdateMetadata {
@@ -405,128 +877,325 @@ impl MessageServerRequest for DbRequest {
                 typ,
                 metadata,
             } => {
-                let txn = db.transaction.lock().await;
-                match txn.as_ref() {
-                    Some((tid, txn)) => {
-                        if *tid != transaction_id {
-                            let msg = "Transaction Inactive";
-                            MessageResult::Err(ErrorMessage {
-                                message: msg.into(),
-                                stack_trace: msg.into(),
-                            })
-                        } else {
-                            match typ {
-                                Typ::MmSong => update_metadata::<MmSong>(txn, id, metadata).await?,
-                                Typ::MmAlbum => {
-                                    update_metadata::<MmAlbum>(txn, id, metadata).await?
-                                }
-                                Typ::MmArtist => {
-                                    update_metadata::<MmArtist>(txn, id, metadata).await?
-                                }
-                                Typ::MmPlaylist => {
-                                    update_metadata::<MmPlaylist>(txn, id, metadata).await?
-                                }
-                                Typ::MmQueue => {
-                                    update_metadata::<MmQueue>(txn, id, metadata).await?
-                                }
-                                Typ::LocalState => {
-                                    update_metadata::<LocalState>(txn, id, metadata).await?
-                                }
-                                Typ::Song => update_metadata::<Song>(txn, id, metadata).await?,
-                                Typ::Playlist => {
-                                    update_metadata::<Playlist>(txn, id, metadata).await?
-                                }
-                                Typ::Queue => update_metadata::<Queue>(txn, id, metadata).await?,
-                                Typ::ArtistBlacklist => {
-                                    update_metadata::<ArtistBlacklist>(txn, id, metadata).await?
-                                }
-                                Typ::SongBlacklist => {
-                                    update_metadata::<SongBlacklist>(txn, id, metadata).await?
-                                }
-                                Typ::Updater => {
-                                    update_metadata::<Updater>(txn, id, metadata).await?
-                                }
-                                Typ::StSong => update_metadata::<StSong>(txn, id, metadata).await?,
-                                Typ::StAlbum => {
-                                    update_metadata::<StAlbum>(txn, id, metadata).await?
-                                }
-                                Typ::StPlaylist => {
-                                    update_metadata::<StPlaylist>(txn, id, metadata).await?
-                                }
-                                Typ::StArtist => {
-                                    update_metadata::<StArtist>(txn, id, metadata).await?
-                                }
-                                Typ::MbzRecording => {
-                                    update_metadata::<MbzRecording>(txn, id, metadata).await?
-                                }
-                                Typ::MbzArtist => {
-                                    update_metadata::<MbzArtist>(txn, id, metadata).await?
-                                }
+                let mut txn = db.lock_transaction().await?;
+                match txn.as_mut() {
+                    Some(t) => match t.access(transaction_id, connection) {
+                        Err(msg) => MessageResult::Err(ErrorMessage::new(ErrorKind::Conflict, msg)),
+                        Ok(txn) => match typ {
+                            Typ::MmSong => {
+                                update_metadata::<MmSong>(&db, txn, id, metadata).await?
                             }
-                        }
-                    }
-                    None => {
-                        let msg = "No Transaction Active";
-                        MessageResult::Err(ErrorMessage {
-                            message: msg.into(),
-                            stack_trace: msg.into(),
-                        })
-                    }
+                            Typ::MmAlbum => {
+                                update_metadata::<MmAlbum>(&db, txn, id, metadata).await?
+                            }
+                            Typ::MmArtist => {
+                                update_metadata::<MmArtist>(&db, txn, id, metadata).await?
+                            }
+                            Typ::MmPlaylist => {
+                                update_metadata::<MmPlaylist>(&db, txn, id, metadata).await?
+                            }
+                            Typ::MmQueue => {
+                                update_metadata::<MmQueue>(&db, txn, id, metadata).await?
+                            }
+                            Typ::LocalState => {
+                                update_metadata::<LocalState>(&db, txn, id, metadata).await?
+                            }
+                            Typ::Song => update_metadata::<Song>(&db, txn, id, metadata).await?,
+                            Typ::Playlist => {
+                                update_metadata::<Playlist>(&db, txn, id, metadata).await?
+                            }
+                            Typ::Queue => update_metadata::<Queue>(&db, txn, id, metadata).await?,
+                            Typ::ArtistBlacklist => {
+                                update_metadata::<ArtistBlacklist>(&db, txn, id, metadata).await?
+                            }
+                            Typ::SongBlacklist => {
+                                update_metadata::<SongBlacklist>(&db, txn, id, metadata).await?
+                            }
+                            Typ::Updater => {
+                                update_metadata::<Updater>(&db, txn, id, metadata).await?
+                            }
+                            Typ::SmartPlaylist => {
+                                update_metadata::<SmartPlaylist>(&db, txn, id, metadata).await?
+                            }
+                            Typ::StSong => {
+                                update_metadata::<StSong>(&db, txn, id, metadata).await?
+                            }
+                            Typ::StAlbum => {
+                                update_metadata::<StAlbum>(&db, txn, id, metadata).await?
+                            }
+                            Typ::StPlaylist => {
+                                update_metadata::<StPlaylist>(&db, txn, id, metadata).await?
+                            }
+                            Typ::StArtist => {
+                                update_metadata::<StArtist>(&db, txn, id, metadata).await?
+                            }
+                            Typ::MbzRecording => {
+                                update_metadata::<MbzRecording>(&db, txn, id, metadata).await?
+                            }
+                            Typ::MbzArtist => {
+                                update_metadata::<MbzArtist>(&db, txn, id, metadata).await?
+                            }
+                        },
+                    },
+                    None => MessageResult::Err(ErrorMessage::new(
+                        ErrorKind::Conflict,
+                        "No Transaction Active",
+                    )),
                 }
             }
             DbRequest::Delete {
                 transaction_id,
                 item,
             } => {
-                let txn = db.transaction.lock().await;
-                match txn.as_ref() {
-                    Some((tid, txn)) => {
-                        if *tid != transaction_id {
-                            let msg = "Transaction Inactive";
-                            MessageResult::Err(ErrorMessage {
-                                message: msg.into(),
-                                stack_trace: msg.into(),
-                            })
-                        } else {
-                            match item.typ {
-                                Typ::LocalState => {
-                                    let msg = "Operation Not Allowed";
-                                    MessageResult::Err(ErrorMessage {
-                                        message: msg.into(),
-                                        stack_trace: msg.into(),
-                                    })
-                                }
-                                Typ::MmSong => delete::<MmSong>(txn, item).await?,
-                                Typ::MmAlbum => delete::<MmAlbum>(txn, item).await?,
-                                Typ::MmArtist => delete::<MmArtist>(txn, item).await?,
-                                Typ::MmPlaylist => delete::<MmPlaylist>(txn, item).await?,
-                                Typ::MmQueue => delete::<MmQueue>(txn, item).await?,
-                                Typ::Song => delete::<Song>(txn, item).await?,
-                                Typ::Playlist => delete::<Playlist>(txn, item).await?,
-                                Typ::Queue => delete::<Queue>(txn, item).await?,
-                                Typ::ArtistBlacklist => {
-                                    delete::<ArtistBlacklist>(txn, item).await?
-                                }
-                                Typ::SongBlacklist => delete::<SongBlacklist>(txn, item).await?,
-                                Typ::Updater => delete::<Updater>(txn, item).await?,
-                                Typ::StSong => delete::<StSong>(txn, item).await?,
-                                Typ::StAlbum => delete::<StAlbum>(txn, item).await?,
-                                Typ::StPlaylist => delete::<StPlaylist>(txn, item).await?,
-                                Typ::StArtist => delete::<StArtist>(txn, item).await?,
-                                Typ::MbzRecording => delete::<MbzRecording>(txn, item).await?,
-                                Typ::MbzArtist => delete::<MbzArtist>(txn, item).await?,
+                let mut txn = db.lock_transaction().await?;
+                match txn.as_mut() {
+                    Some(t) => match t.access(transaction_id, connection) {
+                        Err(msg) => MessageResult::Err(ErrorMessage::new(ErrorKind::Conflict, msg)),
+                        Ok(txn) => match item.typ {
+                            Typ::LocalState => MessageResult::Err(ErrorMessage::new(
+                                ErrorKind::NotAllowed,
+                                "Operation Not Allowed",
+                            )),
+                            Typ::MmSong => delete::<MmSong>(&db, txn, item).await?,
+                            Typ::MmAlbum => delete::<MmAlbum>(&db, txn, item).await?,
+                            Typ::MmArtist => delete::<MmArtist>(&db, txn, item).await?,
+                            Typ::MmPlaylist => delete::<MmPlaylist>(&db, txn, item).await?,
+                            Typ::MmQueue => delete::<MmQueue>(&db, txn, item).await?,
+                            Typ::Song => delete::<Song>(&db, txn, item).await?,
+                            Typ::Playlist => delete::<Playlist>(&db, txn, item).await?,
+                            Typ::Queue => delete::<Queue>(&db, txn, item).await?,
+                            Typ::ArtistBlacklist => {
+                                delete::<ArtistBlacklist>(&db, txn, item).await?
                             }
+                            Typ::SongBlacklist => delete::<SongBlacklist>(&db, txn, item).await?,
+                            Typ::Updater => delete::<Updater>(&db, txn, item).await?,
+                            Typ::SmartPlaylist => delete::<SmartPlaylist>(&db, txn, item).await?,
+                            Typ::StSong => delete::<StSong>(&db, txn, item).await?,
+                            Typ::StAlbum => delete::<StAlbum>(&db, txn, item).await?,
+                            Typ::StPlaylist => delete::<StPlaylist>(&db, txn, item).await?,
+                            Typ::StArtist => delete::<StArtist>(&db, txn, item).await?,
+                            Typ::MbzRecording => delete::<MbzRecording>(&db, txn, item).await?,
+                            Typ::MbzArtist => delete::<MbzArtist>(&db, txn, item).await?,
+                        },
+                    },
+                    None => MessageResult::Err(ErrorMessage::new(
+                        ErrorKind::Conflict,
+                        "No Transaction Active",
+                    )),
+                }
+            }
+            DbRequest::GetTrash { typ } => match typ {
+                Typ::MmSong => get_trash::<MmSong>(db).await?,
+                Typ::MmAlbum => get_trash::<MmAlbum>(db).await?,
+                Typ::MmArtist => get_trash::<MmArtist>(db).await?,
+                Typ::MmPlaylist => get_trash::<MmPlaylist>(db).await?,
+                Typ::MmQueue => get_trash::<MmQueue>(db).await?,
+                Typ::LocalState => get_trash::<LocalState>(db).await?,
+                Typ::Song => get_trash::<Song>(db).await?,
+                Typ::Playlist => get_trash::<Playlist>(db).await?,
+                Typ::Queue => get_trash::<Queue>(db).await?,
+                Typ::ArtistBlacklist => get_trash::<ArtistBlacklist>(db).await?,
+                Typ::SongBlacklist => get_trash::<SongBlacklist>(db).await?,
+                Typ::Updater => get_trash::<Updater>(db).await?,
+                Typ::SmartPlaylist => get_trash::<SmartPlaylist>(db).await?,
+                Typ::StSong => get_trash::<StSong>(db).await?,
+                Typ::StAlbum => get_trash::<StAlbum>(db).await?,
+                Typ::StPlaylist => get_trash::<StPlaylist>(db).await?,
+                Typ::StArtist => get_trash::<StArtist>(db).await?,
+                Typ::MbzRecording => get_trash::<MbzRecording>(db).await?,
+                Typ::MbzArtist => get_trash::<MbzArtist>(db).await?,
+            },
+            DbRequest::Restore {
+                transaction_id,
+                typ,
+                id,
+            } => {
+                let mut txn = db.lock_transaction().await?;
+                match txn.as_mut() {
+                    Some(t) => match t.access(transaction_id, connection) {
+                        Err(msg) => MessageResult::Err(ErrorMessage::new(ErrorKind::Conflict, msg)),
+                        Ok(txn) => match typ {
+                            Typ::LocalState => MessageResult::Err(ErrorMessage::new(
+                                ErrorKind::NotAllowed,
+                                "Operation Not Allowed",
+                            )),
+                            Typ::MmSong => restore::<MmSong>(&db, txn, id).await?,
+                            Typ::MmAlbum => restore::<MmAlbum>(&db, txn, id).await?,
+                            Typ::MmArtist => restore::<MmArtist>(&db, txn, id).await?,
+                            Typ::MmPlaylist => restore::<MmPlaylist>(&db, txn, id).await?,
+                            Typ::MmQueue => restore::<MmQueue>(&db, txn, id).await?,
+                            Typ::Song => restore::<Song>(&db, txn, id).await?,
+                            Typ::Playlist => restore::<Playlist>(&db, txn, id).await?,
+                            Typ::Queue => restore::<Queue>(&db, txn, id).await?,
+                            Typ::ArtistBlacklist => {
+                                restore::<ArtistBlacklist>(&db, txn, id).await?
+                            }
+                            Typ::SongBlacklist => restore::<SongBlacklist>(&db, txn, id).await?,
+                            Typ::Updater => restore::<Updater>(&db, txn, id).await?,
+                            Typ::SmartPlaylist => restore::<SmartPlaylist>(&db, txn, id).await?,
+                            Typ::StSong => restore::<StSong>(&db, txn, id).await?,
+                            Typ::StAlbum => restore::<StAlbum>(&db, txn, id).await?,
+                            Typ::StPlaylist => restore::<StPlaylist>(&db, txn, id).await?,
+                            Typ::StArtist => restore::<StArtist>(&db, txn, id).await?,
+                            Typ::MbzRecording => restore::<MbzRecording>(&db, txn, id).await?,
+                            Typ::MbzArtist => restore::<MbzArtist>(&db, txn, id).await?,
+                        },
+                    },
+                    None => MessageResult::Err(ErrorMessage::new(
+                        ErrorKind::Conflict,
+                        "No Transaction Active",
+                    )),
+                }
+            }
+            DbRequest::Purge { transaction_id, id } => {
+                let mut txn = db.lock_transaction().await?;
+                match txn.as_mut() {
+                    Some(t) => match t.access(transaction_id, connection) {
+                        Err(msg) => MessageResult::Err(ErrorMessage::new(ErrorKind::Conflict, msg)),
+                        Ok(txn) => {
+                            Db::purge(txn, id).await?;
+                            MessageResult::OkOne(()).json()
                         }
-                    }
-                    None => {
-                        let msg = "No Transaction Active";
-                        MessageResult::Err(ErrorMessage {
-                            message: msg.into(),
-                            stack_trace: msg.into(),
-                        })
-                    }
+                    },
+                    None => MessageResult::Err(ErrorMessage::new(
+                        ErrorKind::Conflict,
+                        "No Transaction Active",
+                    )),
+                }
+            }
+            DbRequest::GetRevisions { typ, id } => match typ {
+                Typ::MmSong => get_revisions::<MmSong>(db, id).await?,
+                Typ::MmAlbum => get_revisions::<MmAlbum>(db, id).await?,
+                Typ::MmArtist => get_revisions::<MmArtist>(db, id).await?,
+                Typ::MmPlaylist => get_revisions::<MmPlaylist>(db, id).await?,
+                Typ::MmQueue => get_revisions::<MmQueue>(db, id).await?,
+                Typ::LocalState => get_revisions::<LocalState>(db, id).await?,
+                Typ::Song => get_revisions::<Song>(db, id).await?,
+                Typ::Playlist => get_revisions::<Playlist>(db, id).await?,
+                Typ::Queue => get_revisions::<Queue>(db, id).await?,
+                Typ::ArtistBlacklist => get_revisions::<ArtistBlacklist>(db, id).await?,
+                Typ::SongBlacklist => get_revisions::<SongBlacklist>(db, id).await?,
+                Typ::Updater => get_revisions::<Updater>(db, id).await?,
+                Typ::SmartPlaylist => get_revisions::<SmartPlaylist>(db, id).await?,
+                Typ::StSong => get_revisions::<StSong>(db, id).await?,
+                Typ::StAlbum => get_revisions::<StAlbum>(db, id).await?,
+                Typ::StPlaylist => get_revisions::<StPlaylist>(db, id).await?,
+                Typ::StArtist => get_revisions::<StArtist>(db, id).await?,
+                Typ::MbzRecording => get_revisions::<MbzRecording>(db, id).await?,
+                Typ::MbzArtist => get_revisions::<MbzArtist>(db, id).await?,
+            },
+            DbRequest::DiffRevisions { id, from, to } => {
+                let res = db.diff_revisions(id, from, to).await?;
+                MessageResult::OkOne(res).json()
+            }
+            DbRequest::Revert {
+                transaction_id,
+                typ,
+                id,
+                update_counter,
+            } => {
+                let mut txn = db.lock_transaction().await?;
+                match txn.as_mut() {
+                    Some(t) => match t.access(transaction_id, connection) {
+                        Err(msg) => MessageResult::Err(ErrorMessage::new(ErrorKind::Conflict, msg)),
+                        Ok(txn) => match typ {
+                            Typ::MmSong => revert::<MmSong>(&db, txn, id, update_counter).await?,
+                            Typ::MmAlbum => revert::<MmAlbum>(&db, txn, id, update_counter).await?,
+                            Typ::MmArtist => {
+                                revert::<MmArtist>(&db, txn, id, update_counter).await?
+                            }
+                            Typ::MmPlaylist => {
+                                revert::<MmPlaylist>(&db, txn, id, update_counter).await?
+                            }
+                            Typ::MmQueue => revert::<MmQueue>(&db, txn, id, update_counter).await?,
+                            Typ::LocalState => {
+                                revert::<LocalState>(&db, txn, id, update_counter).await?
+                            }
+                            Typ::Song => revert::<Song>(&db, txn, id, update_counter).await?,
+                            Typ::Playlist => {
+                                revert::<Playlist>(&db, txn, id, update_counter).await?
+                            }
+                            Typ::Queue => revert::<Queue>(&db, txn, id, update_counter).await?,
+                            Typ::ArtistBlacklist => {
+                                revert::<ArtistBlacklist>(&db, txn, id, update_counter).await?
+                            }
+                            Typ::SongBlacklist => {
+                                revert::<SongBlacklist>(&db, txn, id, update_counter).await?
+                            }
+                            Typ::Updater => revert::<Updater>(&db, txn, id, update_counter).await?,
+                            Typ::SmartPlaylist => {
+                                revert::<SmartPlaylist>(&db, txn, id, update_counter).await?
+                            }
+                            Typ::StSong => revert::<StSong>(&db, txn, id, update_counter).await?,
+                            Typ::StAlbum => revert::<StAlbum>(&db, txn, id, update_counter).await?,
+                            Typ::StPlaylist => {
+                                revert::<StPlaylist>(&db, txn, id, update_counter).await?
+                            }
+                            Typ::StArtist => {
+                                revert::<StArtist>(&db, txn, id, update_counter).await?
+                            }
+                            Typ::MbzRecording => {
+                                revert::<MbzRecording>(&db, txn, id, update_counter).await?
+                            }
+                            Typ::MbzArtist => {
+                                revert::<MbzArtist>(&db, txn, id, update_counter).await?
+                            }
+                        },
+                    },
+                    None => MessageResult::Err(ErrorMessage::new(
+                        ErrorKind::Conflict,
+                        "No Transaction Active",
+                    )),
                 }
             }
+            DbRequest::GetDuplicates => {
+                let res = crate::dedup::find_duplicates(&db).await?;
+                MessageResult::OkOne(res).json()
+            }
+            DbRequest::MergeSongs {
+                transaction_id,
+                survivor,
+                duplicates,
+            } => {
+                let mut txn = db.lock_transaction().await?;
+                match txn.as_mut() {
+                    Some(t) => match t.access(transaction_id, connection) {
+                        Err(msg) => MessageResult::Err(ErrorMessage::new(ErrorKind::Conflict, msg)),
+                        Ok(txn) => {
+                            let item = crate::dedup::merge(&db, txn, survivor, duplicates).await?;
+                            MessageResult::OkOne(item).json()
+                        }
+                    },
+                    None => MessageResult::Err(ErrorMessage::new(
+                        ErrorKind::Conflict,
+                        "No Transaction Active",
+                    )),
+                }
+            }
+            DbRequest::LogPlay(event) => {
+                let id = db
+                    .in_transaction(|txn| {
+                        Box::pin(async move { crate::plays::log(txn, &event).await })
+                    })
+                    .await?;
+                MessageResult::OkOne(id).json()
+            }
+            DbRequest::GetPlays(query) => {
+                let res = crate::plays::query(&db.db, query).await?;
+                MessageResult::OkOne(res).json()
+            }
+            DbRequest::GetListeningStats { after, before } => {
+                let res = crate::analytics::collect(&db.db, after, before).await?;
+                MessageResult::OkOne(res).json()
+            }
+            DbRequest::EvaluateSmartPlaylist(sp) => {
+                let res = crate::smart::evaluate(&db.db, &sp).await?;
+                MessageResult::OkOne(res).json()
+            }
+            DbRequest::SearchAll(query) => {
+                let res = crate::search::search_all(&db, query).await?;
+                MessageResult::OkOne(res).json()
+            }
             DbRequest::Search { typ, query } => match typ {
                 Typ::MmSong => search::<MmSong>(db, query).await?,
                 Typ::MmAlbum => search::<MmAlbum>(db, query).await?,
@@ -540,6 +1209,7 @@ impl MessageServerRequest for DbRequest {
                 Typ::ArtistBlacklist => search::<ArtistBlacklist>(db, query).await?,
                 Typ::SongBlacklist => search::<SongBlacklist>(db, query).await?,
                 Typ::Updater => search::<Updater>(db, query).await?,
+                Typ::SmartPlaylist => search::<SmartPlaylist>(db, query).await?,
                 Typ::StSong => search::<StSong>(db, query).await?,
                 Typ::StAlbum => search::<StAlbum>(db, query).await?,
                 Typ::StPlaylist => search::<StPlaylist>(db, query).await?,
@@ -547,21 +1217,40 @@ impl MessageServerRequest for DbRequest {
                 Typ::MbzRecording => search::<MbzRecording>(db, query).await?,
                 Typ::MbzArtist => search::<MbzArtist>(db, query).await?,
             },
+            DbRequest::Query { typ, query } => match typ {
+                Typ::MmSong => db_query::<MmSong>(db, query).await?,
+                Typ::MmAlbum => db_query::<MmAlbum>(db, query).await?,
+                Typ::MmArtist => db_query::<MmArtist>(db, query).await?,
+                Typ::MmPlaylist => db_query::<MmPlaylist>(db, query).await?,
+                Typ::MmQueue => db_query::<MmQueue>(db, query).await?,
+                Typ::LocalState => db_query::<LocalState>(db, query).await?,
+                Typ::Song => db_query::<Song>(db, query).await?,
+                Typ::Playlist => db_query::<Playlist>(db, query).await?,
+                Typ::Queue => db_query::<Queue>(db, query).await?,
+                Typ::ArtistBlacklist => db_query::<ArtistBlacklist>(db, query).await?,
+                Typ::SongBlacklist => db_query::<SongBlacklist>(db, query).await?,
+                Typ::Updater => db_query::<Updater>(db, query).await?,
+                Typ::SmartPlaylist => db_query::<SmartPlaylist>(db, query).await?,
+                Typ::StSong => db_query::<StSong>(db, query).await?,
+                Typ::StAlbum => db_query::<StAlbum>(db, query).await?,
+                Typ::StPlaylist => db_query::<StPlaylist>(db, query).await?,
+                Typ::StArtist => db_query::<StArtist>(db, query).await?,
+                Typ::MbzRecording => db_query::<MbzRecording>(db, query).await?,
+                Typ::MbzArtist => db_query::<MbzArtist>(db, query).await?,
+            },
             DbRequest::GetByRefid { typ, refid } => match typ {
                 Typ::MmPlaylist
                 | Typ::MmQueue
                 | Typ::MmArtist
                 | Typ::Playlist
                 | Typ::Queue
+                | Typ::SmartPlaylist
                 | Typ::ArtistBlacklist
                 | Typ::SongBlacklist
-                | Typ::LocalState => {
-                    let msg = "Item does not support Refids";
-                    MessageResult::Err(ErrorMessage {
-                        message: msg.into(),
-                        stack_trace: msg.into(),
-                    })
-                }
+                | Typ::LocalState => MessageResult::Err(ErrorMessage::new(
+                    ErrorKind::Invalid,
+                    "Item does not support Refids",
+                )),
                 Typ::MmSong => get_by_refid::<MmSong>(db, refid).await?,
                 Typ::MmAlbum => get_by_refid::<MmAlbum>(db, refid).await?,
                 Typ::Song => get_by_refid::<Song>(db, refid).await?,
@@ -579,15 +1268,13 @@ impl MessageServerRequest for DbRequest {
                 | Typ::MmArtist
                 | Typ::Playlist
                 | Typ::Queue
+                | Typ::SmartPlaylist
                 | Typ::ArtistBlacklist
                 | Typ::SongBlacklist
-                | Typ::LocalState => {
-                    let msg = "Item does not support Refids";
-                    MessageResult::Err(ErrorMessage {
-                        message: msg.into(),
-                        stack_trace: msg.into(),
-                    })
-                }
+                | Typ::LocalState => MessageResult::Err(ErrorMessage::new(
+                    ErrorKind::Invalid,
+                    "Item does not support Refids",
+                )),
                 Typ::MmSong => get_many_by_refid::<MmSong>(db, refids).await?,
                 Typ::MmAlbum => get_many_by_refid::<MmAlbum>(db, refids).await?,
                 Typ::Song => get_many_by_refid::<Song>(db, refids).await?,
@@ -612,6 +1299,7 @@ impl MessageServerRequest for DbRequest {
                 Typ::ArtistBlacklist => get_by_id::<ArtistBlacklist>(db, id).await?,
                 Typ::SongBlacklist => get_by_id::<SongBlacklist>(db, id).await?,
                 Typ::Updater => get_by_id::<Updater>(db, id).await?,
+                Typ::SmartPlaylist => get_by_id::<SmartPlaylist>(db, id).await?,
                 Typ::StSong => get_by_id::<StSong>(db, id).await?,
                 Typ::StAlbum => get_by_id::<StAlbum>(db, id).await?,
                 Typ::StPlaylist => get_by_id::<StPlaylist>(db, id).await?,
@@ -632,6 +1320,7 @@ impl MessageServerRequest for DbRequest {
                 Typ::ArtistBlacklist => get_many_by_id::<ArtistBlacklist>(db, ids).await?,
                 Typ::SongBlacklist => get_many_by_id::<SongBlacklist>(db, ids).await?,
                 Typ::Updater => get_many_by_id::<Updater>(db, ids).await?,
+                Typ::SmartPlaylist => get_many_by_id::<SmartPlaylist>(db, ids).await?,
                 Typ::StSong => get_many_by_id::<StSong>(db, ids).await?,
                 Typ::StAlbum => get_many_by_id::<StAlbum>(db, ids).await?,
                 Typ::StPlaylist => get_many_by_id::<StPlaylist>(db, ids).await?,
@@ -641,7 +1330,273 @@ impl MessageServerRequest for DbRequest {
             },
             DbRequest::GetUntypedById { id } => get_untyped_by_id(db, id).await?,
             DbRequest::GetManyUntypedById { ids } => get_many_untyped_by_id(db, ids).await?,
+            DbRequest::GetLinkedFrom { from, to, refids } => match (from, to) {
+                (Typ::Song, Typ::StSong) => get_linked_from::<Song, StSong>(db, refids).await?,
+                (Typ::Song, Typ::MbzRecording) => {
+                    get_linked_from::<Song, MbzRecording>(db, refids).await?
+                }
+                (Typ::Updater, Typ::StArtist) => {
+                    get_linked_from::<Updater, StArtist>(db, refids).await?
+                }
+                (Typ::StSong, Typ::StAlbum) => {
+                    get_linked_from::<StSong, StAlbum>(db, refids).await?
+                }
+                (Typ::StSong, Typ::StArtist) => {
+                    get_linked_from::<StSong, StArtist>(db, refids).await?
+                }
+                (Typ::StAlbum, Typ::StArtist) => {
+                    get_linked_from::<StAlbum, StArtist>(db, refids).await?
+                }
+                (Typ::MbzRecording, Typ::MbzArtist) => {
+                    get_linked_from::<MbzRecording, MbzArtist>(db, refids).await?
+                }
+                _ => MessageResult::Err(ErrorMessage::new(
+                    ErrorKind::Invalid,
+                    "Items are not linked",
+                )),
+            },
+            DbRequest::GetLinkedTo { from, to, refids } => match (from, to) {
+                (Typ::Song, Typ::StSong) => get_linked_to::<Song, StSong>(db, refids).await?,
+                (Typ::Song, Typ::MbzRecording) => {
+                    get_linked_to::<Song, MbzRecording>(db, refids).await?
+                }
+                (Typ::Updater, Typ::StArtist) => {
+                    get_linked_to::<Updater, StArtist>(db, refids).await?
+                }
+                (Typ::StSong, Typ::StAlbum) => get_linked_to::<StSong, StAlbum>(db, refids).await?,
+                (Typ::StSong, Typ::StArtist) => {
+                    get_linked_to::<StSong, StArtist>(db, refids).await?
+                }
+                (Typ::StAlbum, Typ::StArtist) => {
+                    get_linked_to::<StAlbum, StArtist>(db, refids).await?
+                }
+                (Typ::MbzRecording, Typ::MbzArtist) => {
+                    get_linked_to::<MbzRecording, MbzArtist>(db, refids).await?
+                }
+                _ => MessageResult::Err(ErrorMessage::new(
+                    ErrorKind::Invalid,
+                    "Items are not linked",
+                )),
+            },
+            DbRequest::Subscribe(_) => MessageResult::Err(ErrorMessage::new(
+                ErrorKind::Invalid,
+                "Subscribe needs a message stream",
+            )),
+            DbRequest::Unsubscribe(id) => {
+                // only the connection that subscribed can stop it
+                let stop = match connection {
+                    Some(c) => db.subscriptions.lock().await.get(&(c, id)).cloned(),
+                    None => None,
+                };
+                match stop {
+                    Some(stop) => {
+                        stop.notify_one();
+                        MessageResult::OkOne(()).json()
+                    }
+                    None => MessageResult::Err(ErrorMessage::new(
+                        ErrorKind::NotFound,
+                        "No Such Subscription",
+                    )),
+                }
+            }
         };
         Ok(res)
     }
 }
+
+#[async_trait::async_trait]
+impl MessageServerRequest for DbRequest {
+    type Ctx = Db;
+
+    async fn handle(
+        self,
+        db: Self::Ctx,
+        id_src: Arc<AtomicU32>,
+    ) -> anyhow::Result<MessageResult<String>> {
+        self.handle_request(db, id_src, None).await
+    }
+
+    async fn handle_stream(
+        self,
+        db: Self::Ctx,
+        id_src: Arc<AtomicU32>,
+        stream: MessageStream,
+    ) -> anyhow::Result<MessageResult<String>> {
+        match self {
+            DbRequest::Subscribe(sub) => subscribe(db, sub, stream).await,
+            req => {
+                req.handle_request(db, id_src, Some(stream.connection()))
+                    .await
+            }
+        }
+    }
+
+    async fn on_disconnect(db: Self::Ctx, connection: ConnectionId) {
+        // subscriptions only notice a closed socket when they next try to send
+        for ((c, _), stop) in db.subscriptions.lock().await.iter() {
+            if *c == connection {
+                stop.notify_one();
+            }
+        }
+        if let Err(e) = db.rollback_owned_by(connection).await {
+            eprintln!(
+                "could not roll back transaction of closed connection: {}",
+                e
+            );
+        }
+    }
+}
+
+async fn subscribe(
+    db: Db,
+    sub: DbSubscription,
+    stream: MessageStream,
+) -> anyhow::Result<MessageResult<String>> {
+    use tokio::sync::broadcast::error::RecvError;
+
+    let key = (stream.connection(), stream.id());
+    let stop = Arc::new(tokio::sync::Notify::new());
+    match db.subscriptions.lock().await.entry(key) {
+        Entry::Occupied(_) => {
+            return Ok(MessageResult::Err(ErrorMessage::new(
+                ErrorKind::Invalid,
+                "Subscription id already in use",
+            )));
+        }
+        Entry::Vacant(e) => {
+            e.insert(stop.clone());
+        }
+    }
+    let mut rx = db.subscribe();
+
+    // first message tells the client that it won't miss anything after this.
+    // so it can (re)load whatever it is showing
+    let mut index = 0;
+    let mut event = DbEvent::Resync;
+    let res = loop {
+        if sub.matches(&event) {
+            let msg = MessageResult::OkMany {
+                data: event,
+                done: false,
+                index,
+            };
+            if let Err(e) = stream.send(msg.json()).await {
+                break Err(e);
+            }
+            index += 1;
+        }
+
+        event = tokio::select! {
+            _ = stop.notified() => break Ok(()),
+            e = rx.recv() => match e {
+                Ok(e) => e,
+                Err(RecvError::Lagged(_)) => DbEvent::Resync,
+                Err(RecvError::Closed) => break Ok(()),
+            },
+        };
+    };
+    db.subscriptions.lock().await.remove(&key);
+    res?;
+
+    Ok(MessageResult::OkMany {
+        data: (),
+        done: true,
+        index,
+    }
+    .json())
+}
+
+pub fn fsck_routes(
+    db: Db,
+    config: Arc<crate::config::DerivedConfig>,
+    path: &'static str,
+) -> BoxedFilter<(impl Reply,)> {
+    let check_db = db.clone();
+    let check = warp::path(path)
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::any().map(move || check_db.clone()))
+        .and_then(|db: Db| async move {
+            let report = crate::fsck::check(&db).await.map_err(custom_reject)?;
+            Ok::<_, warp::Rejection>(warp::reply::json(&report))
+        });
+    let repair = warp::path(path)
+        .and(warp::path("repair"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(warp::any().map(move || (db.clone(), config.clone())))
+        .and_then(
+            |(db, config): (Db, Arc<crate::config::DerivedConfig>)| async move {
+                let backup_path = config
+                    .db_path
+                    .join(format!("music.db.backup-fsck-{}", Db::timestamp()));
+                let report = crate::fsck::repair(&db, backup_path)
+                    .await
+                    .map_err(custom_reject)?;
+                Ok::<_, warp::Rejection>(warp::reply::json(&report))
+            },
+        );
+
+    let route = check.or(repair);
+    route.boxed()
+}
+
+pub fn stats_route(
+    db: Db,
+    config: Arc<crate::config::DerivedConfig>,
+    path: &'static str,
+) -> BoxedFilter<(impl Reply,)> {
+    let route = warp::path(path)
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::any().map(move || (db.clone(), config.clone())))
+        .and_then(
+            |(db, config): (Db, Arc<crate::config::DerivedConfig>)| async move {
+                let stats = crate::stats::collect(&db, &config)
+                    .await
+                    .map_err(custom_reject)?;
+                Ok::<_, warp::Rejection>(warp::reply::json(&stats))
+            },
+        );
+    route.boxed()
+}
+
+pub fn archive_routes(db: Db, path: &'static str) -> BoxedFilter<(impl Reply,)> {
+    let export_db = db.clone();
+    let export = warp::path(path)
+        .and(warp::path("export"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::any().map(move || export_db.clone()))
+        .and_then(|db: Db| async move {
+            let mut archive = Vec::new();
+            let report = crate::archive::export(&db, &mut archive)
+                .await
+                .map_err(custom_reject)?;
+            warp::http::Response::builder()
+                .header("content-type", "application/x-ndjson")
+                // objects that could not be parsed are not in the archive
+                .header("x-covau-skipped", report.skipped)
+                .header(
+                    "content-disposition",
+                    format!("attachment; filename=\"covau-{}.jsonl\"", Db::timestamp()),
+                )
+                .body(archive)
+                .map_err(custom_reject)
+        });
+    let import = warp::path(path)
+        .and(warp::path("import"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(warp::body::bytes())
+        .and(warp::any().map(move || db.clone()))
+        .and_then(|archive: bytes::Bytes, db: Db| async move {
+            let report = crate::archive::import(&db, archive.as_ref())
+                .await
+                .map_err(custom_reject)?;
+            Ok::<_, warp::Rejection>(warp::reply::json(&report))
+        });
+
+    let route = export.or(import);
+    route.boxed()
+}
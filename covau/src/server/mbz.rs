@@ -21,7 +21,6 @@ where
             let res = T::search(query).await.map_err(custom_reject)?;
             Ok::<_, warp::Rejection>(warp::reply::json(&res))
         });
-    let search = search.with(warp::cors().allow_any_origin());
     search.boxed()
 }
 
@@ -42,7 +41,6 @@ pub fn mbz_radio_route(c: reqwest::Client) -> BoxedFilter<(impl Reply,)> {
             };
             Ok::<_, warp::Rejection>(warp::reply::json(&res))
         });
-    let search = search.with(warp::cors().allow_any_origin());
     search.boxed()
 }
 
@@ -57,7 +55,6 @@ fn paged_search<T: PagedSearch + Serialize + Send>(
             let res = T::search(query).await.map_err(custom_reject)?;
             Ok::<_, warp::Rejection>(warp::reply::json(&res))
         });
-    let search = search.with(warp::cors().allow_any_origin());
     search.boxed()
 }
 
@@ -71,7 +68,6 @@ fn id_search<T: IdSearch + Serialize + Send>(path: &'static str) -> BoxedFilter<
             let res = T::get(&query).await.map_err(custom_reject)?;
             Ok::<_, warp::Rejection>(warp::reply::json(&res))
         });
-    let search = search.with(warp::cors().allow_any_origin());
     search.boxed()
 }
 
@@ -12,6 +12,9 @@ use warp::Reply;
 
 use crate::server::{ErrorMessage, Message, MessageResult};
 
+/// unique for every websocket connection
+pub type ConnectionId = u32;
+
 fn get_id_route(path: &'static str, id: Arc<AtomicU32>) -> BoxedFilter<(impl Reply,)> {
     let route = warp::path("serve")
         .and(warp::path(path))
@@ -23,7 +26,6 @@ fn get_id_route(path: &'static str, id: Arc<AtomicU32>) -> BoxedFilter<(impl Rep
             Ok::<_, Infallible>(warp::reply::json(&id))
         });
 
-    let route = route.with(warp::cors().allow_any_origin());
     route.boxed()
 }
 
@@ -32,16 +34,19 @@ fn client_ws_route<R: MessageServerRequest<Ctx = Ctx>, Ctx: Clone + Sync + Send
     ctx: Ctx,
     id: Arc<AtomicU32>,
 ) -> BoxedFilter<(impl Reply,)> {
+    let connection_id: Arc<AtomicU32> = Arc::new(0.into());
     let ws_route = warp::path("serve")
         .and(warp::path(path))
         .and(warp::path::end())
         .and(warp::ws())
         .and(warp::any().map(move || ctx.clone()))
         .and(warp::any().map(move || id.clone()))
+        .and(warp::any().map(move || connection_id.clone()))
         .then(
-            |ws: warp::ws::Ws, ctx: Ctx, id: Arc<AtomicU32>| async move {
+            |ws: warp::ws::Ws, ctx: Ctx, id: Arc<AtomicU32>, connection_id: Arc<AtomicU32>| async move {
                 ws.on_upgrade(move |ws| async move {
                     let ctx = ctx.clone();
+                    let connection = connection_id.fetch_add(1, Ordering::Relaxed);
                     let (mut wstx, mut wsrx) = ws.split();
                     let (tx, rx) = mpsc::channel::<Message<String>>(100);
                     let mut rx = ReceiverStream::new(rx);
@@ -67,6 +72,7 @@ fn client_ws_route<R: MessageServerRequest<Ctx = Ctx>, Ctx: Clone + Sync + Send
                         msg: warp::ws::Message,
                         ctx: Ctx,
                         id_src: Arc<AtomicU32>,
+                        connection: ConnectionId,
                     ) -> anyhow::Result<()> {
                         let Some(msg) = msg.to_str().ok() else {
                             return Ok(());
@@ -75,9 +81,16 @@ fn client_ws_route<R: MessageServerRequest<Ctx = Ctx>, Ctx: Clone + Sync + Send
                         match msg.id {
                             Some(id) => match msg.data {
                                 MessageResult::Request(data) => {
+                                    let stream = MessageStream {
+                                        id,
+                                        connection,
+                                        sender: sender.clone(),
+                                    };
                                     let res = async { serde_json::from_str(&data) }
                                         .map_err(|e| anyhow::anyhow!(e))
-                                        .and_then(move |req: R| req.handle(ctx, id_src))
+                                        .and_then(move |req: R| {
+                                            req.handle_stream(ctx, id_src, stream)
+                                        })
                                         .await;
                                     match res {
                                         Ok(res) => {
@@ -92,10 +105,7 @@ fn client_ws_route<R: MessageServerRequest<Ctx = Ctx>, Ctx: Clone + Sync + Send
                                             sender
                                                 .send(Message {
                                                     id: Some(id),
-                                                    data: MessageResult::Err(ErrorMessage {
-                                                        message: format!("{}", err),
-                                                        stack_trace: format!("{:?}", err),
-                                                    }),
+                                                    data: MessageResult::Err(ErrorMessage::from(&err)),
                                                 })
                                                 .await?;
                                         }
@@ -116,6 +126,10 @@ fn client_ws_route<R: MessageServerRequest<Ctx = Ctx>, Ctx: Clone + Sync + Send
                                 MessageResult::Err(err) => {
                                     println!("frontend sent an error: {}", err);
                                 }
+                                // requests are not cancelled midway. the response is just ignored
+                                MessageResult::Cancel => {}
+                                // db clients only send requests. nothing gets routed to them
+                                MessageResult::Register(_) => {}
                             },
                             None => match msg.data {
                                 MessageResult::OkOne(msg)
@@ -129,6 +143,7 @@ fn client_ws_route<R: MessageServerRequest<Ctx = Ctx>, Ctx: Clone + Sync + Send
                                 MessageResult::Err(e) => {
                                     println!("frontend sent an error: {}", e);
                                 }
+                                MessageResult::Cancel | MessageResult::Register(_) => {}
                             },
                         }
                         Ok(())
@@ -141,7 +156,8 @@ fn client_ws_route<R: MessageServerRequest<Ctx = Ctx>, Ctx: Clone + Sync + Send
                                 let ctx = ctx.clone();
                                 let id = id.clone();
                                 let _j = tokio::task::spawn(async move {
-                                    match message_handler::<R, Ctx>(tx, msg, ctx, id.clone()).await
+                                    match message_handler::<R, Ctx>(tx, msg, ctx, id.clone(), connection)
+                                        .await
                                     {
                                         Ok(_) => (),
                                         Err(e) => {
@@ -156,15 +172,46 @@ fn client_ws_route<R: MessageServerRequest<Ctx = Ctx>, Ctx: Clone + Sync + Send
                         }
                     }
 
+                    R::on_disconnect(ctx, connection).await;
+
                     // NOTE: abort drops everything correctly. so this is fine
                     j.abort();
                 })
             },
         );
-    let ws_route = ws_route.with(warp::cors().allow_any_origin());
     ws_route.boxed()
 }
 
+/// for sending multiple responses to a single request
+#[derive(Clone)]
+pub struct MessageStream {
+    id: u32,
+    connection: ConnectionId,
+    sender: Sender<Message<String>>,
+}
+impl MessageStream {
+    /// id of the request
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// the websocket connection this request came from
+    pub fn connection(&self) -> ConnectionId {
+        self.connection
+    }
+
+    /// fails once the websocket is closed
+    pub async fn send(&self, data: MessageResult<String>) -> anyhow::Result<()> {
+        self.sender
+            .send(Message {
+                id: Some(self.id),
+                data,
+            })
+            .await?;
+        Ok(())
+    }
+}
+
 #[async_trait::async_trait]
 pub trait MessageServerRequest
 where
@@ -178,6 +225,20 @@ where
         id: Arc<AtomicU32>,
     ) -> anyhow::Result<MessageResult<String>>;
 
+    /// requests that keep responding can send messages using the stream.
+    /// the returned result is sent last
+    async fn handle_stream(
+        self,
+        ctx: Self::Ctx,
+        id: Arc<AtomicU32>,
+        _stream: MessageStream,
+    ) -> anyhow::Result<MessageResult<String>> {
+        self.handle(ctx, id).await
+    }
+
+    /// called once the websocket connection is closed
+    async fn on_disconnect(_ctx: Self::Ctx, _connection: ConnectionId) {}
+
     fn routes(ctx: Self::Ctx, path: &'static str) -> BoxedFilter<(impl Reply,)> {
         let id: Arc<AtomicU32> = Arc::new(0.into());
         client_ws_route::<Self, _>(path, ctx, id.clone())
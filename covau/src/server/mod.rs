@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use std::{net::Ipv4Addr, sync::Arc, time};
+use std::{net::IpAddr, sync::Arc, time};
 use warp::Filter;
 
 use crate::{
@@ -18,10 +18,13 @@ use crate::{
     yt::YtiRequest,
 };
 
+pub mod auth;
 pub mod db;
 pub mod mbz;
 pub mod message_server;
 pub mod player;
+pub mod proxy;
+pub mod rest;
 pub mod routes;
 
 // [Rejection and anyhow](https://github.com/seanmonstar/warp/issues/307#issuecomment-570833388)
@@ -30,10 +33,54 @@ struct CustomReject(anyhow::Error);
 
 impl warp::reject::Reject for CustomReject {}
 
+/// what went wrong, so that callers don't have to look at the message
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, specta::Type)]
+pub enum ErrorKind {
+    /// no such item (or revision)
+    NotFound,
+    /// the request is fine but the db is not in a state to take it.
+    /// eg: item updated since it was fetched, item still referred to, transaction gone
+    Conflict,
+    /// the request itself is wrong
+    Invalid,
+    NotAllowed,
+    /// another transaction held the db for too long
+    Busy,
+    Internal,
+}
+
 #[derive(Clone, Serialize, Deserialize, specta::Type)]
 pub struct ErrorMessage {
     pub message: String,
     pub stack_trace: String,
+    pub kind: ErrorKind,
+}
+impl ErrorMessage {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        let message = message.into();
+        Self {
+            stack_trace: message.clone(),
+            message,
+            kind,
+        }
+    }
+}
+impl From<&anyhow::Error> for ErrorMessage {
+    fn from(err: &anyhow::Error) -> Self {
+        let kind = if let Some(e) = err.downcast_ref::<ErrorMessage>() {
+            e.kind
+        } else if err.downcast_ref::<serde_json::Error>().is_some() {
+            // items that don't parse
+            ErrorKind::Invalid
+        } else {
+            ErrorKind::Internal
+        };
+        Self {
+            message: format!("{}", err),
+            stack_trace: format!("{:?}", err),
+            kind,
+        }
+    }
 }
 impl core::fmt::Debug for ErrorMessage {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -53,13 +100,30 @@ pub(crate) fn custom_reject(error: impl Into<anyhow::Error>) -> warp::Rejection
     warp::reject::custom(CustomReject(error.into()))
 }
 
+/// what a frontend tells the server about itself when it connects
+#[derive(Clone, Debug, Serialize, Deserialize, specta::Type)]
+pub struct ClientInfo {
+    /// shows up in logs
+    pub name: String,
+    /// if it can handle requests right now. clients that can't still get broadcasts
+    pub capable: bool,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, specta::Type)]
 #[serde(tag = "type", content = "content")]
 pub enum MessageResult<T> {
     Request(T),
     OkOne(T),
-    OkMany { data: T, done: bool, index: u32 },
+    OkMany {
+        data: T,
+        done: bool,
+        index: u32,
+    },
     Err(ErrorMessage),
+    /// the requester does not need the response anymore
+    Cancel,
+    /// sent by frontends before they get any requests. can be sent again to update the info
+    Register(ClientInfo),
 }
 impl<T: Serialize> MessageResult<T> {
     pub fn json(self) -> MessageResult<String> {
@@ -72,12 +136,18 @@ impl<T: Serialize> MessageResult<T> {
             MessageResult::OkOne(t) => MessageResult::OkOne(serde_json::to_string(&t).unwrap()),
             MessageResult::Request(t) => MessageResult::Request(serde_json::to_string(&t).unwrap()),
             MessageResult::Err(e) => MessageResult::Err(e),
+            MessageResult::Cancel => MessageResult::Cancel,
+            MessageResult::Register(info) => MessageResult::Register(info),
         }
     }
     pub fn is_done(&self) -> bool {
         match self {
             MessageResult::OkMany { done, .. } => *done,
-            MessageResult::OkOne(_) | MessageResult::Err(_) | MessageResult::Request(_) => true,
+            MessageResult::OkOne(_)
+            | MessageResult::Err(_)
+            | MessageResult::Request(_)
+            | MessageResult::Cancel
+            | MessageResult::Register(_) => true,
         }
     }
 }
@@ -88,7 +158,7 @@ pub struct Message<T> {
     pub data: MessageResult<T>,
 }
 
-pub async fn start(ip_addr: Ipv4Addr, port: u16, config: Arc<DerivedConfig>) -> anyhow::Result<()> {
+pub async fn start(ip_addr: IpAddr, port: u16, config: Arc<DerivedConfig>) -> anyhow::Result<()> {
     let client = reqwest::Client::builder()
         .timeout(time::Duration::from_secs(5 * 60))
         .build()
@@ -97,7 +167,15 @@ pub async fn start(ip_addr: Ipv4Addr, port: u16, config: Arc<DerivedConfig>) ->
     let db_exists = db_path.exists();
     let db = Db::new(format!("sqlite:{}?mode=rwc", db_path.to_string_lossy()))
         .await
-        .expect("cannot connect to database");
+        .expect("cannot connect to database")
+        .with_transaction_timeouts(
+            time::Duration::from_secs(config.config.db_transaction_timeout_secs.unwrap_or(30)),
+            time::Duration::from_secs(config.config.db_transaction_wait_secs.unwrap_or(30)),
+        )
+        .with_search_boost(crate::db::SearchBoost {
+            likes: config.config.search_like_boost.unwrap_or(20),
+            interactions: config.config.search_interaction_boost.unwrap_or(10),
+        });
     if !db_exists {
         db.init_tables().await.expect("could not init database");
         db.init_state().await.expect("could not init state");
@@ -109,20 +187,39 @@ pub async fn start(ip_addr: Ipv4Addr, port: u16, config: Arc<DerivedConfig>) ->
                     .expect("could not init musimanager data");
             }
         }
+    } else {
+        let version = crate::migrations::schema_version(&db.db)
+            .await
+            .expect("could not read database schema version");
+        let backup_path =
+            config
+                .db_path
+                .join(format!("music.db.backup-v{}-{}", version, Db::timestamp()));
+        crate::migrations::migrate(&db, backup_path)
+            .await
+            .expect("could not migrate database");
     }
 
-    let yti = FrontendClient::<YtiRequest>::new();
-    let fe = FrontendClient::<FeRequest>::new();
+    let day = 24 * 60 * 60;
+    let retention = crate::db::Retention {
+        trash: time::Duration::from_secs(config.config.trash_retention_days.unwrap_or(30) * day),
+        history: time::Duration::from_secs(
+            config.config.history_retention_days.unwrap_or(30) * day,
+        ),
+        max_revisions: config.config.history_max_revisions.unwrap_or(100),
+    };
+    let _j = tokio::task::spawn(db.clone().purge_loop(retention));
+
+    let fe_timeout = time::Duration::from_secs(config.config.frontend_timeout_secs.unwrap_or(60));
+    let yti = FrontendClient::<YtiRequest>::new(fe_timeout);
+    let fe = FrontendClient::<FeRequest>::new(fe_timeout);
     let state = AppState::new();
+    let auth = server::auth::Auth::load(&config).expect("could not load auth tokens");
+    let proxy = server::proxy::Proxy::new(&config)
+        .await
+        .expect("could not initialize proxy");
     let ytf = crate::yt::SongTubeFac::new(yti.clone(), client.clone(), config.clone());
 
-    let options_route = warp::any().and(warp::options()).map(warp::reply).with(
-        warp::cors()
-            .allow_any_origin()
-            .allow_header("content-type")
-            .allow_methods(["POST", "GET"]),
-    );
-
     use message_server::MessageServerRequest;
 
     let all = FrontendClient::client_ws_route(yti.clone(), "yti")
@@ -130,14 +227,17 @@ pub async fn start(ip_addr: Ipv4Addr, port: u16, config: Arc<DerivedConfig>) ->
         .or(FeRequest::cli_command_route(fe.clone(), "cli"))
         .or(AppState::app_state_handler_route(state.clone(), "app"))
         .or(DbRequest::routes(db.clone(), "db"))
-        .or(ProxyRequest::cors_proxy_route(client.clone()))
+        .or(server::db::archive_routes(db.clone(), "archive"))
+        .or(server::db::fsck_routes(db.clone(), config.clone(), "fsck"))
+        .or(server::db::stats_route(db.clone(), config.clone(), "stats"))
+        .or(server::rest::rest_routes(db.clone(), "api"))
+        .or(ProxyRequest::cors_proxy_route(proxy.clone()))
         .or(mbz_routes(client.clone()))
         .or(source_path_route("to_path", config.clone()))
         .or(save_song_route("save_song", ytf.clone()))
-        .or(image_route("image", client.clone(), config.clone()))
+        .or(image_route("image", proxy.clone()))
         .or(stream_yt("yt", ytf.clone()))
-        .or(stream_file("file", config.clone()))
-        .or(options_route.boxed());
+        .or(stream_file("file", config.clone()));
 
     // #[cfg(build_mode = "DEV")]
     // let all = all.or(routes::redirect_route(client.clone(), config.clone()));
@@ -145,33 +245,55 @@ pub async fn start(ip_addr: Ipv4Addr, port: u16, config: Arc<DerivedConfig>) ->
     #[cfg(feature = "native-player")]
     let all = all.or(server::player::player_route());
 
-    let all = all.or(Asset::embedded_asset_route(config.clone()));
+    // cors preflight requests never have the token
+    let options_route = warp::options().map(warp::reply);
+    // the ui has to load without a token so that new devices can pair
+    let all = options_route
+        .or(auth.guard().and(all))
+        .or(auth.routes("auth"))
+        .or(Asset::embedded_asset_route(config.clone()));
     let all = all.recover(|rej: warp::reject::Rejection| async move {
-        let msg = if let Some(CustomReject(err)) = rej.find() {
-            match err.downcast_ref() {
-                Some(ErrorMessage {
-                    message,
-                    stack_trace,
-                }) => warp::reply::json(&ErrorMessage {
-                    message: message.into(),
-                    stack_trace: stack_trace.into(),
-                }),
-                None => warp::reply::json(&ErrorMessage {
-                    message: format!("{}", err),
-                    stack_trace: format!("{:?}", err),
-                }),
-            }
+        let (msg, status) = if let Some(CustomReject(err)) = rej.find() {
+            let msg = match err.downcast_ref::<ErrorMessage>() {
+                Some(e) => warp::reply::json(e),
+                None => warp::reply::json(&ErrorMessage::from(err)),
+            };
+            (msg, warp::http::StatusCode::INTERNAL_SERVER_ERROR)
+        } else if let Some(server::auth::Unauthorized) = rej.find() {
+            let msg = warp::reply::json(&ErrorMessage {
+                message: "unauthorized".into(),
+                stack_trace: format!("{:?}", rej),
+                kind: ErrorKind::NotAllowed,
+            });
+            (msg, warp::http::StatusCode::UNAUTHORIZED)
         } else {
-            warp::reply::json(&ErrorMessage {
+            let msg = warp::reply::json(&ErrorMessage {
                 message: "server error".into(),
                 stack_trace: format!("{:?}", rej),
-            })
+                kind: ErrorKind::Internal,
+            });
+            (msg, warp::http::StatusCode::INTERNAL_SERVER_ERROR)
         };
-        let r = warp::reply::with_status(msg, warp::http::StatusCode::INTERNAL_SERVER_ERROR);
-        let r = warp::reply::with_header(r, "access-control-allow-origin", "*");
+        let r = warp::reply::with_status(msg, status);
 
         Result::<_, std::convert::Infallible>::Ok(r)
     });
+    let all = auth.origin().and(all).map(server::auth::with_cors).recover(
+        |rej: warp::reject::Rejection| async move {
+            let origin = match rej.find() {
+                Some(server::auth::ForbiddenOrigin(origin)) => origin.clone(),
+                None => return Err(rej),
+            };
+            let msg = warp::reply::json(&ErrorMessage::new(
+                ErrorKind::NotAllowed,
+                format!("origin not allowed: {}", origin),
+            ));
+            Ok(warp::reply::with_status(
+                msg,
+                warp::http::StatusCode::FORBIDDEN,
+            ))
+        },
+    );
 
     let j = tokio::task::spawn(async move {
         let ytf = ytf;
@@ -219,10 +341,11 @@ async fn updater_system(ytf: crate::yt::SongTubeFac, fec: FrontendClient<FeReque
 }
 
 pub fn dump_types(config: &specta::ts::ExportConfiguration) -> anyhow::Result<String> {
-    use crate::server::{db::*, player::*, routes::*};
+    use crate::server::{auth::*, db::*, player::*, routes::*};
 
     let mut types = String::new();
-    types += "import type { DbMetadata, DbItem, Typ, SearchQuery } from '$types/db.ts';\n";
+    types += "import type { DbMetadata, DbItem, Typ, SearchQuery, UniversalQuery, DbQuery, DbSubscription } from '$types/db.ts';\n";
+    types += "import type { PlayEvent, PlayQuery } from '$types/covau.ts';\n";
     types += "\n";
     types += &specta::ts::export::<Message<()>>(config)?;
     types += ";\n";
@@ -240,14 +363,26 @@ pub fn dump_types(config: &specta::ts::ExportConfiguration) -> anyhow::Result<St
     types += ";\n";
     types += &specta::ts::export::<InsertResponse<()>>(config)?;
     types += ";\n";
+    types += &specta::ts::export::<BatchResult<()>>(config)?;
+    types += ";\n";
     types += &specta::ts::export::<YtStreamQuery>(config)?;
     types += ";\n";
     types += &specta::ts::export::<ImageQuery>(config)?;
     types += ";\n";
     types += &specta::ts::export::<DbRequest>(config)?;
     types += ";\n";
+    types += &specta::ts::export::<ErrorKind>(config)?;
+    types += ";\n";
     types += &specta::ts::export::<ErrorMessage>(config)?;
     types += ";\n";
+    types += &specta::ts::export::<ClientInfo>(config)?;
+    types += ";\n";
+    types += &specta::ts::export::<PairedDevice>(config)?;
+    types += ";\n";
+    types += &specta::ts::export::<PairingCode>(config)?;
+    types += ";\n";
+    types += &specta::ts::export::<PairRequest>(config)?;
+    types += ";\n";
 
     Ok(types)
 }
@@ -226,7 +226,6 @@ pub fn player_route() -> BoxedFilter<(impl Reply,)> {
                 // let _  = j2.await;
             })
         });
-    let route = route.with(warp::cors().allow_any_origin());
 
     route.boxed()
 }
@@ -0,0 +1,322 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use futures::{Stream, StreamExt};
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::config::DerivedConfig;
+use crate::db::Db;
+
+/// everything the ui needs to talk to youtube and the metabrainz services
+const DEFAULT_HOSTS: [&str; 12] = [
+    "youtube.com",
+    "youtu.be",
+    "youtubei.googleapis.com",
+    "googlevideo.com",
+    "ytimg.com",
+    "ggpht.com",
+    "googleusercontent.com",
+    "musicbrainz.org",
+    "coverartarchive.org",
+    // cover art archive redirects here
+    "archive.org",
+    "listenbrainz.org",
+    "metabrainz.org",
+];
+
+const MAX_REDIRECTS: usize = 10;
+
+/// headers that are only about the connection to the proxy. reqwest sets its own.
+/// a Host from the caller would also reach another site on the same server than the one
+/// that was checked
+const DROPPED_HEADERS: [&str; 10] = [
+    "host",
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+    "content-length",
+];
+
+struct ProxyState {
+    // a request goes to any of these or their subdomains
+    hosts: Vec<String>,
+    allow_private: bool,
+    max_bytes: u64,
+    log: Mutex<Option<tokio::fs::File>>,
+}
+
+/// fetches urls for the ui (/fetch and /image). only reaches allowed hosts, never
+/// private network addresses (unless allowed), and logs every request
+#[derive(Clone)]
+pub struct Proxy {
+    state: Arc<ProxyState>,
+    client: reqwest::Client,
+}
+
+impl Proxy {
+    pub async fn new(config: &DerivedConfig) -> anyhow::Result<Self> {
+        let hosts = match &config.config.proxy_allowed_hosts {
+            Some(hosts) => hosts.iter().map(|h| h.to_lowercase()).collect(),
+            None => DEFAULT_HOSTS.iter().map(|h| h.to_string()).collect(),
+        };
+        let allow_private = config.config.proxy_allow_private_addresses.unwrap_or(false);
+        let max_bytes = config.config.proxy_max_response_mb.unwrap_or(20) * 1024 * 1024;
+
+        let log = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(config.log_path.join("proxy.log"))
+            .await
+            .map_err(|e| eprintln!("could not open proxy log: {}", e))
+            .ok();
+
+        let state = Arc::new(ProxyState {
+            hosts,
+            allow_private,
+            max_bytes,
+            log: Mutex::new(log),
+        });
+
+        // every redirect has to pass the same checks
+        let redirect_state = state.clone();
+        let redirect = reqwest::redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() > MAX_REDIRECTS {
+                attempt.error("too many redirects")
+            } else if let Err(e) = redirect_state.check(attempt.url()) {
+                attempt.error(e)
+            } else {
+                attempt.follow()
+            }
+        });
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(5 * 60))
+            .redirect(redirect)
+            .dns_resolver(Arc::new(Resolver { allow_private }))
+            .build()
+            .context("could not initialize proxy client")?;
+
+        Ok(Self { state, client })
+    }
+
+    /// sends the request if the proxy is allowed to, and logs it.
+    /// build adds the headers and body
+    pub async fn send(
+        &self,
+        route: &str,
+        method: &str,
+        url: &str,
+        build: impl FnOnce(reqwest::RequestBuilder) -> anyhow::Result<reqwest::RequestBuilder>,
+    ) -> anyhow::Result<reqwest::Response> {
+        let res = self.send_impl(method, url, build).await;
+        let outcome = res
+            .as_ref()
+            .map(|r| r.status().as_u16())
+            .map_err(|e| e.to_string());
+        self.log(route, method, url, outcome).await;
+        res
+    }
+
+    async fn send_impl(
+        &self,
+        method: &str,
+        url: &str,
+        build: impl FnOnce(reqwest::RequestBuilder) -> anyhow::Result<reqwest::RequestBuilder>,
+    ) -> anyhow::Result<reqwest::Response> {
+        let method = match method.to_uppercase().as_str() {
+            "GET" => reqwest::Method::GET,
+            "HEAD" => reqwest::Method::HEAD,
+            "POST" => reqwest::Method::POST,
+            m => return Err(anyhow::anyhow!("method not allowed: {}", m)),
+        };
+        let url = reqwest::Url::parse(url)?;
+        self.state.check(&url)?;
+
+        let mut req = build(self.client.request(method, url))?.build()?;
+        drop_hop_headers(req.headers_mut());
+        let res = self.client.execute(req).await?;
+
+        let max = self.state.max_bytes;
+        if res.content_length().is_some_and(|len| len > max) {
+            return Err(anyhow::anyhow!("response is larger than {} bytes", max));
+        }
+        Ok(res)
+    }
+
+    /// response body that errors once it gets bigger than the limit
+    pub fn body(&self, res: reqwest::Response) -> impl Stream<Item = anyhow::Result<bytes::Bytes>> {
+        let max = self.state.max_bytes;
+        let mut read = 0;
+        res.bytes_stream().map(move |chunk| {
+            let chunk = chunk?;
+            read += chunk.len() as u64;
+            if read > max {
+                return Err(anyhow::anyhow!("response is larger than {} bytes", max));
+            }
+            Ok(chunk)
+        })
+    }
+
+    /// upstream headers that can be passed on. the body is streamed again (and cut off at
+    /// the size limit), so the upstream content-length doesn't hold
+    pub fn headers(&self, res: &reqwest::Response) -> reqwest::header::HeaderMap {
+        let mut headers = res.headers().clone();
+        drop_hop_headers(&mut headers);
+        headers
+    }
+
+    /// one json line per request in logs/proxy.log
+    async fn log(&self, route: &str, method: &str, url: &str, outcome: Result<u16, String>) {
+        #[derive(Serialize)]
+        struct Entry<'a> {
+            ts: u64,
+            route: &'a str,
+            method: &'a str,
+            url: &'a str,
+            status: Option<u16>,
+            error: Option<String>,
+        }
+
+        let entry = Entry {
+            ts: Db::timestamp(),
+            route,
+            method,
+            url,
+            status: outcome.as_ref().ok().copied(),
+            error: outcome.err(),
+        };
+        let Ok(mut line) = serde_json::to_string(&entry) else {
+            return;
+        };
+        line.push('\n');
+
+        let mut log = self.state.log.lock().await;
+        if let Some(file) = log.as_mut() {
+            if let Err(e) = file.write_all(line.as_bytes()).await {
+                eprintln!("could not write proxy log: {}", e);
+            }
+        }
+    }
+}
+
+impl ProxyState {
+    fn check(&self, url: &reqwest::Url) -> anyhow::Result<()> {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(anyhow::anyhow!("scheme not allowed: {}", url.scheme()));
+        }
+        // the url parser already turns odd ip forms (like 0x7f.1) into plain ones
+        let host = url
+            .host_str()
+            .context("url has no host")?
+            .trim_end_matches('.')
+            .to_lowercase();
+        if let Ok(ip) = host.trim_matches(['[', ']']).parse::<IpAddr>() {
+            self.check_ip(ip)?;
+        }
+        let allowed = self.hosts.iter().any(|h| {
+            host == *h
+                || host
+                    .strip_suffix(h.as_str())
+                    .is_some_and(|s| s.ends_with('.'))
+        });
+        if !allowed {
+            return Err(anyhow::anyhow!("host not allowed: {}", host));
+        }
+        Ok(())
+    }
+
+    fn check_ip(&self, ip: IpAddr) -> anyhow::Result<()> {
+        if !self.allow_private && !is_public(ip) {
+            return Err(anyhow::anyhow!("private address not allowed: {}", ip));
+        }
+        Ok(())
+    }
+}
+
+fn drop_hop_headers(headers: &mut reqwest::header::HeaderMap) {
+    // and whatever the Connection header says is only for this hop
+    let listed = headers
+        .get_all(reqwest::header::CONNECTION)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|h| h.trim().to_lowercase())
+        .filter(|h| !h.is_empty())
+        .collect::<Vec<_>>();
+    for h in listed.iter().map(|h| h.as_str()).chain(DROPPED_HEADERS) {
+        headers.remove(h);
+    }
+}
+
+/// drops private addresses from dns results, so allowed hosts can't point the proxy
+/// at the local network
+struct Resolver {
+    allow_private: bool,
+}
+
+impl reqwest::dns::Resolve for Resolver {
+    fn resolve(&self, name: warp::hyper::client::connect::dns::Name) -> reqwest::dns::Resolving {
+        let allow_private = self.allow_private;
+        Box::pin(async move {
+            let addrs = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|a| allow_private || is_public(a.ip()))
+                .collect::<Vec<SocketAddr>>();
+            if addrs.is_empty() {
+                return Err(format!("{} has no public address", name.as_str()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            // 100.64.0.0/10 is carrier grade nat
+            let shared = a == 100 && (64..128).contains(&b);
+            // 0.0.0.0/8 is "this network", 198.18.0.0/15 is for benchmarking
+            // and 240.0.0.0/4 (with the broadcast address) is reserved
+            let reserved = a == 0 || (a == 198 && b & 0xfe == 18) || a >= 240;
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_multicast()
+                || shared
+                || reserved)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public(ip.into());
+            }
+            let s = ip.segments();
+            // nat64 (64:ff9b::/96) and 6to4 (2002::/16) reach the ipv4 address inside
+            let embedded = match s {
+                [0x64, 0xff9b, 0, 0, 0, 0, hi, lo] => Some((hi, lo)),
+                [0x2002, hi, lo, ..] => Some((hi, lo)),
+                _ => None,
+            };
+            if let Some((hi, lo)) = embedded {
+                let v4 = std::net::Ipv4Addr::from(((hi as u32) << 16) | lo as u32);
+                return is_public(v4.into());
+            }
+            // 64:ff9b:1::/48 is nat64 for local networks
+            let local_nat64 = s[0] == 0x64 && s[1] == 0xff9b && s[2] == 1;
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || ip.is_unique_local()
+                || ip.is_unicast_link_local()
+                || local_nat64)
+        }
+    }
+}
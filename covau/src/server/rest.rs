@@ -0,0 +1,334 @@
+use std::sync::{atomic::AtomicU32, Arc};
+
+use sea_orm::Iterable;
+use serde::Deserialize;
+use warp::http::StatusCode;
+use warp::{filters::BoxedFilter, reply::Reply, Filter};
+
+use crate::{
+    db::{
+        Db, DbId, DbItem, DbMetadata, DbQuery, MetadataField, SearchContinuation, SearchQuery,
+        TransactionId, Typ,
+    },
+    server::{db::DbRequest, ErrorKind, ErrorMessage, MessageResult},
+};
+
+// plain http routes for scripts. every route maps to a DbRequest, and writes get a
+// transaction of their own
+//
+// GET    /api/{typ}s?q=&page_size=&cont=   search (or list all items without q)
+// POST   /api/{typ}s                       insert the item in the body
+// GET    /api/objects/{id}
+// PUT    /api/objects/{id}                 update. body is the item as GET returns it
+// PATCH  /api/objects/{id}/metadata
+// DELETE /api/objects/{id}                 moves the item to the trash
+//
+// {typ} is snake case. eg: songs, playlists, mm_songs, st_albums
+
+const PAGE_SIZE: u32 = 50;
+
+#[derive(Deserialize)]
+struct ListQuery {
+    q: Option<String>,
+    page_size: Option<u32>,
+    // continuation of the previous page
+    cont: Option<String>,
+    // only without q
+    sort: Option<MetadataField>,
+    desc: Option<bool>,
+}
+
+struct ApiError(StatusCode, ErrorMessage);
+
+impl ApiError {
+    fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self::from_message(ErrorMessage::new(kind, message))
+    }
+
+    fn from_message(err: ErrorMessage) -> Self {
+        let status = match err.kind {
+            ErrorKind::NotFound => StatusCode::NOT_FOUND,
+            // eg: someone else updated the item since it was fetched
+            ErrorKind::Conflict => StatusCode::CONFLICT,
+            ErrorKind::Invalid => StatusCode::BAD_REQUEST,
+            ErrorKind::NotAllowed => StatusCode::FORBIDDEN,
+            ErrorKind::Busy => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorKind::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        Self(status, err)
+    }
+
+    fn into_response(self) -> warp::reply::Response {
+        warp::reply::with_status(warp::reply::json(&self.1), self.0).into_response()
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(err: anyhow::Error) -> Self {
+        Self::from_message(ErrorMessage::from(&err))
+    }
+}
+
+type ApiResult = Result<warp::reply::Response, ApiError>;
+
+#[derive(Clone)]
+struct Api {
+    db: Db,
+    // DbRequest needs one. rest routes never ask for ids
+    id_src: Arc<AtomicU32>,
+}
+
+impl Api {
+    /// the json of a successful response
+    async fn run(&self, req: DbRequest) -> Result<String, ApiError> {
+        match req
+            .handle_request(self.db.clone(), self.id_src.clone(), None)
+            .await?
+        {
+            MessageResult::OkOne(json) => Ok(json),
+            MessageResult::Err(e) => Err(ApiError::from_message(e)),
+            _ => Err(ApiError::new(ErrorKind::Internal, "unexpected response")),
+        }
+    }
+
+    /// runs the request in a transaction of its own. commits only if it worked
+    async fn run_in_transaction(
+        &self,
+        req: impl FnOnce(TransactionId) -> DbRequest,
+    ) -> Result<String, ApiError> {
+        let id = self.db.begin(None).await?;
+        let res = self.run(req(id)).await;
+        match &res {
+            Ok(_) => self.db.commit(id, None).await?,
+            Err(_) => {
+                if let Err(e) = self.db.rollback(id, None).await {
+                    eprintln!("could not roll back transaction {}: {}", id, e);
+                }
+            }
+        }
+        res
+    }
+
+    async fn object(&self, id: DbId) -> Result<DbItem<String>, ApiError> {
+        let json = self.run(DbRequest::GetUntypedById { id }).await?;
+        let item: Option<DbItem<String>> =
+            serde_json::from_str(&json).map_err(anyhow::Error::from)?;
+        item.ok_or_else(|| ApiError::new(ErrorKind::NotFound, "item not found"))
+    }
+
+    async fn list(&self, typ: Typ, query: ListQuery) -> ApiResult {
+        let page_size = query.page_size.unwrap_or(PAGE_SIZE);
+        let req = match query.q {
+            Some(q) => {
+                let query = match query.cont {
+                    Some(cont) => SearchQuery::Continuation(SearchContinuation {
+                        typ: typ.clone(),
+                        page_size,
+                        query: q,
+                        cont,
+                    }),
+                    None => SearchQuery::Query {
+                        page_size,
+                        query: q,
+                    },
+                };
+                DbRequest::Search { typ, query }
+            }
+            None => DbRequest::Query {
+                typ,
+                query: DbQuery {
+                    filters: vec![],
+                    text: None,
+                    sort_by: query.sort,
+                    desc: query.desc.unwrap_or(false),
+                    page_size,
+                    cursor: query.cont,
+                },
+            },
+        };
+        let json = self.run(req).await?;
+        Ok(json_reply(json, StatusCode::OK))
+    }
+
+    async fn insert(&self, typ: Typ, body: bytes::Bytes) -> ApiResult {
+        let item = parse_body::<serde_json::Value>(&body)?.to_string();
+        let json = self
+            .run_in_transaction(|transaction_id| DbRequest::Insert {
+                transaction_id,
+                typ,
+                item,
+            })
+            .await?;
+        Ok(json_reply(json, StatusCode::CREATED))
+    }
+
+    async fn get(&self, id: DbId) -> ApiResult {
+        let item = parsed(self.object(id).await?)?;
+        Ok(warp::reply::json(&item).into_response())
+    }
+
+    async fn update(&self, id: DbId, body: bytes::Bytes) -> ApiResult {
+        let item = parse_body::<DbItem<serde_json::Value>>(&body)?;
+        if item.id != id {
+            return Err(ApiError::new(ErrorKind::Invalid, "id mismatch"));
+        }
+        let item = DbItem {
+            metadata: item.metadata,
+            id: item.id,
+            typ: item.typ,
+            t: item.t.to_string(),
+        };
+        let json = self
+            .run_in_transaction(|transaction_id| DbRequest::Update {
+                transaction_id,
+                item,
+            })
+            .await?;
+        Ok(json_reply(json, StatusCode::OK))
+    }
+
+    async fn update_metadata(&self, id: DbId, body: bytes::Bytes) -> ApiResult {
+        let metadata = parse_body::<DbMetadata>(&body)?;
+        let typ = self.object(id).await?.typ;
+        let json = self
+            .run_in_transaction(|transaction_id| DbRequest::UpdateMetadata {
+                transaction_id,
+                id,
+                typ,
+                metadata,
+            })
+            .await?;
+        Ok(json_reply(json, StatusCode::OK))
+    }
+
+    async fn delete(&self, id: DbId) -> ApiResult {
+        let item = self.object(id).await?;
+        self.run_in_transaction(|transaction_id| DbRequest::Delete {
+            transaction_id,
+            item,
+        })
+        .await?;
+        Ok(StatusCode::NO_CONTENT.into_response())
+    }
+}
+
+pub fn rest_routes(db: Db, path: &'static str) -> BoxedFilter<(impl Reply,)> {
+    let api = Api {
+        db,
+        id_src: Arc::new(0.into()),
+    };
+    let with_api = move || {
+        let api = api.clone();
+        warp::any().map(move || api.clone())
+    };
+
+    let objects = warp::path(path).and(warp::path("objects"));
+    let get = objects
+        .and(warp::path::param::<DbId>())
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(with_api())
+        .then(|id: DbId, api: Api| async move { api.get(id).await });
+    let update = objects
+        .and(warp::path::param::<DbId>())
+        .and(warp::path::end())
+        .and(warp::put())
+        .and(warp::body::bytes())
+        .and(with_api())
+        .then(|id: DbId, body: bytes::Bytes, api: Api| async move { api.update(id, body).await });
+    let update_metadata = objects
+        .and(warp::path::param::<DbId>())
+        .and(warp::path("metadata"))
+        .and(warp::path::end())
+        .and(warp::patch())
+        .and(warp::body::bytes())
+        .and(with_api())
+        .then(|id: DbId, body: bytes::Bytes, api: Api| async move {
+            api.update_metadata(id, body).await
+        });
+    let delete = objects
+        .and(warp::path::param::<DbId>())
+        .and(warp::path::end())
+        .and(warp::delete())
+        .and(with_api())
+        .then(|id: DbId, api: Api| async move { api.delete(id).await });
+
+    let list = warp::path(path)
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::query::<ListQuery>())
+        .and(with_api())
+        .then(|typ: String, query: ListQuery, api: Api| async move {
+            api.list(typ_from_path(&typ)?, query).await
+        });
+    let insert = warp::path(path)
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(warp::body::bytes())
+        .and(with_api())
+        .then(|typ: String, body: bytes::Bytes, api: Api| async move {
+            api.insert(typ_from_path(&typ)?, body).await
+        });
+
+    let route = get
+        .or(update)
+        .unify()
+        .or(update_metadata)
+        .unify()
+        .or(delete)
+        .unify()
+        .or(list)
+        .unify()
+        .or(insert)
+        .unify()
+        .map(|res: ApiResult| match res {
+            Ok(res) => res,
+            Err(e) => e.into_response(),
+        });
+    route.boxed()
+}
+
+/// eg: songs for Song, mm_songs for MmSong
+fn typ_path(typ: &Typ) -> String {
+    let name = format!("{:?}", typ);
+    let mut path = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            path.push('_');
+        }
+        path.push(c.to_ascii_lowercase());
+    }
+    path.push('s');
+    path
+}
+
+fn typ_from_path(name: &str) -> Result<Typ, ApiError> {
+    Typ::iter()
+        .find(|t| typ_path(t) == name)
+        .ok_or_else(|| ApiError::new(ErrorKind::NotFound, format!("unknown typ: {}", name)))
+}
+
+fn parse_body<T: for<'de> Deserialize<'de>>(body: &[u8]) -> Result<T, ApiError> {
+    serde_json::from_slice(body)
+        .map_err(|e| ApiError::new(ErrorKind::Invalid, format!("invalid body: {}", e)))
+}
+
+// the untyped lookup keeps items as json strings
+fn parsed(item: DbItem<String>) -> Result<DbItem<serde_json::Value>, ApiError> {
+    Ok(DbItem {
+        t: serde_json::from_str(&item.t).map_err(anyhow::Error::from)?,
+        metadata: item.metadata,
+        id: item.id,
+        typ: item.typ,
+    })
+}
+
+fn json_reply(json: String, status: StatusCode) -> warp::reply::Response {
+    warp::http::Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(json.into())
+        .expect("valid response")
+}
@@ -1,5 +1,6 @@
 use std::ops::Deref;
 use std::sync::atomic;
+use std::time::Duration;
 use std::{collections::HashMap, sync::Arc};
 
 use anyhow::Context;
@@ -17,7 +18,9 @@ use crate::yt::SongTubeFac;
 use crate::{
     config::DerivedConfig,
     covau_types::{SourcePath, SourcePathType},
-    server::{custom_reject, ErrorMessage, Message, MessageResult},
+    server::{
+        custom_reject, proxy::Proxy, ClientInfo, ErrorKind, ErrorMessage, Message, MessageResult,
+    },
 };
 
 pub struct FrontendClient<R>(Arc<RequestTracker<R>>);
@@ -34,60 +37,279 @@ impl<R> Deref for FrontendClient<R> {
     }
 }
 
+/// unique for every websocket connection
+pub type ClientId = u32;
+
+struct ConnectedClient<R> {
+    // None until the client registers
+    info: Option<ClientInfo>,
+    sender: mpsc::Sender<Message<R>>,
+    // requests that timed out since the last response from this client
+    failures: u32,
+}
+
+struct Clients<R> {
+    connected: HashMap<ClientId, ConnectedClient<R>>,
+    // requests stick to one client while it is healthy. (song tubes live in the client that
+    // created them)
+    primary: Option<ClientId>,
+}
+
+// request id -> client it was sent to, where its responses go
+type Pending<S> = Mutex<HashMap<u32, (ClientId, S)>>;
+
+/// only the client a request was sent to can respond to it
+async fn take_pending<S>(pending: &Pending<S>, id: u32, client: ClientId) -> Option<S> {
+    let mut map = pending.lock().await;
+    match map.get(&id) {
+        Some((c, _)) if *c == client => map.remove(&id).map(|(_, s)| s),
+        _ => None,
+    }
+}
+
 pub struct RequestTracker<R> {
     id_count: std::sync::atomic::AtomicU32,
-    request_sender: mpsc::Sender<Message<R>>,
-    request_receiver: Mutex<ReceiverStream<Message<R>>>,
-    ok_one: Mutex<HashMap<u32, oneshot::Sender<MessageResult<String>>>>,
-    ok_many: Mutex<HashMap<u32, mpsc::Sender<MessageResult<String>>>>,
+    client_count: std::sync::atomic::AtomicU32,
+    clients: Mutex<Clients<R>>,
+    // notified when a client registers
+    registered: tokio::sync::Notify,
+    ok_one: Pending<oneshot::Sender<MessageResult<String>>>,
+    ok_many: Pending<mpsc::Sender<MessageResult<String>>>,
+    // max wait for a response (or the next one for get_many)
+    timeout: Duration,
 }
 
-impl<R: Send + Sync + Serialize + 'static> FrontendClient<R> {
-    pub fn new() -> Self {
-        let (tx, rx) = mpsc::channel(100);
+/// removes the request from the tracker and cancels it in the frontend if dropped before
+/// the last response
+struct PendingRequest<R: Clone + Send + Sync + Serialize + 'static> {
+    fe: FrontendClient<R>,
+    id: u32,
+    client: ClientId,
+    done: bool,
+}
+impl<R: Clone + Send + Sync + Serialize + 'static> Drop for PendingRequest<R> {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let fe = self.fe.clone();
+        let id = self.id;
+        handle.spawn(async move { fe.cancel(id).await });
+    }
+}
+
+impl<R: Clone + Send + Sync + Serialize + 'static> FrontendClient<R> {
+    pub fn new(timeout: Duration) -> Self {
         Self(Arc::new(RequestTracker {
             id_count: Default::default(),
-            request_sender: tx,
-            request_receiver: Mutex::new(ReceiverStream::new(rx)),
+            client_count: Default::default(),
+            clients: Mutex::new(Clients {
+                connected: HashMap::new(),
+                primary: None,
+            }),
+            registered: tokio::sync::Notify::new(),
             ok_one: Mutex::new(HashMap::new()),
             ok_many: Mutex::new(HashMap::new()),
+            timeout,
         }))
     }
 
-    pub async fn send(&self, msg: MessageResult<R>) -> anyhow::Result<()> {
-        self.request_sender
-            .send(Message {
-                id: None,
-                data: msg,
-            })
-            .await?;
+    /// sends the request to every registered client. no responses are expected
+    pub async fn broadcast(&self, req: R) -> anyhow::Result<()> {
+        let senders = self
+            .clients
+            .lock()
+            .await
+            .connected
+            .values()
+            .filter(|c| c.info.is_some())
+            .map(|c| c.sender.clone())
+            .collect::<Vec<_>>();
+        if senders.is_empty() {
+            return Err(anyhow::anyhow!("no frontend connected"));
+        }
+        for sender in senders {
+            // a client that just disconnected does not matter
+            let _ = sender
+                .send(Message {
+                    id: None,
+                    data: MessageResult::Request(req.clone()),
+                })
+                .await;
+        }
         Ok(())
     }
 
-    /// NOTE: there is no timeout on these. (so infinite timeout ig)
-    ///       you will wait forever if no no response is sent
-    // MAYBE: implement timeout?
-    pub async fn get_one<T: for<'de> Deserialize<'de>>(&self, req: R) -> anyhow::Result<T> {
-        let id = self
-            .id_count
-            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    /// the primary client if it is healthy, else the capable client with the least failures
+    async fn pick_client(
+        &self,
+        exclude: &[ClientId],
+    ) -> Option<(ClientId, mpsc::Sender<Message<R>>)> {
+        let mut clients = self.clients.lock().await;
+        let capable = clients
+            .connected
+            .iter()
+            .filter(|(id, c)| c.info.as_ref().is_some_and(|i| i.capable) && !exclude.contains(id))
+            .map(|(id, c)| (*id, c.failures))
+            .collect::<Vec<_>>();
+        let id = match clients.primary {
+            Some(p) if capable.contains(&(p, 0)) => p,
+            // newer clients first
+            _ => {
+                capable
+                    .iter()
+                    .min_by_key(|(id, failures)| (*failures, std::cmp::Reverse(*id)))?
+                    .0
+            }
+        };
+        clients.primary = Some(id);
+        Some((id, clients.connected[&id].sender.clone()))
+    }
 
-        let mut map = self.ok_one.lock().await;
-        let (tx, rx) = oneshot::channel::<MessageResult<String>>();
-        map.insert(id, tx)
-            .is_none()
-            .then(|| Some(()))
-            .expect("request with this id already exists");
-        drop(map);
+    async fn wait_for_client(&self) -> anyhow::Result<(ClientId, mpsc::Sender<Message<R>>)> {
+        let wait = async {
+            loop {
+                // created before checking so that a registration in between is not missed
+                let registered = self.registered.notified();
+                if let Some(client) = self.pick_client(&[]).await {
+                    return client;
+                }
+                registered.await;
+            }
+        };
+        tokio::time::timeout(self.timeout, wait)
+            .await
+            .map_err(|_| anyhow::anyhow!("no frontend connected that can handle this request"))
+    }
 
-        self.request_sender
-            .send(Message {
+    async fn register(&self, client: ClientId, info: ClientInfo) {
+        let mut clients = self.clients.lock().await;
+        if let Some(c) = clients.connected.get_mut(&client) {
+            c.info = Some(info);
+        }
+        drop(clients);
+        self.registered.notify_waiters();
+    }
+
+    async fn set_failures(&self, client: ClientId, f: impl FnOnce(u32) -> u32) {
+        if let Some(c) = self.clients.lock().await.connected.get_mut(&client) {
+            c.failures = f(c.failures);
+        }
+    }
+
+    /// stop tracking the request and tell the frontend to stop working on it
+    async fn cancel(&self, id: u32) {
+        let one = self.ok_one.lock().await.remove(&id).map(|(c, _)| c);
+        let many = self.ok_many.lock().await.remove(&id).map(|(c, _)| c);
+        let Some(client) = one.or(many) else {
+            return;
+        };
+        let clients = self.clients.lock().await;
+        if let Some(c) = clients.connected.get(&client) {
+            // the frontend might be gone. cancelling is best effort
+            let _ = c.sender.try_send(Message {
                 id: Some(id),
-                data: MessageResult::Request(req),
-            })
-            .await?;
+                data: MessageResult::Cancel,
+            });
+        }
+    }
+
+    /// fail all requests that this client can no longer respond to
+    async fn fail_pending(&self, client: ClientId) {
+        // a dropped sender lets get_one try another client
+        self.ok_one.lock().await.retain(|_, (c, _)| *c != client);
+
+        // an error has to be sent. a dropped sender looks like the end of the stream
+        let mut map = self.ok_many.lock().await;
+        let ids = map
+            .iter()
+            .filter(|(_, (c, _))| *c == client)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        let senders = ids
+            .into_iter()
+            .filter_map(|id| map.remove(&id))
+            .map(|(_, tx)| tx)
+            .collect::<Vec<_>>();
+        // sending can wait on a slow consumer. the lock must not be held for that
+        drop(map);
+        for tx in senders {
+            let _ = tx
+                .send(MessageResult::Err(ErrorMessage::new(
+                    ErrorKind::Internal,
+                    "frontend disconnected",
+                )))
+                .await;
+        }
+    }
+
+    /// tries other clients if the request can't be sent or the chosen one disconnects
+    /// before responding
+    pub async fn get_one<T: for<'de> Deserialize<'de>>(&self, req: R) -> anyhow::Result<T> {
+        let mut tried = vec![];
+        let mut failed = None;
+        let resp = loop {
+            let (client, sender) = match failed.take() {
+                None => self.wait_for_client().await?,
+                Some(e) => match self.pick_client(&tried).await {
+                    Some(client) => client,
+                    None => return Err(e),
+                },
+            };
+            tried.push(client);
+
+            let id = self
+                .id_count
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+            let mut map = self.ok_one.lock().await;
+            let (tx, rx) = oneshot::channel::<MessageResult<String>>();
+            map.insert(id, (client, tx))
+                .is_none()
+                .then(|| Some(()))
+                .expect("request with this id already exists");
+            drop(map);
+            let mut pending = PendingRequest {
+                fe: self.clone(),
+                id,
+                client,
+                done: false,
+            };
+
+            let sent = sender
+                .send(Message {
+                    id: Some(id),
+                    data: MessageResult::Request(req.clone()),
+                })
+                .await;
+            if sent.is_err() {
+                failed = Some(anyhow::anyhow!("frontend disconnected"));
+                continue;
+            }
 
-        let resp = rx.await?;
+            match tokio::time::timeout(self.timeout, rx).await {
+                Ok(Ok(resp)) => {
+                    pending.done = true;
+                    break resp;
+                }
+                Ok(Err(_)) => {
+                    pending.done = true;
+                    failed = Some(anyhow::anyhow!("frontend disconnected"));
+                }
+                Err(_) => {
+                    // the frontend might still act on it (like, next). so it is not sent again.
+                    // the failure makes the next requests go to another client
+                    self.set_failures(client, |f| f + 1).await;
+                    return Err(anyhow::anyhow!(
+                        "frontend did not respond within {:?}",
+                        self.timeout
+                    ));
+                }
+            }
+        };
         match resp {
             MessageResult::OkOne(resp) => {
                 let resp = serde_json::from_str(&resp)?;
@@ -101,47 +323,80 @@ impl<R: Send + Sync + Serialize + 'static> FrontendClient<R> {
                 "got 'Request' where 'OkOne' was expected: {}",
                 data
             ))),
+            MessageResult::Cancel | MessageResult::Register(_) => Err(anyhow::anyhow!(
+                "got an unexpected message where 'OkOne' was expected"
+            )),
             MessageResult::Err(e) => Err(e.into()),
         }
     }
 
+    /// dropping the stream before it ends cancels the request
     pub async fn get_many<T: for<'de> Deserialize<'de>>(
         &self,
         req: R,
     ) -> anyhow::Result<impl Stream<Item = anyhow::Result<T>>> {
+        let (client, sender) = self.wait_for_client().await?;
         let id = self
             .id_count
             .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
 
         let mut map = self.ok_many.lock().await;
         let (tx, rx) = mpsc::channel::<MessageResult<String>>(20);
-        map.insert(id, tx)
+        map.insert(id, (client, tx))
             .is_none()
             .then(|| Some(()))
             .expect("request with this id already exists");
         drop(map);
+        let pending = PendingRequest {
+            fe: self.clone(),
+            id,
+            client,
+            done: false,
+        };
 
-        self.request_sender
+        sender
             .send(Message {
                 id: Some(id),
                 data: MessageResult::Request(req),
             })
-            .await?;
+            .await
+            .ok()
+            .context("frontend disconnected")?;
 
-        let resp = ReceiverStream::new(rx).map(|m| match m {
-            MessageResult::OkOne(resp) => Err(anyhow::anyhow!(format!(
-                "got 'OkOne' where 'OkMany' was expected: {}",
-                resp
-            ))),
-            MessageResult::Request(req) => Err(anyhow::anyhow!(format!(
-                "got 'Request' where 'OkMany' was expected: {}",
-                req
-            ))),
-            MessageResult::OkMany { data, .. } => {
-                let data = serde_json::from_str::<T>(&data)?;
-                Ok(data)
-            }
-            MessageResult::Err(e) => Err(e.into()),
+        let timeout = self.timeout;
+        let resp = futures::stream::unfold(Some((rx, pending)), move |state| async move {
+            let (mut rx, mut pending) = state?;
+            let m = match tokio::time::timeout(timeout, rx.recv()).await {
+                Ok(Some(m)) => m,
+                Ok(None) => {
+                    pending.done = true;
+                    return None;
+                }
+                Err(_) => {
+                    pending.fe.set_failures(pending.client, |f| f + 1).await;
+                    let e = anyhow::anyhow!("frontend did not respond within {:?}", timeout);
+                    return Some((Err(e), None));
+                }
+            };
+            pending.done = m.is_done();
+            let item = match m {
+                MessageResult::OkOne(resp) => Err(anyhow::anyhow!(format!(
+                    "got 'OkOne' where 'OkMany' was expected: {}",
+                    resp
+                ))),
+                MessageResult::Request(req) => Err(anyhow::anyhow!(format!(
+                    "got 'Request' where 'OkMany' was expected: {}",
+                    req
+                ))),
+                MessageResult::OkMany { data, .. } => {
+                    serde_json::from_str::<T>(&data).map_err(anyhow::Error::from)
+                }
+                MessageResult::Cancel | MessageResult::Register(_) => Err(anyhow::anyhow!(
+                    "got an unexpected message where 'OkMany' was expected"
+                )),
+                MessageResult::Err(e) => Err(e.into()),
+            };
+            Some((item, Some((rx, pending))))
         });
         Ok(resp)
     }
@@ -156,10 +411,22 @@ impl<R: Send + Sync + Serialize + 'static> FrontendClient<R> {
                 ws.on_upgrade(move |ws| async move {
                     let (mut wstx, mut wsrx) = ws.split();
 
-                    let f = fe.clone();
+                    // the client gets requests once it registers
+                    let client = fe
+                        .client_count
+                        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    let (tx, rx) = mpsc::channel::<Message<R>>(100);
+                    fe.clients.lock().await.connected.insert(
+                        client,
+                        ConnectedClient {
+                            info: None,
+                            sender: tx,
+                            failures: 0,
+                        },
+                    );
+
                     let j = tokio::task::spawn(async move {
-                        let fe = f;
-                        let mut rx = fe.request_receiver.lock().await;
+                        let mut rx = ReceiverStream::new(rx);
                         while let Some(msg) = rx.next().await {
                             let msg = serde_json::to_string(&msg).unwrap();
                             let msg = ws::Message::text(msg);
@@ -175,8 +442,9 @@ impl<R: Send + Sync + Serialize + 'static> FrontendClient<R> {
                         }
                     });
 
-                    async fn message_handler<R>(
+                    async fn message_handler<R: Clone + Send + Sync + Serialize + 'static>(
                         fe: &FrontendClient<R>,
+                        client: ClientId,
                         msg: ws::Message,
                     ) -> anyhow::Result<()> {
                         let Some(msg) = msg.to_str().ok() else {
@@ -186,67 +454,64 @@ impl<R: Send + Sync + Serialize + 'static> FrontendClient<R> {
                         match msg.id {
                             Some(id) => match msg.data {
                                 MessageResult::OkMany { data, done, index } => {
-                                    let mut map = fe.ok_many.lock().await;
-                                    let tx = map.remove(&id).context("sender already taken")?;
+                                    let tx = take_pending(&fe.ok_many, id, client)
+                                        .await
+                                        .context("sender already taken")?;
+                                    // the lock is not held while waiting on the consumer
                                     tx.send(MessageResult::OkMany { data, done, index })
                                         .await
                                         .ok()
                                         .context("could not send over channel (1)")?;
                                     if !done {
-                                        map.insert(id, tx);
+                                        fe.ok_many.lock().await.insert(id, (client, tx));
                                     }
                                 }
                                 MessageResult::OkOne(msg) => {
-                                    let mut map = fe.ok_one.lock().await;
-                                    let tx = map.remove(&id).context("sender already taken")?;
+                                    let tx = take_pending(&fe.ok_one, id, client)
+                                        .await
+                                        .context("sender already taken")?;
                                     tx.send(MessageResult::OkOne(msg))
                                         .ok()
                                         .context("could not send over channel (2)")?;
                                 }
                                 MessageResult::Err(err) => {
-                                    let mut onemap = fe.ok_one.lock().await;
-                                    let mut manymap = fe.ok_many.lock().await;
-                                    if let Some(tx) = onemap.remove(&id) {
+                                    if let Some(tx) = take_pending(&fe.ok_one, id, client).await {
                                         tx.send(MessageResult::Err(err))
                                             .ok()
                                             .context("could not send over channel (3)")?;
                                     } else {
-                                        let tx =
-                                            manymap.remove(&id).context("sender already taken")?;
+                                        let tx = take_pending(&fe.ok_many, id, client)
+                                            .await
+                                            .context("sender already taken")?;
                                         tx.send(MessageResult::Err(err))
                                             .await
                                             .ok()
                                             .context("could not send over channel (4)")?;
                                     }
                                 }
+                                // the server never gets requests from this frontend
+                                MessageResult::Cancel => {}
+                                MessageResult::Register(info) => {
+                                    fe.register(client, info).await;
+                                }
                                 MessageResult::Request(msg) => {
-                                    let mut onemap = fe.ok_one.lock().await;
-                                    let mut manymap = fe.ok_many.lock().await;
-                                    if let Some(tx) = onemap.remove(&id) {
-                                        let mesg = format!(
-                                            "this WS does not support requests from frontend: {}",
-                                            msg
-                                        );
-                                        tx.send(MessageResult::Err(ErrorMessage {
-                                            message: mesg.clone(),
-                                            stack_trace: mesg,
-                                        }))
-                                        .ok()
-                                        .context("could not send over channel (5)")?;
+                                    let mesg = format!(
+                                        "this WS does not support requests from frontend: {}",
+                                        msg
+                                    );
+                                    let err = ErrorMessage::new(ErrorKind::Invalid, mesg);
+                                    if let Some(tx) = take_pending(&fe.ok_one, id, client).await {
+                                        tx.send(MessageResult::Err(err))
+                                            .ok()
+                                            .context("could not send over channel (5)")?;
                                     } else {
-                                        let tx =
-                                            manymap.remove(&id).context("sender already taken")?;
-                                        let mesg = format!(
-                                            "this WS does not support requests from frontend: {}",
-                                            msg
-                                        );
-                                        tx.send(MessageResult::Err(ErrorMessage {
-                                            message: mesg.clone(),
-                                            stack_trace: mesg,
-                                        }))
-                                        .await
-                                        .ok()
-                                        .context("could not send over channel (6)")?;
+                                        let tx = take_pending(&fe.ok_many, id, client)
+                                            .await
+                                            .context("sender already taken")?;
+                                        tx.send(MessageResult::Err(err))
+                                            .await
+                                            .ok()
+                                            .context("could not send over channel (6)")?;
                                     }
                                 }
                             },
@@ -262,14 +527,20 @@ impl<R: Send + Sync + Serialize + 'static> FrontendClient<R> {
                                 MessageResult::Err(e) => {
                                     println!("frontend could not fullfill some request: {}", e);
                                 }
+                                MessageResult::Cancel => {}
+                                MessageResult::Register(info) => {
+                                    fe.register(client, info).await;
+                                }
                             },
                         }
+                        // the client is alive
+                        fe.set_failures(client, |_| 0).await;
                         Ok(())
                     }
 
                     while let Some(msg) = wsrx.next().await {
                         match msg {
-                            Ok(msg) => match message_handler(&fe, msg).await {
+                            Ok(msg) => match message_handler(&fe, client, msg).await {
                                 Ok(_) => (),
                                 Err(e) => {
                                     eprintln!("Error: {}", &e);
@@ -283,9 +554,17 @@ impl<R: Send + Sync + Serialize + 'static> FrontendClient<R> {
 
                     // NOTE: abort drops everything correctly. so this is fine
                     j.abort();
+
+                    let mut clients = fe.clients.lock().await;
+                    clients.connected.remove(&client);
+                    if clients.primary == Some(client) {
+                        clients.primary = None;
+                    }
+                    drop(clients);
+                    // requests sent to this client will never get a response
+                    fe.fail_pending(client).await;
                 })
             });
-        let ws_route = ws_route.with(warp::cors().allow_any_origin());
         ws_route.boxed()
     }
 }
@@ -320,10 +599,16 @@ impl FeRequest {
             .and(warp::any().map(move || fe.clone()))
             .and(warp::body::json())
             .and_then(|fe: FrontendClient<_>, req: FeRequest| async move {
-                fe.get_one::<()>(req).await.map_err(custom_reject)?;
+                match req {
+                    FeRequest::Notify(_) | FeRequest::NotifyError(_) => {
+                        fe.broadcast(req).await.map_err(custom_reject)?;
+                    }
+                    _ => {
+                        fe.get_one::<()>(req).await.map_err(custom_reject)?;
+                    }
+                }
                 Ok::<_, warp::Rejection>(warp::reply())
             });
-        let route = route.with(warp::cors().allow_any_origin());
         route.boxed()
     }
 }
@@ -401,13 +686,13 @@ pub struct ProxyRequest {
 
 impl ProxyRequest {
     // - [danielSanchezQ/warp-reverse-proxy](https://github.com/danielSanchezQ/warp-reverse-proxy)
-    pub fn cors_proxy_route(c: reqwest::Client) -> BoxedFilter<(impl Reply,)> {
+    pub fn cors_proxy_route(proxy: Proxy) -> BoxedFilter<(impl Reply,)> {
         let cors_proxy = warp::path("fetch")
             .and(warp::path::end())
             // .and(warp::post())
             .and(warp::body::bytes())
-            .and(warp::any().map(move || c.clone()))
-            .and_then(|fetch: bytes::Bytes, c: reqwest::Client| async move {
+            .and(warp::any().map(move || proxy.clone()))
+            .and_then(|fetch: bytes::Bytes, proxy: Proxy| async move {
                 let fetch = fetch.to_vec();
                 if fetch.is_empty() {
                     // OOF: for preflight requests. idk what else to do
@@ -419,30 +704,27 @@ impl ProxyRequest {
                     .map_err(custom_reject)?;
                 let headers: Vec<(String, String)> =
                     serde_json::from_str(&fetch.headers).map_err(custom_reject)?;
-                let url = reqwest::Url::parse(&fetch.url).map_err(custom_reject)?;
-                let mut url = c.request(
-                    reqwest::Method::from_bytes(fetch.method.as_bytes()).map_err(custom_reject)?,
-                    url,
-                );
-                if let Some(body) = fetch.body {
-                    url = url.body(body);
-                }
-                for (k, v) in headers {
-                    url = url.header(k, v);
-                }
-                let res = c
-                    .execute(url.build().map_err(custom_reject)?)
+                let res = proxy
+                    .send("fetch", &fetch.method, &fetch.url, |mut req| {
+                        if let Some(body) = fetch.body.clone() {
+                            req = req.body(body);
+                        }
+                        for (k, v) in headers {
+                            req = req.header(k, v);
+                        }
+                        Ok(req)
+                    })
                     .await
                     .map_err(custom_reject)?;
+
                 let mut wres = warp::http::Response::builder();
-                for (k, v) in res.headers().iter() {
+                for (k, v) in proxy.headers(&res).iter() {
                     wres = wres.header(k, v);
                 }
                 let status = res.status();
-                let body = warp::hyper::Body::wrap_stream(res.bytes().into_stream());
+                let body = warp::hyper::Body::wrap_stream(proxy.body(res));
                 wres.status(status).body(body).map_err(custom_reject)
             });
-        let cors_proxy = cors_proxy.with(warp::cors().allow_any_origin());
         cors_proxy.boxed()
     }
 }
@@ -528,7 +810,6 @@ impl AppState {
 
                 Ok::<_, warp::Rejection>(warp::reply())
             });
-        let handler = handler.with(warp::cors().allow_any_origin());
         handler.boxed()
     }
 }
@@ -590,7 +871,6 @@ pub fn source_path_route(
             Ok::<_, warp::Rejection>(warp::reply::json(&path))
         });
 
-    let route = route.with(warp::cors().allow_any_origin());
     route.boxed()
 }
 
@@ -685,7 +965,6 @@ pub fn stream_yt(path: &'static str, st: SongTubeFac) -> BoxedFilter<(impl Reply
             },
         );
 
-    let route = route.with(warp::cors().allow_any_origin());
     route.boxed()
 }
 
@@ -746,7 +1025,6 @@ pub fn stream_file(path: &'static str, config: Arc<DerivedConfig>) -> BoxedFilte
             },
         );
 
-    let route = route.with(warp::cors().allow_any_origin());
     route.boxed()
 }
 
@@ -777,7 +1055,6 @@ pub fn save_song_route(
             Ok::<_, warp::Rejection>(warp::reply::json(&path))
         });
 
-    let route = route.with(warp::cors().allow_any_origin());
     route.boxed()
 }
 
@@ -791,37 +1068,30 @@ pub fn save_song_route(
 pub struct ImageQuery {
     src: String,
 }
-pub fn image_route(
-    path: &'static str,
-    c: reqwest::Client,
-    config: Arc<DerivedConfig>,
-) -> BoxedFilter<(impl Reply,)> {
+pub fn image_route(path: &'static str, proxy: Proxy) -> BoxedFilter<(impl Reply,)> {
     let redirect = warp::path(path)
         .and(warp::path::end())
         .and(warp::query::<ImageQuery>())
         .and(warp::header::headers_cloned())
-        .and(warp::any().map(move || c.clone()))
-        .and(warp::any().map(move || config.clone()))
+        .and(warp::any().map(move || proxy.clone()))
         .and_then(
-            |query: ImageQuery,
-             headers: warp::http::HeaderMap,
-             client: reqwest::Client,
-             _config: Arc<DerivedConfig>| async move {
-                let mut req = client.get(&query.src);
-                for k in [
-                    "accept",
-                    "accept-encoding",
-                    "accept-language",
-                    "connection",
-                    "DNT",
-                    "user-agent",
-                ] {
-                    if let Some(v) = headers.get(k) {
-                        req = req.header(k, v);
-                    }
-                }
-                let res = client
-                    .execute(req.build().map_err(custom_reject)?)
+            |query: ImageQuery, headers: warp::http::HeaderMap, proxy: Proxy| async move {
+                let res = proxy
+                    .send("image", "GET", &query.src, |mut req| {
+                        for k in [
+                            "accept",
+                            "accept-encoding",
+                            "accept-language",
+                            "connection",
+                            "DNT",
+                            "user-agent",
+                        ] {
+                            if let Some(v) = headers.get(k) {
+                                req = req.header(k, v);
+                            }
+                        }
+                        Ok(req)
+                    })
                     .await
                     .map_err(custom_reject)?;
 
@@ -832,7 +1102,7 @@ pub fn image_route(
                     "age",
                     "cache-control",
                     "alt-svc",
-                    "content-length",
+                    // no content-length. the body can get cut off at the size limit
                     "content-type",
                     "server",
                 ] {
@@ -843,9 +1113,8 @@ pub fn image_route(
                 // for (k, v) in res.headers().iter() {
                 //     wres = wres.header(k, v);
                 // }
-                wres = wres.header("access-control-allow-origin", "*");
                 let status = res.status();
-                let body = warp::hyper::Body::wrap_stream(res.bytes().into_stream());
+                let body = warp::hyper::Body::wrap_stream(proxy.body(res));
                 wres.status(status).body(body).map_err(custom_reject)
             },
         );
@@ -0,0 +1,230 @@
+use std::collections::{hash_map::RandomState, HashMap, HashSet};
+use std::hash::BuildHasher;
+
+use sea_orm::{
+    sea_query::Expr, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect,
+};
+
+use crate::covau_types::{PlaySource, Playlist, SmartPlaylist, SmartRule, SmartSort, Song};
+use crate::db::{
+    haystack_contains, metadata_column, metadata_value, object, play, Db, DbAble, DbId, DbItem,
+    Typ, BATCH_SIZE,
+};
+use crate::dedup::artist_key;
+
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+/// the songs of a smart playlist right now. rules that sql can check narrow down the query,
+/// the rest are checked on the parsed songs
+pub async fn evaluate<C: ConnectionTrait>(
+    conn: &C,
+    sp: &SmartPlaylist,
+) -> anyhow::Result<Vec<DbItem<Song>>> {
+    let mut q = object::Entity::find().filter(object::Column::Typ.eq(Song::typ()));
+
+    // normalized names, ids
+    let mut artist_rules = vec![];
+    let mut local_file = false;
+    // songs that every InPlaylist rule allows
+    let mut in_playlists: Option<HashSet<DbId>> = None;
+    for rule in sp.rules.iter() {
+        q = match rule {
+            SmartRule::ArtistIn(artists) => {
+                let names = artists
+                    .iter()
+                    .map(|a| artist_key(&a.name))
+                    .filter(|a| !a.is_empty())
+                    .collect::<HashSet<_>>();
+                let ids = artists
+                    .iter()
+                    .filter_map(|a| a.source.clone())
+                    .collect::<Vec<_>>();
+                artist_rules.push((names, ids));
+                q
+            }
+            SmartRule::Liked => q.filter(object::Column::Likes.gt(0)),
+            SmartRule::NotPlayedFor { days } => {
+                let since = Db::timestamp().saturating_sub(*days as u64 * DAY_SECS);
+                q.filter(Expr::cust_with_values(
+                    "id NOT IN (SELECT song_id FROM covau_plays WHERE started_ts >= ?)",
+                    [since as i64],
+                ))
+            }
+            SmartRule::AddedAfter { ts } => q.filter(object::Column::AddedTs.gt(*ts as i64)),
+            SmartRule::HasLocalFile => {
+                local_file = true;
+                q
+            }
+            SmartRule::InPlaylist(playlists) => {
+                let songs = playlist_songs(conn, playlists).await?;
+                in_playlists = Some(match in_playlists {
+                    Some(ids) => ids.intersection(&songs).copied().collect(),
+                    None => songs,
+                });
+                q
+            }
+            SmartRule::Text(text) => {
+                // same as [Db::query]
+                for word in text.split_whitespace() {
+                    q = q.filter(haystack_contains(word));
+                }
+                q
+            }
+        };
+    }
+
+    let order = if sp.desc {
+        sea_orm::Order::Desc
+    } else {
+        sea_orm::Order::Asc
+    };
+    if let Some(SmartSort::Metadata(field)) = sp.sort {
+        q = q.order_by(metadata_column(field), order.clone());
+    }
+    q = q.order_by(object::Column::Id, order);
+    // the limit can only go in the query if nothing else drops or reorders songs
+    let sql_only = artist_rules.is_empty()
+        && !local_file
+        && matches!(sp.sort, None | Some(SmartSort::Metadata(_)));
+    if let (true, Some(limit)) = (sql_only, sp.limit) {
+        q = q.limit(limit as u64);
+    }
+
+    let models = match in_playlists {
+        None => q.all(conn).await?,
+        Some(ids) => {
+            // playlists can have more songs than sqlite takes in one query. every chunk comes
+            // back sorted (and limited), so sorting them together again gives the same order
+            let ids = ids.into_iter().collect::<Vec<_>>();
+            let mut models = vec![];
+            for chunk in ids.chunks(BATCH_SIZE) {
+                let q = q
+                    .clone()
+                    .filter(object::Column::Id.is_in(chunk.iter().copied()));
+                models.extend(q.all(conn).await?);
+            }
+            let field = match sp.sort {
+                Some(SmartSort::Metadata(field)) => Some(field),
+                _ => None,
+            };
+            models.sort_by_key(|m| (field.map(|f| metadata_value(m, f)), m.id));
+            if sp.desc {
+                models.reverse();
+            }
+            models
+        }
+    };
+
+    let mut songs = models
+        .into_iter()
+        .filter_map(|m| {
+            Some(DbItem {
+                metadata: m.db_metadata(),
+                t: m.parsed_or_skip::<Song>()?,
+                id: m.id,
+                typ: Song::typ(),
+            })
+        })
+        .filter(|s| {
+            artist_rules
+                .iter()
+                .all(|(names, ids)| has_artist(&s.t, names, ids))
+        })
+        .filter(|s| {
+            !local_file
+                || s.t
+                    .play_sources
+                    .iter()
+                    .any(|p| matches!(p, PlaySource::File(_)))
+        })
+        .collect::<Vec<_>>();
+
+    match sp.sort {
+        None | Some(SmartSort::Metadata(_)) => {}
+        Some(SmartSort::Title) => {
+            songs.sort_by_cached_key(|s| s.t.title.to_lowercase());
+            if sp.desc {
+                songs.reverse();
+            }
+        }
+        Some(SmartSort::Plays) | Some(SmartSort::LastPlayed) => {
+            let ids = songs.iter().map(|s| s.id).collect::<Vec<_>>();
+            let plays = play_counts(conn, &ids).await?;
+            let key = |id: DbId| {
+                let (count, last) = plays.get(&id).copied().unwrap_or((0, 0));
+                match sp.sort {
+                    Some(SmartSort::Plays) => count,
+                    _ => last,
+                }
+            };
+            // stable. ties stay in id order
+            songs.sort_by_key(|s| key(s.id));
+            if sp.desc {
+                songs.reverse();
+            }
+        }
+        Some(SmartSort::Random) => {
+            let state = RandomState::new();
+            songs.sort_by_cached_key(|s| state.hash_one(s.id));
+        }
+    }
+    if let Some(limit) = sp.limit {
+        songs.truncate(limit as usize);
+    }
+
+    Ok(songs)
+}
+
+fn has_artist(
+    song: &Song,
+    names: &HashSet<String>,
+    ids: &[crate::covau_types::InfoSource],
+) -> bool {
+    song.artists.iter().any(|a| {
+        a.source.as_ref().is_some_and(|s| ids.contains(s)) || names.contains(&artist_key(&a.name))
+    })
+}
+
+async fn playlist_songs<C: ConnectionTrait>(
+    conn: &C,
+    playlists: &[DbId],
+) -> anyhow::Result<HashSet<DbId>> {
+    let mut songs = HashSet::new();
+    for chunk in playlists.chunks(BATCH_SIZE) {
+        // deleted playlists have no songs. ids of other typs are not playlists
+        let models = object::Entity::find()
+            .filter(object::Column::Id.is_in(chunk.iter().copied()))
+            .filter(object::Column::Typ.eq(Typ::Playlist))
+            .all(conn)
+            .await?;
+        for m in models {
+            if let Some(p) = m.parsed_or_skip::<Playlist>() {
+                songs.extend(p.songs);
+            }
+        }
+    }
+    Ok(songs)
+}
+
+/// song -> (number of plays, started_ts of the last play)
+async fn play_counts<C: ConnectionTrait>(
+    conn: &C,
+    ids: &[DbId],
+) -> anyhow::Result<HashMap<DbId, (i64, i64)>> {
+    let mut counts = HashMap::new();
+    for chunk in ids.chunks(BATCH_SIZE) {
+        let rows = play::Entity::find()
+            .select_only()
+            .column(play::Column::SongId)
+            .column_as(play::Column::Id.count(), "plays")
+            .column_as(play::Column::StartedTs.max(), "last")
+            .filter(play::Column::SongId.is_in(chunk.iter().copied()))
+            .group_by(play::Column::SongId)
+            .into_tuple::<(DbId, i64, i64)>()
+            .all(conn)
+            .await?;
+        counts.extend(rows.into_iter().map(|(id, n, last)| (id, (n, last))));
+    }
+    Ok(counts)
+}
@@ -0,0 +1,250 @@
+use std::path::{Path, PathBuf};
+
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QueryOrder, QuerySelect, TransactionTrait};
+use serde::{Deserialize, Serialize};
+
+use crate::config::DerivedConfig;
+use crate::covau_types::{PlaySource, Song, Updater};
+use crate::db::{object, Db, DbAble, DbId, Typ};
+
+// how many items the most liked / most interacted lists have
+const TOP_ITEMS: u64 = 10;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct LibraryStats {
+    pub objects: Vec<TypCount>,
+    // covau Songs with at least 1 PlaySource::File
+    pub songs_with_file: u32,
+    pub songs_without_file: u32,
+    pub disk_usage: Vec<DiskUsage>,
+    pub most_liked: Vec<TopItem>,
+    pub most_interacted: Vec<TopItem>,
+    pub updaters: UpdaterStats,
+    pub db_bytes: u64,
+    pub wal_bytes: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TypCount {
+    pub typ: Typ,
+    pub count: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DiskUsage {
+    pub name: String,
+    pub path: PathBuf,
+    pub exists: bool,
+    pub files: u32,
+    pub bytes: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TopItem {
+    pub id: DbId,
+    pub typ: Typ,
+    // first entry of the haystack
+    pub name: Option<String>,
+    pub likes: u32,
+    pub interactions: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct UpdaterStats {
+    pub enabled: u32,
+    pub disabled: u32,
+    // enabled, but not updated in [Updater::CHECK_INTERVAL_SECS]
+    pub stale: u32,
+}
+
+pub async fn collect(db: &Db, config: &DerivedConfig) -> anyhow::Result<LibraryStats> {
+    let mut stats = LibraryStats::default();
+
+    // everything from 1 snapshot of the db
+    let txn = db.db.begin().await?;
+
+    let counts = object::Entity::find()
+        .select_only()
+        .column(object::Column::Typ)
+        .column_as(object::Column::Id.count(), "count")
+        .group_by(object::Column::Typ)
+        .into_tuple::<(Typ, i64)>()
+        .all(&txn)
+        .await?;
+    stats.objects = counts
+        .into_iter()
+        .map(|(typ, count)| TypCount {
+            typ,
+            count: count as _,
+        })
+        .collect();
+
+    let songs = object::Entity::find()
+        .filter(object::Column::Typ.eq(Song::typ()))
+        .all(&txn)
+        .await?;
+    for m in songs {
+        let Some(song) = m.parsed_or_skip::<Song>() else {
+            continue;
+        };
+        if song
+            .play_sources
+            .iter()
+            .any(|s| matches!(s, PlaySource::File(_)))
+        {
+            stats.songs_with_file += 1;
+        } else {
+            stats.songs_without_file += 1;
+        }
+    }
+
+    let now = Db::timestamp();
+    let updaters = object::Entity::find()
+        .filter(object::Column::Typ.eq(Updater::typ()))
+        .all(&txn)
+        .await?;
+    for m in updaters {
+        let Some(u) = m.parsed_or_skip::<Updater>() else {
+            continue;
+        };
+        if !u.enabled {
+            stats.updaters.disabled += 1;
+            continue;
+        }
+        stats.updaters.enabled += 1;
+        if u.is_due(now) {
+            stats.updaters.stale += 1;
+        }
+    }
+
+    stats.most_liked = top_items(&txn, object::Column::Likes).await?;
+    stats.most_interacted = top_items(&txn, object::Column::Interactions).await?;
+    txn.commit().await?;
+
+    let mut dirs = vec![("music".to_string(), config.music_path.clone())];
+    if let Some(mm) = config.musimanager.as_ref() {
+        dirs.push(("musimanager music".to_string(), mm.music_path.clone()));
+        dirs.push(("musimanager temp".to_string(), mm.temp_music_path.clone()));
+    }
+    stats.disk_usage = tokio::task::spawn_blocking(move || {
+        dirs.into_iter()
+            .map(|(name, path)| disk_usage(name, path))
+            .collect()
+    })
+    .await?;
+
+    stats.db_bytes = file_size(&config.db_path.join("music.db"));
+    stats.wal_bytes = file_size(&config.db_path.join("music.db-wal"));
+
+    Ok(stats)
+}
+
+async fn top_items<C: sea_orm::ConnectionTrait>(
+    conn: &C,
+    col: object::Column,
+) -> anyhow::Result<Vec<TopItem>> {
+    let models = object::Entity::find()
+        .filter(col.gt(0))
+        .order_by_desc(col)
+        .order_by_asc(object::Column::Id)
+        .limit(TOP_ITEMS)
+        .all(conn)
+        .await?;
+    let items = models
+        .into_iter()
+        .map(|m| TopItem {
+            id: m.id,
+            name: name(&m.typ, &m.data),
+            typ: m.typ,
+            likes: m.likes as _,
+            interactions: m.interactions as _,
+        })
+        .collect();
+    Ok(items)
+}
+
+fn first_haystack<T: DbAble>(data: &str) -> Option<String> {
+    let t: T = serde_json::from_str(data).ok()?;
+    let name = t.haystack().into_iter().next();
+    name
+}
+
+fn name(typ: &Typ, data: &str) -> Option<String> {
+    use crate::{covau_types as covau, mbz, musimanager as mm, yt::song_tube as st};
+    type MmSong = mm::Song<Option<mm::SongInfo>, covau::SourcePath>;
+
+    match typ {
+        Typ::MmSong => first_haystack::<MmSong>(data),
+        Typ::MmAlbum => first_haystack::<mm::Album<crate::yt::VideoId>>(data),
+        Typ::MmArtist => first_haystack::<mm::Artist<crate::yt::VideoId, crate::yt::AlbumId>>(data),
+        Typ::MmPlaylist => first_haystack::<mm::Playlist<crate::yt::VideoId>>(data),
+        Typ::MmQueue => first_haystack::<mm::Queue<crate::yt::VideoId>>(data),
+        Typ::LocalState => first_haystack::<covau::LocalState>(data),
+        // haystack of songs has the artists first
+        Typ::Song => serde_json::from_str::<covau::Song>(data)
+            .ok()
+            .map(|s| s.title),
+        Typ::Playlist => first_haystack::<covau::Playlist>(data),
+        Typ::Queue => first_haystack::<covau::Queue>(data),
+        Typ::ArtistBlacklist => first_haystack::<covau::ArtistBlacklist>(data),
+        Typ::SongBlacklist => first_haystack::<covau::SongBlacklist>(data),
+        Typ::Updater => first_haystack::<covau::Updater>(data),
+        Typ::SmartPlaylist => first_haystack::<covau::SmartPlaylist>(data),
+        Typ::StSong => first_haystack::<st::Song>(data),
+        Typ::StAlbum => first_haystack::<st::Album>(data),
+        Typ::StPlaylist => first_haystack::<st::Playlist>(data),
+        Typ::StArtist => first_haystack::<st::Artist>(data),
+        Typ::MbzRecording => first_haystack::<mbz::RecordingWithInfo>(data),
+        Typ::MbzArtist => first_haystack::<mbz::Artist>(data),
+    }
+}
+
+fn disk_usage(name: String, path: PathBuf) -> DiskUsage {
+    let mut usage = DiskUsage {
+        name,
+        exists: path.is_dir(),
+        path,
+        files: 0,
+        bytes: 0,
+    };
+
+    // symlinks are not followed
+    let mut dirs = vec![usage.path.clone()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for e in entries.flatten() {
+            let Ok(meta) = e.metadata() else {
+                continue;
+            };
+            if meta.is_dir() {
+                dirs.push(e.path());
+            } else if meta.is_file() {
+                usage.files += 1;
+                usage.bytes += meta.len();
+            }
+        }
+    }
+    usage
+}
+
+fn file_size(path: &Path) -> u64 {
+    std::fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+}
+
+/// 1.2 GiB etc
+pub fn human_bytes(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, units[0])
+    } else {
+        format!("{:.1} {}", size, units[unit])
+    }
+}
@@ -3,6 +3,7 @@ import { exhausted } from "$lib/utils.ts";
 import type { PlayerMessage, PlayerCommand } from "$types/server.ts";
 import type { MessageHandler, Player } from "$lib/stores.ts";
 import type { ListItem } from "$lib/searcher/item.ts";
+import * as server from "$lib/server.ts";
 
 export class Musiplayer implements Player {
     ws: WebSocket;
@@ -19,7 +20,7 @@ export class Musiplayer implements Player {
     private closed: Promise<void>;
     private constructor() {
         this.listeners = new Map();
-        this.ws = new WebSocket(`ws://localhost:${import.meta.env.SERVER_PORT}/player`);
+        this.ws = new WebSocket(server.utils.ws_url("player"));
         this.ws.addEventListener('message', async (e) => {
             let message: PlayerMessage = JSON.parse(e.data);
 
@@ -21,6 +21,18 @@ export class QueueManager implements Searcher {
 
     state: "Unstarted" | "Playing" | "Detour" | "Finished" = "Unstarted";
 
+    // the item the player is on and when it started. logged to the play history once it changes.
+    // listened_ms only counts time the player was actually playing (resumed_at is set while it is)
+    current_play: {
+        item: ListItem,
+        started_ts: number,
+        autoplay: boolean,
+        listened_ms: number,
+        resumed_at: number | null,
+    } | null = null;
+    // set when the next item to play was picked by autoplay
+    protected next_play_is_autoplay: boolean = false;
+
     has_next_page: boolean = true;
     async next_page(): Promise<ListItem[]> {
         this.has_next_page = false;
@@ -28,6 +40,8 @@ export class QueueManager implements Searcher {
     }
 
     reset() {
+        // whatever keeps playing after this is not a play of this queue
+        this.end_play();
         this.items = [];
         this.playing_index = null;
         let is_playing = get(player).is_playing();
@@ -244,11 +258,26 @@ export class QueueManager implements Searcher {
     async play_item(item: ListItem) {
         try {
             let p = get(player);
+            let autoplay = this.next_play_is_autoplay;
+            this.next_play_is_autoplay = false;
+            await this.log_play(!p.is_finished());
+            p.on_message(this.on_player_message);
+            this.current_play = {
+                item,
+                started_ts: Math.floor(Date.now() / 1000),
+                autoplay,
+                listened_ms: 0,
+                resumed_at: null,
+            };
             await p.play_item(item);
+            if (p.is_playing() && this.current_play) {
+                this.current_play.resumed_at ??= Date.now();
+            }
             playing_item.set(item);
             player.update(p => p);
             return true;
         } catch (e: any) {
+            this.current_play = null;
             if (e instanceof Error) {
                 toast(e.message, "error");
             } else {
@@ -258,6 +287,83 @@ export class QueueManager implements Searcher {
         }
     }
 
+    protected play_queue_id(): number | null {
+        return null;
+    }
+
+    // pauses (and the time until the next item starts) do not count as listening
+    protected on_player_message = (m: types.server.PlayerMessage) => {
+        let play = this.current_play;
+        if (!play) {
+            return;
+        }
+        switch (m.type) {
+            case "Playing":
+            case "Unpaused":
+                play.resumed_at ??= Date.now();
+                break;
+            case "Paused":
+            case "Finished":
+            case "Error":
+                if (play.resumed_at != null) {
+                    play.listened_ms += Date.now() - play.resumed_at;
+                    play.resumed_at = null;
+                }
+                break;
+        }
+    };
+
+    // logs what is playing now. eg: before the page goes away.
+    // not a skip, the user did not move on to something else
+    end_play() {
+        return this.log_play(false);
+    }
+
+    // only songs that are in the db are logged
+    protected async log_play(skipped: boolean) {
+        let play = this.current_play;
+        this.current_play = null;
+        if (!play || !["Song", "MmSong"].includes(play.item.typ())) {
+            return;
+        }
+        // before anything is awaited. the queue can change in the meantime
+        let queue_id = this.play_queue_id();
+        let listened_ms = play.listened_ms;
+        if (play.resumed_at != null) {
+            listened_ms += Date.now() - play.resumed_at;
+        }
+
+        let id = play.item.mega_id().dbid;
+        if (id == null) {
+            return;
+        }
+
+        let path = play.item.source_path();
+        let yt_id = path ? null : await play.item.yt_id();
+        let source: types.covau.PlaySource;
+        if (path) {
+            source = { type: "File", content: path };
+        } else if (yt_id) {
+            source = { type: "YtId", content: yt_id };
+        } else {
+            return;
+        }
+
+        try {
+            await server.db.log_play({
+                song: id,
+                queue: queue_id,
+                started_ts: play.started_ts.toString(),
+                listened_secs: Math.floor(listened_ms / 1000),
+                skipped,
+                source,
+                autoplay: play.autoplay,
+            });
+        } catch (e: any) {
+            console.error(e);
+        }
+    }
+
     // must not call the db
     async sync_play(item: ListItem) {
         if (this.state == "Detour") {
@@ -416,6 +522,9 @@ export type AutoplayQueryInfo = {
     type: "MbzRadio",
     title: string | null,
     artists: string[], // name (or id) straight from mbz
+} | {
+    type: "SmartPlaylist",
+    sp: types.covau.SmartPlaylist,
 };
 export type AutoplayTyp = "StSearchRelated" | "StRelated" | "MbzRadio";
 
@@ -454,6 +563,11 @@ export async function autoplay_searcher(q: AutoplayQueryInfo): Promise<Searcher>
                 query: query
             }, 30);
         } break;
+        case "SmartPlaylist": {
+            // autoplay picks from the pool in random order
+            let songs = await server.db.evaluate_smart_playlist({ ...q.sp, sort: { type: "Random" }, limit: null });
+            return StaticSearcher(db.db.wrapped_items(songs));
+        } break;
         default:
             throw exhausted(q);
     }
@@ -829,6 +943,7 @@ export class AutoplayQueueManager extends QueueManager {
             let item = await this.autoplay_consume();
             if (item) {
                 await this.add(item);
+                this.next_play_is_autoplay = true;
             }
         }
         await super.play_next();
@@ -926,6 +1041,11 @@ export class LocalSyncQueue extends AutoplayQueueManager {
         });
     }
 
+    protected play_queue_id(): number | null {
+        let sync = get(stores.syncer);
+        return sync.queue?.t.id ?? null;
+    }
+
     protected async update_queue() {
         let sync = get(stores.syncer);
 
@@ -975,10 +1095,13 @@ export class LocalSyncQueue extends AutoplayQueueManager {
         return res;
     }
     async init_with_seed(item: ListItem): Promise<boolean> {
-        await server.db.txn(async dbops => {
-            let dbitem = await item.saved_covau_song(dbops);
-            item = db.db.wrapped(dbitem!);
-        });
+        // smart playlists are already in the db and are not songs
+        if (item.typ() != "SmartPlaylist") {
+            await server.db.txn(async dbops => {
+                let dbitem = await item.saved_covau_song(dbops);
+                item = db.db.wrapped(dbitem!);
+            });
+        }
         if (this.autoplay_state.state == "Uninit") {
             await stores.syncops.new.seen();
         }
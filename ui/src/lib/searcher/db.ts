@@ -38,6 +38,7 @@ export type MusicListItem = { id: number, metadata: types.db.DbMetadata } & (
     | { typ: "Song", t: covau.Song }
     | { typ: "Playlist", t: covau.Playlist }
     | { typ: "Queue", t: covau.Queue }
+    | { typ: "SmartPlaylist", t: covau.SmartPlaylist }
     | { typ: "Updater", t: covau.Updater }
     | { typ: "LocalState", t: covau.LocalState }
     | { typ: "ArtistBlacklist", t: covau.ArtistBlacklist }
@@ -148,6 +149,7 @@ export class DbListItem extends ListItem {
             case "StPlaylist":
             case "Playlist":
             case "Queue":
+            case "SmartPlaylist":
             case "Updater":
             case "ArtistBlacklist":
             case "SongBlacklist":
@@ -193,6 +195,7 @@ export class DbListItem extends ListItem {
             case "StArtist":
             case "Playlist":
             case "Queue":
+            case "SmartPlaylist":
             case "Updater":
             case "ArtistBlacklist":
             case "SongBlacklist":
@@ -223,6 +226,7 @@ export class DbListItem extends ListItem {
             case "Song":
             case "Playlist":
             case "Queue":
+            case "SmartPlaylist":
             case "Updater":
             case "LocalState":
             case "ArtistBlacklist":
@@ -259,6 +263,7 @@ export class DbListItem extends ListItem {
             case "StArtist":
             case "Playlist":
             case "Queue":
+            case "SmartPlaylist":
             case "Updater":
             case "ArtistBlacklist":
             case "SongBlacklist":
@@ -302,6 +307,7 @@ export class DbListItem extends ListItem {
             case "StPlaylist":
             case "Playlist":
             case "Queue":
+            case "SmartPlaylist":
             case "Updater":
             case "ArtistBlacklist":
             case "SongBlacklist":
@@ -330,6 +336,7 @@ export class DbListItem extends ListItem {
             case "MmSong":
             case "Song":
             case "Playlist":
+            case "SmartPlaylist":
             case "Updater":
             case "MbzRecording":
                 return t.t.title;
@@ -374,6 +381,7 @@ export class DbListItem extends ListItem {
                 return null;
             case "Queue":
                 return null;
+            case "SmartPlaylist":
             case "Updater":
                 return null;
             case "ArtistBlacklist":
@@ -436,6 +444,8 @@ export class DbListItem extends ListItem {
                 return `item number ${t.id}`;
             case "Playlist":
                 return `${t.t.songs.length} items`;
+            case "SmartPlaylist":
+                return `${t.t.rules.length} rules`;
             case "Updater":
                 return null;
             case "Queue":
@@ -525,6 +535,7 @@ export class DbListItem extends ListItem {
             case "StArtist":
             case "Playlist":
             case "Queue":
+            case "SmartPlaylist":
             case "Updater":
             case "LocalState":
             case "ArtistBlacklist":
@@ -565,6 +576,7 @@ export class DbListItem extends ListItem {
             case "StArtist":
             case "Playlist":
             case "Queue":
+            case "SmartPlaylist":
             case "Updater":
             case "LocalState":
             case "ArtistBlacklist":
@@ -652,6 +664,13 @@ export class DbListItem extends ListItem {
             case "MbzRecording": {
                 return mbz.mbz.recording_autoplay(utils.clone(t.t), typ);
             } break;
+            case "SmartPlaylist": {
+                // same pool for every autoplay type
+                return {
+                    type: "SmartPlaylist",
+                    sp: utils.clone(t.t),
+                };
+            } break;
             case "MmAlbum":
             case "MmArtist":
             case "MmPlaylist":
@@ -764,6 +783,7 @@ export class DbListItem extends ListItem {
             case "StArtist":
             case "Playlist":
             case "Queue":
+            case "SmartPlaylist":
             case "Updater":
             case "LocalState":
             case "ArtistBlacklist":
@@ -857,6 +877,7 @@ export class DbListItem extends ListItem {
             case "StPlaylist":
             case "StArtist":
             case "Song":
+            case "SmartPlaylist":
             case "Updater":
             case "LocalState":
             case "ArtistBlacklist":
@@ -935,6 +956,7 @@ export class DbListItem extends ListItem {
             case "MmQueue":
             case "LocalState":
             case "Song":
+            case "SmartPlaylist":
             case "Updater":
             case "StSong":
             case "StAlbum":
@@ -1008,6 +1030,7 @@ export class DbListItem extends ListItem {
             case "StPlaylist":
             case "StArtist":
             case "Song":
+            case "SmartPlaylist":
             case "Updater":
             case "LocalState":
             case "MbzArtist":
@@ -2000,6 +2023,127 @@ export class DbListItem extends ListItem {
                         throw exhausted(ctx);
                 }
             } break;
+            case "SmartPlaylist": {
+                let sp = this.rc<typeof t.t>();
+                let evaluate = async () => {
+                    return await server.db.evaluate_smart_playlist(utils.clone(sp.t.t));
+                };
+                let options = {
+                    open: {
+                        icon: icons.open_new_tab,
+                        title: "open",
+                        onclick: async () => {
+                            let songs = await evaluate();
+                            let s = Db.new({
+                                query_type: "ids",
+                                type: "Song",
+                                ids: songs.map(s => s.id),
+                            }, 30, ops.option_mapper(), this);
+                            stores.new_tab(s, sp.t.t.title);
+                        },
+                    },
+                    play: {
+                        icon: icons.play,
+                        title: "play",
+                        onclick: async () => {
+                            let songs = await evaluate();
+                            if (songs.length == 0) {
+                                toast("no songs match this smart playlist", "error");
+                                return;
+                            }
+                            let q: types.covau.Queue = {
+                                queue: {
+                                    current_index: null,
+                                    queue: {
+                                        title: `Smart Playlist '${sp.t.t.title}'`,
+                                        songs: songs.map(s => s.id),
+                                    },
+                                },
+                                blacklist: null,
+                                seed: null,
+                                seen: null,
+                            };
+                            let queue = await server.db.txn(async db => {
+                                return await db.insert({ typ: "Queue", t: q });
+                            });
+                            let dbrc = rc.rc.store.rc(queue);
+
+                            await stores.syncops.set.queue(dbrc);
+                            await stores.queue_ops.play_next();
+
+                            toast(`playing ${sp.t.t.title}`);
+                        },
+                    },
+                    add_all_to_queue: {
+                        icon: icons.add,
+                        title: "add all to queue",
+                        onclick: async () => {
+                            let songs = await evaluate();
+                            await stores.queue_ops.add_item(...db.wrapped_items(songs));
+                        },
+                    },
+                    rename: {
+                        icon: icons.floppy_disk,
+                        title: "rename",
+                        onclick: async () => {
+                            let name = await prompter.prompt("Enter smart playlist name");
+                            if (!name) {
+                                return;
+                            }
+
+                            let pl = utils.clone(sp.t);
+                            pl.t.title = name;
+
+                            this.t = await server.db.txn(async db => {
+                                return await db.update(pl);
+                            });
+
+                            toast("smart playlist renamed");
+                        },
+                    },
+                };
+
+                switch (ctx) {
+                    case "Browser":
+                        return {
+                            ...common_options.empty_ops,
+                            bottom: [
+                                ops.options.like,
+                                ops.options.dislike,
+                            ],
+                            menu: [
+                                options.open,
+                                options.play,
+                                options.add_all_to_queue,
+                                common_options.set_as_seed,
+                                options.rename,
+                                common_options.open_details,
+                            ],
+                        };
+                    case "DetailSection":
+                        return {
+                            ...common_options.empty_ops,
+                            menu: [
+                                options.open,
+                                options.play,
+                                options.add_all_to_queue,
+                                common_options.set_as_seed,
+                                options.rename,
+                                ops.options.like,
+                                ops.options.dislike,
+                                ops.options.unlike,
+                                ops.options.undislike,
+                                common_options.refresh_details,
+                            ],
+                        };
+                    case "Queue":
+                    case "Playbar":
+                    case "Prompt":
+                        return common_options.empty_ops;
+                    default:
+                        throw exhausted(ctx);
+                }
+            } break;
             case "Updater": {
                 let u = this.rc<typeof t.t>();
                 switch (u.t.t.source.type) {
@@ -2921,6 +3065,48 @@ export class DbListItem extends ListItem {
                     sections.json,
                 ] as DetailSection[];
             } break;
+            case "SmartPlaylist": {
+                let sp = this.rc<typeof t.t>();
+                return [
+                    {
+                        type: "Info",
+                        info: [
+                            {
+                                heading: "Type",
+                                content: this.t.t.typ,
+                            },
+                            {
+                                heading: "Title",
+                                content: sp.t.t.title,
+                            },
+                            ...sp.t.t.rules.map(r => ({
+                                heading: "Rule",
+                                content: JSON.stringify(r),
+                            })),
+                            ...maybe(sp.t.t.sort, sort => ({
+                                heading: "Sort",
+                                content: `${sort.type == "Metadata" ? sort.content : sort.type}${sp.t.t.desc ? " (desc)" : ""}`,
+                            })),
+                            ...maybe(sp.t.t.limit, limit => ({
+                                heading: "Limit",
+                                content: `${limit}`,
+                            })),
+                        ]
+                    },
+                    sections.options,
+                    {
+                        type: "Searcher",
+                        title: "Songs",
+                        options: [],
+                        height: 5,
+                        searcher: writable(AsyncStaticSearcher(async () => {
+                            let songs = await server.db.evaluate_smart_playlist(utils.clone(sp.t.t));
+                            return db.wrapped_items(songs);
+                        })),
+                    },
+                    sections.json,
+                ] as DetailSection[];
+            } break;
             case "Queue": {
                 let queue = this.rc<typeof t.t>();
                 return [
@@ -2983,7 +3169,7 @@ export class DbListItem extends ListItem {
                         options: [],
                         height: 1,
                         searcher: writable(AsyncStaticSearcher(async () => {
-                            let song = await server.db.get_by_id("Song", seed);
+                            let song = await server.db.get_seed_by_id(seed);
                             if (song == null) {
                                 throw new Error("seed does not exist");
                             }
@@ -3485,10 +3671,10 @@ export class Db extends mixins.Unpaged<MusicListItem> {
                 this.has_next_page = false;
             }
 
-            let matches: DB.DbItem<unknown>[] = await server.db.get_many_by_refid(
+            let matches = (await server.db.get_many_by_refid(
                 this.query.type,
                 ids,
-            );
+            )).filter(e => e != null) as DB.DbItem<unknown>[];
             return keyed(matches) as KeyedMusicListItem[];
         } else if (this.query.query_type === "ids") {
             let ids = this.query.ids.slice(
@@ -3504,15 +3690,15 @@ export class Db extends mixins.Unpaged<MusicListItem> {
             }
 
             if (this.query.type == null) {
-                let matches: DB.DbItem<unknown>[] = await server.db.get_many_untyped_by_id(
+                let matches = (await server.db.get_many_untyped_by_id(
                     ids,
-                );
+                )).filter(e => e != null) as DB.DbItem<unknown>[];
                 return keyed(matches) as KeyedMusicListItem[];
             } else {
-                let matches: DB.DbItem<unknown>[] = await server.db.get_many_by_id(
+                let matches = (await server.db.get_many_by_id(
                     this.query.type,
                     ids,
-                );
+                )).filter(e => e != null) as DB.DbItem<unknown>[];
                 return keyed(matches) as KeyedMusicListItem[];
             }
         } else if (this.query.query_type === "dynamic-refids") {
@@ -292,6 +292,7 @@ export abstract class ListItem implements Keyed {
             case "MmPlaylist":
             case "MmQueue":
             case "Playlist":
+            case "SmartPlaylist":
             case "Updater":
             case "StAlbum":
             case "StPlaylist":
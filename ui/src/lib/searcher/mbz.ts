@@ -1247,7 +1247,6 @@ function UnionTypeWrapper<T extends Keyed, S extends Constructor<{
     } as Constructor<IUnionTypeWrapper> & S;
 }
 
-let server_base = `http://localhost:${import.meta.env.SERVER_PORT}/`;
 export const mbz = {
     async id_fetch<T>(id: string, type: IdFetchTyp) {
         let route = this.id_fetch_route(type);
@@ -1656,15 +1655,15 @@ export const mbz = {
     search_route(type: SearchTyp) {
         switch (type) {
             case "MbzReleaseWithInfo":
-                return server_base + "mbz/search/releases_with_info";
+                return server.utils.base_url + "mbz/search/releases_with_info";
             case "MbzReleaseGroupWithInfo":
-                return server_base + "mbz/search/release_groups_with_info";
+                return server.utils.base_url + "mbz/search/release_groups_with_info";
             case "MbzArtist":
-                return server_base + "mbz/search/artists";
+                return server.utils.base_url + "mbz/search/artists";
             case "MbzRecordingWithInfo":
-                return server_base + "mbz/search/recordings_with_info";
+                return server.utils.base_url + "mbz/search/recordings_with_info";
             case "MbzRadioSong":
-                return server_base + "mbz/radio";
+                return server.utils.base_url + "mbz/radio";
             default:
                 throw exhausted(type);
         }
@@ -1672,15 +1671,15 @@ export const mbz = {
     id_fetch_route(type: IdFetchTyp) {
         switch (type) {
             case "MbzReleaseWithInfo":
-                return server_base + "mbz/search/releases_with_info/id";
+                return server.utils.base_url + "mbz/search/releases_with_info/id";
             case "MbzReleaseGroupWithInfo":
-                return server_base + "mbz/search/release_groups_with_info/id";
+                return server.utils.base_url + "mbz/search/release_groups_with_info/id";
             case "MbzArtist":
-                return server_base + "mbz/search/artists/id";
+                return server.utils.base_url + "mbz/search/artists/id";
             case "MbzRecordingWithInfo":
-                return server_base + "mbz/search/recordings_with_info/id";
+                return server.utils.base_url + "mbz/search/recordings_with_info/id";
             case "MbzArtistWithUrls":
-                return server_base + "mbz/search/artist_with_urls/id";
+                return server.utils.base_url + "mbz/search/artist_with_urls/id";
             default:
                 throw exhausted(type);
         }
@@ -1688,15 +1687,15 @@ export const mbz = {
     linked_route(type: LinkedTyp) {
         switch (type) {
             case "MbzReleaseGroup_MbzArtist":
-                return server_base + "mbz/search/release_groups/linked/artist";
+                return server.utils.base_url + "mbz/search/release_groups/linked/artist";
             case "MbzRelease_MbzArtist":
-                return server_base + "mbz/search/releases/linked/artist";
+                return server.utils.base_url + "mbz/search/releases/linked/artist";
             case "MbzRelease_MbzReleaseGroup":
-                return server_base + "mbz/search/releases/linked/release_group";
+                return server.utils.base_url + "mbz/search/releases/linked/release_group";
             case "MbzRecording_MbzArtsit":
-                return server_base + "mbz/search/recordings/linked/artist";
+                return server.utils.base_url + "mbz/search/recordings/linked/artist";
             case "MbzRecording_MbzRelease":
-                return server_base + "mbz/search/recordings/linked/release";
+                return server.utils.base_url + "mbz/search/recordings/linked/release";
             default:
                 throw exhausted(type);
         }
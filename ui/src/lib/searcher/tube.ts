@@ -1,7 +1,9 @@
 import type { ProxyRequest } from "$types/server.ts";
 import Innertube, { UniversalCache } from "youtubei.js/web";
+import * as server from "$lib/server.ts";
 
 export async function new_innertube_instance() {
+    await server.auth.init();
     let yt = await Innertube.create({
         // TODO: make this cache work. it imporoves performance
         // cache: new UniversalCache(false),
@@ -41,9 +43,9 @@ export async function new_innertube_instance() {
                 body: init?.body?.toString(),
                 method: request.method
             };
-            let res = await fetch(`http://localhost:${import.meta.env.SERVER_PORT}/fetch`, {
+            let res = await fetch(server.utils.base_url + "fetch", {
                 method: 'POST',
-                headers: { 'Content-Type': 'application/json' },
+                headers: { 'Content-Type': 'application/json', ...server.auth.headers() },
                 body: JSON.stringify(req)
             });
             return res;
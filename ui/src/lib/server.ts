@@ -10,15 +10,77 @@ import { get } from 'svelte/store';
 import { err_msg, buffer_to_base64 } from './utils.ts';
 import type { ValType } from './searcher/db.ts';
 
+// other devices (eg: a phone on the lan) load the ui from the server itself
+const server_host = location.port == `${import.meta.env.SERVER_PORT}`
+    ? location.host
+    : `localhost:${import.meta.env.SERVER_PORT}`;
+
+export const auth = {
+    token: localStorage.getItem("covau-token"),
+    logging_in: null as Promise<void> | null,
+
+    // every request to the server needs a token
+    async init() {
+        auth.logging_in ??= auth.login();
+        await auth.logging_in;
+    },
+    async login() {
+        if (auth.token != null && await auth.check(auth.token)) {
+            return;
+        }
+
+        // only works if the ui runs on the same machine as the server
+        let res = await fetch(utils.base_url + "auth/local", { method: "POST" });
+        if (res.ok) {
+            auth.set(await res.json());
+            return;
+        }
+
+        while (true) {
+            let code = window.prompt("pairing code (run 'covau auth pair' where the server runs)");
+            if (code == null) {
+                throw new Error("this device is not paired with the server");
+            }
+            let req: types.server.PairRequest = { code, name: navigator.userAgent };
+            try {
+                let token: string = await utils.api_request(utils.base_url + "auth/claim", req);
+                auth.set(token);
+                return;
+            } catch (e: any) {
+                toast(err_msg(e), "error");
+            }
+        }
+    },
+    async check(token: string) {
+        let res = await fetch(utils.base_url + "auth/check", {
+            method: "POST",
+            headers: { "Authorization": `Bearer ${token}` },
+        });
+        return res.ok;
+    },
+    set(token: string) {
+        auth.token = token;
+        localStorage.setItem("covau-token", token);
+    },
+    headers(): Record<string, string> {
+        return { "Authorization": `Bearer ${auth.token}` };
+    },
+};
+
 export const utils = {
-    base_url: `http://localhost:${import.meta.env.SERVER_PORT}/`,
+    base_url: `${location.protocol == "https:" ? "https" : "http"}://${server_host}/`,
+    ws_url(path: string) {
+        let url = new URL(`${location.protocol == "https:" ? "wss" : "ws"}://${server_host}/${path}`);
+        url.searchParams.append("token", auth.token ?? "");
+        return url.toString();
+    },
     async api_request<P, T>(url: string, json_payload: P) {
         let res = await fetch(
             url,
             {
                 method: "POST",
                 body: JSON.stringify(json_payload),
-                headers: { "Content-Type": "application/json" },
+                headers: { "Content-Type": "application/json", ...auth.headers() },
             }
         );
         // console.log(res);
@@ -42,7 +104,7 @@ export const utils = {
             {
                 method: "POST",
                 body: JSON.stringify(json_payload),
-                headers: { "Content-Type": "application/json" },
+                headers: { "Content-Type": "application/json", ...auth.headers() },
             }
         );
         // console.log(res);
@@ -63,6 +125,8 @@ export const utils = {
                 Object.entries(query).forEach(([k, v]) => {
                     url.searchParams.append(k, v);
                 });
+                // audio and img elements can't set headers
+                url.searchParams.append("token", auth.token ?? "");
                 return url.toString();
             },
             yt(query: types.server.YtStreamQuery) {
@@ -70,6 +134,7 @@ export const utils = {
                 Object.entries(query).forEach(([k, v]) => {
                     url.searchParams.append(k, v.toString());
                 });
+                url.searchParams.append("token", auth.token ?? "");
                 return url.toString();
             },
         },
@@ -79,6 +144,8 @@ export const utils = {
                 Object.entries(query).forEach(([k, v]) => {
                     url.searchParams.append(k, v);
                 });
+                // audio and img elements can't set headers
+                url.searchParams.append("token", auth.token ?? "");
                 return url.toString();
             },
         },
@@ -109,7 +176,7 @@ abstract class Server<Req> {
 
     protected wait: Promise<void>;
     protected constructor(path: string) {
-        this.ws = new WebSocket(`ws://localhost:${import.meta.env.SERVER_PORT}/${path}`);
+        this.ws = new WebSocket(utils.ws_url(path));
 
         this.ws.addEventListener('message', async (e) => {
             let mesg: Message<Req> = JSON.parse(e.data);
@@ -120,6 +187,13 @@ abstract class Server<Req> {
                 console.error(mesg.content.stack_trace);
                 return;
             }
+            if (mesg.type === "Cancel") {
+                let running = this.running.get(mesg.id!);
+                if (running) {
+                    running.cancelled = true;
+                }
+                return;
+            }
             if (mesg.type != "Request") {
                 let err = JSON.stringify(mesg);
                 toast(err, "error");
@@ -130,6 +204,20 @@ abstract class Server<Req> {
             let self = this;
             const resolved: Resolved = { _tag: "RESOLVED" };
             let index = 0;
+            let running = { cancelled: false };
+            let send = (resp_mesg: Message<string>) => {
+                // broadcasts don't expect a response
+                if (mesg.id == null) {
+                    return;
+                }
+                // the server is not waiting for this anymore
+                if (running.cancelled) {
+                    throw new Error(`request ${mesg.id} cancelled`);
+                }
+
+                console.log(resp_mesg);
+                self.ws.send(JSON.stringify(resp_mesg));
+            };
             let resolve: ResolveOps = {
                 none() {
                     return resolved;
@@ -141,8 +229,7 @@ abstract class Server<Req> {
                         content: JSON.stringify(null),
                     };
                     
-                    console.log(resp_mesg);
-                    self.ws.send(JSON.stringify(resp_mesg));
+                    send(resp_mesg);
                     return resolved;
                 },
                 one(resp: Object | null) {
@@ -152,8 +239,7 @@ abstract class Server<Req> {
                         content: JSON.stringify(resp),
                     };
 
-                    console.log(resp_mesg);
-                    self.ws.send(JSON.stringify(resp_mesg));
+                    send(resp_mesg);
                     return resolved;
                 },
                 many(resp: Object | null) {
@@ -164,8 +250,7 @@ abstract class Server<Req> {
                     };
                     index += 1;
 
-                    console.log(resp_mesg);
-                    self.ws.send(JSON.stringify(resp_mesg));
+                    send(resp_mesg);
                 },
                 many_done(resp: Object | null) {
                     let resp_mesg: Message<string> = {
@@ -175,15 +260,20 @@ abstract class Server<Req> {
                     };
                     index += 1;
 
-                    console.log(resp_mesg);
-                    self.ws.send(JSON.stringify(resp_mesg));
+                    send(resp_mesg);
                     return resolved;
                 },
             };
 
+            if (mesg.id != null) {
+                this.running.set(mesg.id, running);
+            }
             try {
                 let _ = await this.handle_req(mesg.content, resolve);
             } catch (e: any) {
+                if (running.cancelled) {
+                    return;
+                }
                 console.error(e);
                 let err: string;
                 let trace: string;
@@ -200,11 +290,16 @@ abstract class Server<Req> {
                     content: {
                         message: err,
                         stack_trace: trace,
+                        kind: "Internal",
                     },
                 };
 
                 console.log(resp_mesg);
                 this.ws.send(JSON.stringify(resp_mesg));
+            } finally {
+                if (mesg.id != null) {
+                    this.running.delete(mesg.id);
+                }
             }
         });
 
@@ -213,15 +308,33 @@ abstract class Server<Req> {
             resolve = r as () => {};
         });
         this.ws.addEventListener('open', async (_e) => {
+            this.register();
             resolve();
         });
     }
 
+    // the server only sends requests to registered clients. call again when capable() changes
+    register() {
+        let msg: Message<string> = {
+            id: null,
+            type: "Register",
+            content: { name: navigator.userAgent, capable: this.capable() },
+        };
+        this.ws.send(JSON.stringify(msg));
+    }
+
+    // if this client can handle requests right now
+    protected capable() {
+        return true;
+    }
+
+    // requests that are still being handled. the server can cancel them
+    running: Map<number, { cancelled: boolean }> = new Map();
+
     // don't return anything if no response
     // return null for () unit type
     // else some { object: () }
-    // NOTE: server will wait forever if it expects a response and one is not sent.
-    //       maybe just make a "null" response as a precaution??
+    // NOTE: server times out the request if it expects a response and one is not sent.
     abstract handle_req(req: Req, resolve: ResolveOps): Promise<Resolved>;
 }
 
@@ -236,6 +349,11 @@ class YtiServer extends Server<yt.YtiRequest> {
         return self;
     }
 
+    // youtube needs the network
+    protected override capable() {
+        return navigator.onLine;
+    }
+
     tubes: Map<string, st.SongTube> = new Map();
     async handle_req(req: yt.YtiRequest, resolve: ResolveOps): Promise<Resolved> {
         switch (req.type) {
@@ -396,21 +514,27 @@ class Client<Req> {
     protected wait: Promise<void>;
     protected constructor(path: string) {
         this.new_id_path = utils.base_url + path + "/new_id";
-        this.ws = new WebSocket(`ws://localhost:${import.meta.env.SERVER_PORT}/${path}`);
+        this.ws = new WebSocket(utils.ws_url(path));
 
         this.ws.addEventListener('message', async (e) => {
             let mesg: Message<string> = JSON.parse(e.data);
 
             if (mesg.id == null) {
                 toast("backend sent some data without id", "error");
-                console.error(mesg.content);
+                console.error(mesg);
+                return;
+            }
+
+            let stream = this.streams.get(mesg.id) ?? null;
+            if (stream != null) {
+                stream(mesg);
                 return;
             }
 
             let resolver = this.resolves.get(mesg.id) ?? null;
             if (resolver == null) {
                 toast("backend sent some data with unknown id", "error");
-                console.error(mesg.content);
+                console.error(mesg);
                 return;
             }
             this.resolves.delete(mesg.id);
@@ -428,6 +552,8 @@ class Client<Req> {
     }
 
     resolves: Map<number, Resolver<MessageResult<string>>> = new Map();
+    // requests that get more than 1 response
+    streams: Map<number, Resolver<MessageResult<string>>> = new Map();
     // NOTE: allow_many does not handle race conditions
     async execute<T>(req: Req, id: number | null = null, allow_many: boolean = false): Promise<T> {
         if (id == null) {
@@ -455,7 +581,7 @@ class Client<Req> {
         } else if (resp.type == "OkMany" && allow_many) {
             return JSON.parse(resp.content.data);
         } else if (resp.type != "OkOne") {
-            let data = JSON.stringify(resp.content);
+            let data = JSON.stringify(resp);
             let msg = `expected 'OkOne' found '${resp.type}': ${data}`;
             console.error(msg);
             throw new Error(msg);
@@ -480,6 +606,27 @@ class DbClient extends Client<types.server.DbRequest> {
         let self = new DbClient();
         await self.wait;
         self.def_id = await utils.api_request(self.new_id_path, null);
+
+        // changes from other tabs / devices / the backend
+        await self.subscribe({ typs: ["LocalState", "Queue", "Playlist"], ids: [] }, async (e) => {
+            if (e.type != "Updated" || !self.listeners.has(e.content.id)) {
+                return;
+            }
+            if (self.seen_counters.get(e.content.id) == e.content.metadata.update_counter) {
+                return;
+            }
+            let item: types.db.DbItem<unknown> | null = await self.execute({
+                type: "GetById",
+                content: {
+                    typ: e.content.typ,
+                    id: e.content.id,
+                },
+            });
+            if (item != null) {
+                await self.call_listeners(item);
+            }
+        });
+
         return self;
     }
 
@@ -495,6 +642,39 @@ class DbClient extends Client<types.server.DbRequest> {
         return await super.execute(req, id);
     }
 
+    // callback gets a 'Resync' first. returns a function to unsubscribe
+    async subscribe(sub: types.db.DbSubscription, callback: (e: types.db.DbEvent) => Promise<void>) {
+        let id = await this.new_id();
+        this.streams.set(id, async (resp) => {
+            if (resp.type == "Err") {
+                this.streams.delete(id);
+                toast(resp.content.message, "error");
+                console.error(resp.content.stack_trace);
+            } else if (resp.type == "OkMany") {
+                if (resp.content.done) {
+                    this.streams.delete(id);
+                    return;
+                }
+                let event: types.db.DbEvent = JSON.parse(resp.content.data);
+                await callback(event).catch(e => {
+                    toast(err_msg(e), "error");
+                    console.error(e);
+                });
+            }
+        });
+
+        let msg: Message<string> = {
+            id: id,
+            type: "Request",
+            content: JSON.stringify({ type: "Subscribe", content: sub } as types.server.DbRequest),
+        };
+        this.ws.send(JSON.stringify(msg));
+
+        return async () => {
+            await this.execute({ type: "Unsubscribe", content: id });
+        };
+    }
+
     listeners: Map<number, { enabled: boolean, callback: DbUpdateCallback<unknown> }[]> = new Map();
     set_update_listener<T>(id: number, callback: DbUpdateCallback<T>) {
         let handlers = this.listeners.get(id);
@@ -524,7 +704,10 @@ class DbClient extends Client<types.server.DbRequest> {
 
         return disable;
     }
+    // update_counter of the last version of items that the listeners got
+    seen_counters: Map<number, number> = new Map();
     async call_listeners(item: types.db.DbItem<unknown>) {
+        this.seen_counters.set(item.id, item.metadata.update_counter);
         let listeners = this.listeners.get(item.id) ?? [];
         for (let listener of listeners) {
             if (listener.enabled) {
@@ -569,6 +752,53 @@ class DbClient extends Client<types.server.DbRequest> {
                 return dbitem;
             },
 
+            // 1 result per item. failed items are skipped, the rest still go through
+            async insert_many_or_get<T>(typ: types.db.Typ, items: T[]) {
+                let req: types.server.DbRequest = {
+                    type: "InsertManyOrGet",
+                    content: {
+                        transaction_id: this.id,
+                        typ,
+                        items: items.map(t => JSON.stringify(t)),
+                    },
+                };
+                let res: types.server.BatchResult<types.server.InsertResponse<types.db.DbItem<T>>>[] = await self.execute(req);
+                return res;
+            },
+
+            async update_many<T>(typ: types.db.Typ, items: types.db.DbItem<T>[]) {
+                let req: types.server.DbRequest = {
+                    type: "UpdateMany",
+                    content: {
+                        transaction_id: this.id,
+                        typ,
+                        items: items.map(item => ({ ...item, t: JSON.stringify(item.t) })),
+                    },
+                };
+                let res: types.server.BatchResult<types.db.DbItem<T>>[] = await self.execute(req);
+
+                for (let r of res) {
+                    if (r.type == "Ok") {
+                        await self.call_listeners(r.content);
+                    }
+                }
+
+                return res;
+            },
+
+            async delete_many<T>(typ: types.db.Typ, items: types.db.DbItem<T>[]) {
+                let req: types.server.DbRequest = {
+                    type: "DeleteMany",
+                    content: {
+                        transaction_id: this.id,
+                        typ,
+                        items: items.map(item => ({ ...item, t: JSON.stringify(item.t) })),
+                    },
+                };
+                let res: types.server.BatchResult<null>[] = await self.execute(req);
+                return res;
+            },
+
             async update<T>(item: types.db.DbItem<T>) {
                 let req: types.server.DbRequest = {
                     type: "Update",
@@ -615,6 +845,64 @@ class DbClient extends Client<types.server.DbRequest> {
                 };
                 let _: null = await self.execute(req);
             },
+
+            async restore<T extends types.db.Typ>(typ: T, id: number) {
+                let req: types.server.DbRequest = {
+                    type: "Restore",
+                    content: {
+                        transaction_id: this.id,
+                        typ,
+                        id,
+                    },
+                };
+                let dbitem: types.db.DbItem<ValType<typeof typ>> = await self.execute(req);
+                return dbitem;
+            },
+
+            async revert<T extends types.db.Typ>(typ: T, id: number, update_counter: number) {
+                let req: types.server.DbRequest = {
+                    type: "Revert",
+                    content: {
+                        transaction_id: this.id,
+                        typ,
+                        id,
+                        update_counter,
+                    },
+                };
+                let dbitem: types.db.DbItem<ValType<typeof typ>> = await self.execute(req);
+
+                await self.call_listeners(dbitem);
+
+                return dbitem;
+            },
+
+            async purge(id: number) {
+                let req: types.server.DbRequest = {
+                    type: "Purge",
+                    content: {
+                        transaction_id: this.id,
+                        id,
+                    },
+                };
+                let _: null = await self.execute(req);
+            },
+
+            // duplicates are moved to the trash
+            async merge_songs(survivor: number, duplicates: number[]) {
+                let req: types.server.DbRequest = {
+                    type: "MergeSongs",
+                    content: {
+                        transaction_id: this.id,
+                        survivor,
+                        duplicates,
+                    },
+                };
+                let dbitem: types.db.DbItem<types.covau.Song> = await self.execute(req);
+
+                await self.call_listeners(dbitem);
+
+                return dbitem;
+            },
         }
     }
 }
@@ -649,6 +937,87 @@ export const db = {
         return res;
     },
 
+    async search_all(query: types.db.UniversalQuery) {
+        let res: types.db.UniversalMatches<unknown> = await dbclient.execute({ type: "SearchAll", content: query });
+        return res;
+    },
+
+    async query<T extends types.db.Typ>(typ: T, query: types.db.DbQuery) {
+        let res: types.db.QueryMatches<ValType<typeof typ>> = await dbclient.execute({
+            type: "Query",
+            content: {
+                typ,
+                query,
+            },
+        });
+        return res;
+    },
+
+    async get_revisions<T extends types.db.Typ>(typ: T, id: number) {
+        let res: types.db.Revision<ValType<typeof typ>>[] = await dbclient.execute({
+            type: "GetRevisions",
+            content: {
+                typ,
+                id,
+            },
+        });
+        return res;
+    },
+
+    // to: null diffs against the current version
+    async diff_revisions(id: number, from: number, to: number | null) {
+        let res: types.db.JsonChange[] = await dbclient.execute({
+            type: "DiffRevisions",
+            content: {
+                id,
+                from,
+                to,
+            },
+        });
+        return res;
+    },
+
+    async get_duplicates() {
+        let res: types.db.DuplicateGroup[] = await dbclient.execute({ type: "GetDuplicates" });
+        return res;
+    },
+
+    async log_play(event: types.covau.PlayEvent) {
+        let res: number = await dbclient.execute({ type: "LogPlay", content: event });
+        return res;
+    },
+
+    async get_plays(query: types.covau.PlayQuery) {
+        let res: types.covau.PlayMatches = await dbclient.execute({ type: "GetPlays", content: query });
+        return res;
+    },
+
+    async get_listening_stats(after: number, before: number) {
+        let res: types.covau.ListeningStats = await dbclient.execute({
+            type: "GetListeningStats",
+            content: {
+                after: after.toString(),
+                before: before.toString(),
+            },
+        });
+        return res;
+    },
+
+    async evaluate_smart_playlist(sp: types.covau.SmartPlaylist) {
+        let res: types.db.DbItem<types.covau.Song>[] = await dbclient.execute({ type: "EvaluateSmartPlaylist", content: sp });
+        return res;
+    },
+
+    async get_trash<T extends types.db.Typ>(typ: T) {
+        let res: types.db.TrashedItem<ValType<typeof typ>>[] = await dbclient.execute({
+            type: "GetTrash",
+            content: {
+                typ,
+            },
+        });
+        return res;
+    },
+
     async get_by_refid<T extends types.db.Typ>(typ: T, refid: string) {
         let res: types.db.DbItem<ValType<typeof typ>> | null = await dbclient.execute({
             type: "GetByRefid",
@@ -661,13 +1030,14 @@ export const db = {
     },
 
     async get_many_by_refid<T extends types.db.Typ>(typ: T, refids: string[]) {
-        let res: types.db.DbItem<ValType<typeof typ>>[] = await dbclient.execute({
+        let res: (types.db.DbItem<ValType<typeof typ>> | null)[] = await dbclient.execute({
             type: "GetManyByRefid",
             content: {
                 typ,
                 refids,
             },
         });
+        // null for items that are not in the db. same positions as the request
         return res;
     },
 
@@ -683,16 +1053,26 @@ export const db = {
     },
 
     async get_many_by_id<T extends types.db.Typ>(typ: T, ids: number[]) {
-        let res: types.db.DbItem<ValType<typeof typ>>[] = await dbclient.execute({
+        let res: (types.db.DbItem<ValType<typeof typ>> | null)[] = await dbclient.execute({
             type: "GetManyById",
             content: {
                 typ,
                 ids,
             },
         });
+        // null for items that are not in the db. same positions as the request
         return res;
     },
 
+    // autoplay seeds are Songs or SmartPlaylists
+    async get_seed_by_id(id: number) {
+        let song = await db.get_by_id("Song", id);
+        if (song != null) {
+            return song as types.db.DbItem<types.covau.Song | types.covau.SmartPlaylist>;
+        }
+        return await db.get_by_id("SmartPlaylist", id);
+    },
+
     async get_untyped_by_id(id: number) {
         let res: types.db.DbItem<unknown> | null = await dbclient.execute({
             type: "GetUntypedById",
@@ -704,12 +1084,13 @@ export const db = {
     },
 
     async get_many_untyped_by_id(ids: number[]) {
-        let res: types.db.DbItem<unknown>[] = await dbclient.execute({
+        let res: (types.db.DbItem<unknown> | null)[] = await dbclient.execute({
             type: "GetManyUntypedById",
             content: {
                 ids,
             },
         });
+        // null for items that are not in the db. same positions as the request
         return res;
     },
 };
@@ -737,9 +1118,11 @@ const app_ops = {
         }
     },
     async online(e: Event) {
+        ytiserver?.register();
         await app_ops.send("Online");
     },
     async offline(e: Event) {
+        ytiserver?.register();
         await app_ops.send("Offline");
     },
 };
@@ -764,6 +1147,7 @@ export let feserver: FeServer | null = null;
 // @ts-ignore
 export let dbclient: DbClient = null;
 export const serve = async () => {
+    await auth.init();
     ytiserver = await YtiServer.new();
     feserver = await FeServer.new();
     dbclient = await DbClient.new();
@@ -791,6 +1175,9 @@ export const serve = async () => {
     // console.log(channel)
 };
 export const unserve = async () => {
+    // a stale connection would still get requests
+    ytiserver?.ws.close();
+    feserver?.ws.close();
     ytiserver = null;
     feserver = null;
     // @ts-ignore
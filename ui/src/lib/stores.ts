@@ -383,8 +383,25 @@ type Syncer = {
     queue: rc.DbRc<types.covau.Queue>,
     blacklist: rc.DbRc<types.covau.ArtistBlacklist> | null,
     seen: rc.DbRc<types.covau.SongBlacklist> | null,
-    seed: rc.DbRc<types.covau.Song> | null,
+    seed: rc.DbRc<types.covau.Song | types.covau.SmartPlaylist> | null,
 };
+// trashed songs come back as null. they are left out, and current_index moves along
+// with the playing song (null if that one is gone)
+const queue_songs = async (q: types.covau.Queue) => {
+    let items = await imports.server.db.get_many_by_id("Song", utils.clone(q.queue.queue.songs));
+    let index = q.queue.current_index;
+    if (index != null) {
+        index = items[index] == null ? null : index - items.slice(0, index).filter(e => e == null).length;
+    }
+    let songs = items.filter(e => e != null) as types.db.DbItem<types.covau.Song>[];
+    return { songs, index };
+};
+
+// the song playing when the page goes away is logged too
+const log_play_on_unload = () => {
+    get(queue).end_play();
+};
+
 export let syncer: Writable<Syncer> = writable();
 export const syncops = {
     // load everything from db into _
@@ -407,9 +424,9 @@ export const syncops = {
         if (sync.state.t.t.queue != null) {
             let dbq = (await imports.server.db.get_by_id("Queue", sync.state.t.t.queue))!;
             sync.queue = rc.rc.store.rc(dbq);
-            let items = await imports.server.db.get_many_by_id("Song", dbq.t.queue.queue.songs);
-            q.items = db.db.wrapped_items(items);
-            q.playing_index = dbq.t.queue.current_index;
+            let { songs, index } = await queue_songs(dbq.t);
+            q.items = db.db.wrapped_items(songs);
+            q.playing_index = index;
             if (q.playing_index != null) {
                 q.state = "Playing";
                 playing_item.set(q.items[q.playing_index]);
@@ -428,7 +445,7 @@ export const syncops = {
                 q.bl_ids = new Set(bl.t.songs.map(id => id.content));
             }
             if (sync.queue.t.t.seed != null) {
-                let s = (await imports.server.db.get_by_id("Song", sync.queue.t.t.seed))!;
+                let s = (await imports.server.db.get_seed_by_id(sync.queue.t.t.seed))!;
                 let w = db.db.wrapped(s);
                 sync.seed = rc.rc.store.rc(s);
                 q.set_seed(w);
@@ -437,6 +454,7 @@ export const syncops = {
 
         syncer.set(sync);
         queue.set(q);
+        window.addEventListener("pagehide", log_play_on_unload);
 
         if (sync.state.t.t.queue == null) {
             await syncops.new.queue("Queue");
@@ -467,9 +485,9 @@ export const syncops = {
                         }
 
                         // sync.queue = rc.rc.store.rc(item);
-                        let items = await imports.server.db.get_many_by_id("Song", item.t.queue.queue.songs);
-                        q.items = db.db.wrapped_items(items);
-                        q.playing_index = item.t.queue.current_index;
+                        let { songs, index } = await queue_songs(item.t);
+                        q.items = db.db.wrapped_items(songs);
+                        q.playing_index = index;
                         if (q.playing_index != null) {
                             let item = q.items[q.playing_index];
                             await q.sync_play(item);
@@ -612,9 +630,9 @@ export const syncops = {
             await syncops.listeners.reset.queue();
 
             if (sync.state.t.t.queue != null) {
-                let items = await imports.server.db.get_many_by_id("Song", utils.clone(sync.queue.t.t.queue.queue.songs));
-                q.items = db.db.wrapped_items(items);
-                q.playing_index = sync.queue.t.t.queue.current_index;
+                let { songs, index } = await queue_songs(sync.queue.t.t);
+                q.items = db.db.wrapped_items(songs);
+                q.playing_index = index;
                 if (q.playing_index != null) {
                     let item = q.items[q.playing_index];
                     if (item.get_key() != get(playing_item).get_key()) {
@@ -637,7 +655,7 @@ export const syncops = {
                     q.bl_ids = new Set(bl.t.songs.map(id => id.content));
                 }
                 if (sync.queue.t.t.seed != null) {
-                    let s = (await imports.server.db.get_by_id("Song", sync.queue.t.t.seed))!;
+                    let s = (await imports.server.db.get_seed_by_id(sync.queue.t.t.seed))!;
                     let w = db.db.wrapped(s);
                     sync.seed = rc.rc.store.rc(s);
                     q.set_seed(w);
@@ -869,6 +887,7 @@ selected_menubar_option.subscribe(async (option) => {
                 case "Song":
                 case "Playlist":
                 case "Queue":
+                case "SmartPlaylist":
                 case "ArtistBlacklist":
                 case "SongBlacklist":
                 case "MbzRecording":
@@ -1,5 +1,6 @@
 import type { ReleaseGroupWithInfo, ReleaseWithInfo, Recording } from '$types/mbz.ts';
 import type { VideoId, AlbumId } from '$types/yt.ts';
+import type { MetadataField } from '$types/db.ts';
 
 export type LocalState = { queue: number | null };
 export type Size = { width: number; height: number };
@@ -18,3 +19,16 @@ export type UpdateItem<T> = { done: boolean; points: number; added_ts: string; i
 export type ListenQueue<T> = { queue: T; current_index: number | null };
 export type UpdateSource = { type: "Mbz"; content: { artist_id: string; release_groups: UpdateItem<ReleaseGroupWithInfo>[]; releases: UpdateItem<ReleaseWithInfo>[]; recordings: ListenQueue<UpdateItem<Recording>[]> } } | { type: "MusimanagerSearch"; content: { search_words: string[]; artist_keys: string[]; non_search_words: string[]; known_albums: UpdateItem<AlbumId>[]; songs: ListenQueue<UpdateItem<VideoId>[]> } } | { type: "SongTubeSearch"; content: { search_words: string[]; artist_keys: string[]; known_albums: UpdateItem<AlbumId>[]; songs: ListenQueue<UpdateItem<VideoId>[]> } };
 export type Updater = { title: string; source: UpdateSource; last_update_ts: string; enabled: boolean };
+export type SmartPlaylist = { title: string; rules: SmartRule[]; sort: SmartSort | null; desc: boolean; limit: number | null };
+export type SmartRule = { type: "ArtistIn"; content: Artist[] } | { type: "Liked" } | { type: "NotPlayedFor"; content: { days: number } } | { type: "AddedAfter"; content: { ts: string } } | { type: "HasLocalFile" } | { type: "InPlaylist"; content: number[] } | { type: "Text"; content: string };
+export type SmartSort = { type: "Metadata"; content: MetadataField } | { type: "Plays" } | { type: "LastPlayed" } | { type: "Title" } | { type: "Random" };
+export type PlayEvent = { song: number; queue: number | null; started_ts: string; listened_secs: number; skipped: boolean; source: PlaySource; autoplay: boolean };
+export type Play = { id: number; event: PlayEvent };
+export type PlayQuery = { after: string; before: string; song: number | null; page_size: number; cursor: string | null };
+export type PlayMatches = { items: Play[]; continuation: PlayQuery | null };
+export type ListeningStats = { after: string; before: string; plays: number; listened_secs: number; skips: number; top_songs: SongStat[]; top_artists: ArtistStat[]; top_queues: QueueStat[]; per_day: TimeBucket[]; per_week: TimeBucket[]; autoplay: AutoplayStats };
+export type SongStat = { id: number; title: string | null; artists: string[]; plays: number; listened_secs: number; skips: number };
+export type ArtistStat = { name: string; ids: InfoSource[]; plays: number; listened_secs: number; skips: number; skip_rate: number };
+export type QueueStat = { id: number; title: string | null; plays: number; listened_secs: number };
+export type TimeBucket = { start_ts: string; plays: number; listened_secs: number };
+export type AutoplayStats = { plays: number; new: number; repeats: number; new_ratio: number };
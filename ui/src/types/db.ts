@@ -1,6 +1,22 @@
 export type SearchMatches<T> = { items: DbItem<T>[]; continuation: SearchContinuation | null };
 export type DbMetadata = { done: boolean; likes: number; dislikes: number; interactions: number; update_counter: number; added_ts: string; updated_ts: string };
 export type DbItem<T> = { metadata: DbMetadata; id: number; typ: Typ; t: T };
-export type Typ = "MmSong" | "MmAlbum" | "MmArtist" | "MmPlaylist" | "MmQueue" | "LocalState" | "Song" | "Playlist" | "Queue" | "ArtistBlacklist" | "SongBlacklist" | "Updater" | "StSong" | "StAlbum" | "StPlaylist" | "StArtist" | "MbzRecording" | "MbzArtist";
+export type Typ = "MmSong" | "MmAlbum" | "MmArtist" | "MmPlaylist" | "MmQueue" | "LocalState" | "Song" | "Playlist" | "Queue" | "ArtistBlacklist" | "SongBlacklist" | "Updater" | "SmartPlaylist" | "StSong" | "StAlbum" | "StPlaylist" | "StArtist" | "MbzRecording" | "MbzArtist";
 export type SearchQuery = { type: "Query"; content: { page_size: number; query: string } } | { type: "Continuation"; content: SearchContinuation };
 export type SearchContinuation = { typ: Typ; page_size: number; query: string; cont: string };
+export type UniversalMatches<T> = { groups: TypMatches<T>[]; continuation: UniversalContinuation | null };
+export type TypMatches<T> = { typ: Typ; items: ScoredItem<T>[] };
+export type ScoredItem<T> = { score: number; item: DbItem<T> };
+export type UniversalContinuation = { typs: Typ[]; page_size: number; query: string; cont: string };
+export type UniversalQuery = { type: "Query"; content: { typs: Typ[] | null; page_size: number; query: string } } | { type: "Continuation"; content: UniversalContinuation };
+export type MetadataField = "Likes" | "Dislikes" | "Interactions" | "AddedTs" | "UpdatedTs";
+export type FilterOp = "Eq" | "Gt" | "Gte" | "Lt" | "Lte";
+export type MetadataFilter = { type: "Done"; content: boolean } | { type: "Cmp"; content: { field: MetadataField; op: FilterOp; value: string } };
+export type DbQuery = { filters: MetadataFilter[]; text: string | null; sort_by: MetadataField | null; desc: boolean; page_size: number; cursor: string | null };
+export type QueryMatches<T> = { items: DbItem<T>[]; continuation: DbQuery | null };
+export type TrashedItem<T> = { item: DbItem<T>; trashed_ts: string };
+export type Revision<T> = { item: DbItem<T>; replaced_ts: string };
+export type JsonChange = { path: string; old: string | null; new: string | null };
+export type DuplicateGroup = { survivor: number; duplicates: number[] };
+export type DbEvent = { type: "Inserted"; content: { id: number; typ: Typ } } | { type: "Updated"; content: { id: number; typ: Typ; metadata: DbMetadata } } | { type: "Deleted"; content: { id: number; typ: Typ } } | { type: "Resync" };
+export type DbSubscription = { typs: Typ[]; ids: number[] };
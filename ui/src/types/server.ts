@@ -1,14 +1,27 @@
-import type { DbMetadata, DbItem, Typ, SearchQuery } from '$types/db.ts';
+import type { DbMetadata, DbItem, Typ, SearchQuery, UniversalQuery, DbQuery, DbSubscription } from '$types/db.ts';
+import type { PlayEvent, PlayQuery } from '$types/covau.ts';
 
-export type Message<T> = ({ type: "Request"; content: T } | { type: "OkOne"; content: T } | { type: "OkMany"; content: { data: T; done: boolean; index: number } } | { type: "Err"; content: ErrorMessage }) & { id: number | null };
-export type MessageResult<T> = { type: "Request"; content: T } | { type: "OkOne"; content: T } | { type: "OkMany"; content: { data: T; done: boolean; index: number } } | { type: "Err"; content: ErrorMessage };
+export type Message<T> = ({ type: "Request"; content: T } | { type: "OkOne"; content: T } | { type: "OkMany"; content: { data: T; done: boolean; index: number } } | { type: "Err"; content: ErrorMessage } | { type: "Cancel" } | { type: "Register"; content: ClientInfo }) & { id: number | null };
+export type MessageResult<T> = { type: "Request"; content: T } | { type: "OkOne"; content: T } | { type: "OkMany"; content: { data: T; done: boolean; index: number } } | { type: "Err"; content: ErrorMessage } | { type: "Cancel" } | { type: "Register"; content: ClientInfo };
 export type FeRequest = { type: "Like" } | { type: "Dislike" } | { type: "Next" } | { type: "Prev" } | { type: "Pause" } | { type: "Play" } | { type: "Repeat" } | { type: "ToggleMute" } | { type: "TogglePlay" } | { type: "BlacklistArtists" } | { type: "RemoveAndNext" } | { type: "SeekFwd" } | { type: "SeekBkwd" } | { type: "Notify"; content: string } | { type: "NotifyError"; content: string };
 export type AppMessage = "Online" | "Offline" | "Load" | "Unload" | "Visible" | "NotVisible";
 export type PlayerCommand = { type: "Pause" } | { type: "Unpause" } | { type: "Play"; content: string } | { type: "SeekBy"; content: number } | { type: "SeekToPerc"; content: number } | { type: "Mute" } | { type: "Unmute" } | { type: "IsMuted" } | { type: "GetVolume" } | { type: "SetVolume"; content: number } | { type: "GetDuration" };
 export type PlayerMessage = { type: "Paused" } | { type: "Unpaused" } | { type: "Finished" } | { type: "Playing"; content: string } | { type: "ProgressPerc"; content: number } | { type: "Volume"; content: number } | { type: "Duration"; content: number } | { type: "Mute"; content: boolean } | { type: "Error"; content: string };
 export type ProxyRequest = { url: string; body?: string | null; headers: string; method: string };
 export type InsertResponse<T> = { type: "New"; content: T } | { type: "Old"; content: T };
+export type BatchResult<T> = { type: "Ok"; content: T } | { type: "Err"; content: ErrorMessage };
 export type YtStreamQuery = { size: number; id: string };
 export type ImageQuery = { src: string };
-export type DbRequest = { type: "NewId" } | { type: "Begin" } | { type: "Commit"; content: number } | { type: "Rollback"; content: number } | { type: "Insert"; content: { transaction_id: number; typ: Typ; item: string } } | { type: "InsertOrGet"; content: { transaction_id: number; typ: Typ; item: string } } | { type: "Update"; content: { transaction_id: number; item: DbItem<string> } } | { type: "UpdateMetadata"; content: { transaction_id: number; id: number; typ: Typ; metadata: DbMetadata } } | { type: "Delete"; content: { transaction_id: number; item: DbItem<string> } } | { type: "Search"; content: { typ: Typ; query: SearchQuery } } | { type: "GetByRefid"; content: { typ: Typ; refid: string } } | { type: "GetManyByRefid"; content: { typ: Typ; refids: string[] } } | { type: "GetById"; content: { typ: Typ; id: number } } | { type: "GetManyById"; content: { typ: Typ; ids: number[] } } | { type: "GetUntypedById"; content: { id: number } } | { type: "GetManyUntypedById"; content: { ids: number[] } };
-export type ErrorMessage = { message: string; stack_trace: string };
+export type DbRequest = { type: "NewId" } | { type: "Begin" } | { type: "Commit"; content: number } | { type: "Rollback"; content: number } | { type: "Insert"; content: { transaction_id: number; typ: Typ; item: string } } | { type: "InsertOrGet"; content: { transaction_id: number; typ: Typ; item: string } } | { type: "Update"; content: { transaction_id: number; item: DbItem<string> } } | { type: "InsertManyOrGet"; content: { transaction_id: number; typ: Typ; items: string[] } } | { type: "UpdateMany"; content: { transaction_id: number; typ: Typ; items: DbItem<string>[] } } | { type: "DeleteMany"; content: { transaction_id: number; typ: Typ; items: DbItem<string>[] } } | { type: "UpdateMetadata"; content: { transaction_id: number; id: number; typ: Typ; metadata: DbMetadata } } | { type: "Delete"; content: { transaction_id: number; item: DbItem<string> } } | { type: "GetTrash"; content: { typ: Typ } } | { type: "Restore"; content: { transaction_id: number; typ: Typ; id: number } } | { type: "Purge"; content: { transaction_id: number; id: number } } | { type: "GetRevisions"; content: { typ: Typ; id: number } } | { type: "DiffRevisions"; content: { id: number; from: number; to: number | null } } | { type: "Revert"; content: { transaction_id: number; typ: Typ; id: number; update_counter: number } } | { type: "GetDuplicates" } | { type: "MergeSongs"; content: { transaction_id: number; survivor: number; duplicates: number[] } } | { type: "LogPlay"; content: PlayEvent } | { type: "GetPlays"; content: PlayQuery } | { type: "GetListeningStats"; content: { after: string; before: string } } | { type: "EvaluateSmartPlaylist"; content: SmartPlaylist } | { type: "Search"; content: { typ: Typ; query: SearchQuery } } | { type: "SearchAll"; content: UniversalQuery } | { type: "Query"; content: { typ: Typ; query: DbQuery } } | { type: "GetByRefid"; content: { typ: Typ; refid: string } } | { type: "GetManyByRefid"; content: { typ: Typ; refids: string[] } } | { type: "GetById"; content: { typ: Typ; id: number } } | { type: "GetManyById"; content: { typ: Typ; ids: number[] } } | { type: "GetUntypedById"; content: { id: number } } | { type: "GetManyUntypedById"; content: { ids: number[] } } | { type: "GetLinkedFrom"; content: { from: Typ; to: Typ; refids: string[] } } | { type: "GetLinkedTo"; content: { from: Typ; to: Typ; refids: string[] } } | { type: "Subscribe"; content: DbSubscription } | { type: "Unsubscribe"; content: number };
+/**
+ * what went wrong, so that callers don't have to look at the message
+ */
+export type ErrorKind = "NotFound" | "Conflict" | "Invalid" | "NotAllowed" | "Busy" | "Internal";
+export type ErrorMessage = { message: string; stack_trace: string; kind: ErrorKind };
+/**
+ * what a frontend tells the server about itself when it connects
+ */
+export type ClientInfo = { name: string; capable: boolean };
+export type PairedDevice = { name: string; paired_ts: string };
+export type PairingCode = { code: string; expires_ts: string };
+export type PairRequest = { code: string; name: string };